@@ -5,7 +5,9 @@ use x86_64::{
     VirtAddr,
 };
 
-use self::fixed_size_block::FixedSizeBlockAllocator;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cmdline::{self, CommandLine};
 
 pub mod bump;
 pub mod fixed_size_block;
@@ -26,26 +28,85 @@ impl<A> Locked<A> {
     pub fn lock(&self) -> spin::MutexGuard<A> {
         self.inner.lock()
     }
+
+    /// Locks the allocator, unless it's already locked
+    pub fn try_lock(&self) -> Option<spin::MutexGuard<A>> {
+        self.inner.try_lock()
+    }
 }
 
+// The type of the global allocator, the fixed-size block allocator unless another one is selected
+// with the bump-allocator or linked-list-allocator feature
+#[cfg(not(any(feature = "bump-allocator", feature = "linked-list-allocator")))]
+pub type GlobalHeap = fixed_size_block::FixedSizeBlockAllocator;
+#[cfg(feature = "bump-allocator")]
+pub type GlobalHeap = bump::BumpAllocator;
+#[cfg(feature = "linked-list-allocator")]
+pub type GlobalHeap = linked_list::LinkedListAllocator;
+
+#[cfg(all(feature = "bump-allocator", feature = "linked-list-allocator"))]
+compile_error!("The bump-allocator and linked-list-allocator features can't both be enabled");
+
 #[global_allocator]
-pub static mut ALLOCATOR: Locked<FixedSizeBlockAllocator> =
-    Locked::new(FixedSizeBlockAllocator::new());
+pub static mut ALLOCATOR: Locked<GlobalHeap> = Locked::new(GlobalHeap::new());
 
-// The start address and size of the heap, can be changed if needed
+// The start address and default size of the heap, the size can be changed with `heap=` on the
+// kernel command line
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024;
 
+// The size of the unmapped guard regions directly before and after the heap
+pub const HEAP_GUARD_SIZE: usize = 4096;
+
+// Set once the heap is mapped and the allocator is initialized
+static HEAP_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the size of the heap, set with `heap=` on the kernel command line, or `HEAP_SIZE`
+pub fn heap_size() -> usize {
+    heap_size_from(cmdline::command_line())
+}
+
+/// Reads the heap size from a command line
+///
+/// # Arguments
+/// ```command_line```: the command line with the `heap=` argument
+///
+/// # Returns
+/// The size rounded up to whole pages, or `HEAP_SIZE` if the argument is missing, zero, or
+/// invalid
+fn heap_size_from(command_line: &CommandLine) -> usize {
+    command_line
+        .get_size("heap")
+        .filter(|&size| size > 0)
+        .and_then(|size| size.div_ceil(4096).checked_mul(4096))
+        .unwrap_or(HEAP_SIZE)
+}
+
+/// Maps the heap pages and initializes the allocator
+///
+/// The page directly before `HEAP_START` and the page directly after the end of the heap are
+/// guard pages. They are left unmapped, so heap underflows and overflows cause a page fault at
+/// the accessed address, instead of silently corrupting adjacent memory.
+///
+/// The size of the heap is read from the kernel command line, see `heap_size`.
+///
+/// # Arguments
+/// ```mapper```: the mapper to create the heap mappings with
+/// ```frame_allocator```: the allocator to take the heap frames from
+///
+/// # Returns
+/// An error if a frame couldn't be allocated, or a heap or guard page is already mapped
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
+    let heap_size = heap_size();
     let page_range = {
         // Take the virtual address of the physical heap start address
         let heap_start = VirtAddr::new(HEAP_START as u64);
 
         // Add the heap size to the heap start and subtract 1 to get the end of the heap
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_end = heap_start + heap_size - 1u64;
 
         // Get the pages of the heap start and heap end
         let heap_start_page = Page::containing_address(heap_start);
@@ -55,6 +116,17 @@ pub fn init_heap(
         Page::range_inclusive(heap_start_page, heap_end_page)
     };
 
+    // Make sure the guard pages aren't mapped, otherwise they wouldn't catch anything
+    let guard_pages = [
+        Page::containing_address(VirtAddr::new((HEAP_START - HEAP_GUARD_SIZE) as u64)),
+        Page::containing_address(VirtAddr::new((HEAP_START + heap_size) as u64)),
+    ];
+    for page in guard_pages {
+        if let Ok(frame) = mapper.translate_page(page) {
+            return Err(MapToError::PageAlreadyMapped(frame));
+        }
+    }
+
     // Iterate through the pages
     for page in page_range {
         // Allocate memory for each frame, return a Frame Allocation Failed error on failure
@@ -71,7 +143,122 @@ pub fn init_heap(
     }
 
     // Initialize the allocator
-    unsafe { ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE) };
+    unsafe { ALLOCATOR.lock().init(HEAP_START, heap_size) };
+    HEAP_INITIALIZED.store(true, Ordering::SeqCst);
 
     Ok(())
 }
+
+/// Returns whether the heap has been initialized, so allocating won't fail because of a missing
+/// heap
+pub fn is_heap_initialized() -> bool {
+    HEAP_INITIALIZED.load(Ordering::SeqCst)
+}
+
+/// Returns the number of bytes allocated on the heap which haven't been freed yet
+pub fn heap_bytes_in_use() -> usize {
+    unsafe { ALLOCATOR.lock().bytes_in_use() }
+}
+
+/// Checks whether everything allocated on the heap has been freed, to catch leaks at the end of
+/// a test
+///
+/// # Panics
+/// If any bytes are still allocated
+pub fn assert_heap_empty() {
+    let bytes_in_use = heap_bytes_in_use();
+    assert!(
+        bytes_in_use == 0,
+        "Heap not empty, {} bytes are still allocated",
+        bytes_in_use
+    );
+}
+
+/// The size of the buffer shared by the allocators created with `test_allocator`
+pub const TEST_HEAP_SIZE: usize = 32 * 1024;
+
+// The heap of the allocators created with `test_allocator`, page aligned so every alignment up
+// to a page can be tested
+#[repr(align(4096))]
+struct TestHeap([u8; TEST_HEAP_SIZE]);
+static mut TEST_HEAP: TestHeap = TestHeap([0; TEST_HEAP_SIZE]);
+
+/// Creates an allocator for a test, which manages the start of a static buffer instead of the
+/// kernel heap. Public for the integration tests, like `assert_heap_empty`.
+///
+/// # Arguments
+/// ```allocator```: the empty allocator
+/// ```init```: the function initializing the allocator with the heap bounds
+/// ```heap_size```: the number of bytes of the buffer to manage, at most `TEST_HEAP_SIZE`
+///
+/// # Returns
+/// The initialized allocator, with a zeroed heap
+///
+/// # Safety
+/// This function is unsafe because every allocator shares the buffer, so the allocator returned by
+/// the previous call can't be used anymore.
+///
+/// # Panics
+/// If the heap size is larger than `TEST_HEAP_SIZE`
+pub unsafe fn test_allocator<A>(
+    allocator: A,
+    init: unsafe fn(&mut A, usize, usize),
+    heap_size: usize,
+) -> Locked<A> {
+    assert!(heap_size <= TEST_HEAP_SIZE, "Test heap too large");
+
+    let heap_start = core::ptr::addr_of_mut!(TEST_HEAP) as usize;
+    core::ptr::write_bytes(heap_start as *mut u8, 0, heap_size);
+    let allocator = Locked::new(allocator);
+    init(&mut allocator.lock(), heap_start, heap_size);
+    allocator
+}
+
+/// The byte freed memory is filled with, when the debug-alloc feature is enabled
+#[cfg(feature = "debug-alloc")]
+pub const POISON_BYTE: u8 = 0xde;
+
+/// Fills freed memory with `POISON_BYTE`, so writes after freeing it can be detected
+///
+/// # Arguments
+/// ```start```: the address of the first byte to fill
+/// ```len```: the number of bytes to fill
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the memory is valid and unused.
+#[cfg(feature = "debug-alloc")]
+unsafe fn poison(start: usize, len: usize) {
+    core::ptr::write_bytes(start as *mut u8, POISON_BYTE, len);
+}
+
+/// Checks whether freed memory still contains only `POISON_BYTE`, before it's reused
+///
+/// # Arguments
+/// ```start```: the address of the first byte to check
+/// ```len```: the number of bytes to check
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the memory is valid to read.
+///
+/// # Panics
+/// If a byte has been changed, as the memory was written to after it was freed
+#[cfg(feature = "debug-alloc")]
+unsafe fn check_poison(start: usize, len: usize) {
+    let bytes = core::slice::from_raw_parts(start as *const u8, len);
+    if let Some(offset) = bytes.iter().position(|&byte| byte != POISON_BYTE) {
+        panic!(
+            "Use after free: freed memory at {:#x} was written to",
+            start + offset
+        );
+    }
+}
+
+/// Checks whether the heap size is read from the command line, and falls back to the default
+#[test_case]
+fn test_heap_size_from_command_line() {
+    assert_eq!(heap_size_from(&CommandLine::new("heap=256k")), 256 * 1024);
+    assert_eq!(heap_size_from(&CommandLine::new("heap=5000")), 8192);
+    assert_eq!(heap_size_from(&CommandLine::new("heap=0")), HEAP_SIZE);
+    assert_eq!(heap_size_from(&CommandLine::new("heap=lots")), HEAP_SIZE);
+    assert_eq!(heap_size_from(&CommandLine::new("quiet")), HEAP_SIZE);
+}
@@ -1,4 +1,7 @@
-use core::alloc::GlobalAlloc;
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::NonNull,
+};
 
 use x86_64::align_up;
 
@@ -12,6 +15,8 @@ pub struct BumpAllocator {
     heap_end: usize,
     next: usize,
     allocations: usize,
+    // The number of bytes requested by allocations which haven't been freed yet
+    bytes_in_use: usize,
 }
 
 impl BumpAllocator {
@@ -22,6 +27,7 @@ impl BumpAllocator {
             heap_end: 0,
             next: 0,
             allocations: 0,
+            bytes_in_use: 0,
         }
     }
 
@@ -35,10 +41,46 @@ impl BumpAllocator {
         self.heap_end = heap_start + heap_size;
         self.next = heap_start;
     }
+
+    /// Frees all allocations at once, making the whole heap available again
+    ///
+    /// # Safety
+    /// This method is unsafe because the caller must guarantee that none of the allocations are
+    /// still in use. Using an allocation after a reset is undefined behavior, as the memory is
+    /// handed out again by the next allocations.
+    pub unsafe fn reset(&mut self) {
+        self.next = self.heap_start;
+        self.allocations = 0;
+        self.bytes_in_use = 0;
+    }
+
+    /// Returns the number of bytes allocated since the last reset, including alignment padding
+    pub fn used_bytes(&self) -> usize {
+        self.next - self.heap_start
+    }
+
+    /// Returns the number of bytes that can still be allocated before the heap is full
+    pub fn remaining_bytes(&self) -> usize {
+        self.heap_end - self.next
+    }
+
+    /// Returns the number of bytes requested by allocations which haven't been freed yet.
+    /// Freed memory isn't reused until every allocation is freed, so this can be far less than
+    /// `used_bytes`.
+    pub fn bytes_in_use(&self) -> usize {
+        self.bytes_in_use
+    }
 }
 
-unsafe impl GlobalAlloc for Locked<BumpAllocator> {
-    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+impl Locked<BumpAllocator> {
+    /// Allocates memory, without returning a null pointer on failure
+    ///
+    /// # Arguments
+    /// ```layout```: the size and alignment of the memory to allocate
+    ///
+    /// # Returns
+    /// A pointer to the allocated memory, or None if there isn't enough space left in the heap
+    pub fn try_alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
         // Get a mutable reference to the BumpAllocator
         let mut bump = self.lock();
 
@@ -46,36 +88,86 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
         let alloc_start = align_up(bump.next as u64, layout.align() as u64);
 
         // Add the size of the allocated memory to the start of the allocation to calculate the end
-        // Return a null pointer if that overflowed
-        let alloc_end = match alloc_start.checked_add(layout.size() as u64) {
-            Some(end) => end,
-            None => return core::ptr::null_mut(),
-        };
+        // Return None if that overflowed
+        let alloc_end = alloc_start.checked_add(layout.size() as u64)?;
 
         // Make sure the end of the allocation is before or at the end of the heap
         if alloc_end > bump.heap_end as u64 {
-            // Return null otherwise
-            core::ptr::null_mut()
+            // Return None otherwise
+            None
         } else {
             // Set the start of the next allocation to the end of this one
             bump.next = alloc_end as usize;
 
             // Increment the number of allocations
             bump.allocations += 1;
+            bump.bytes_in_use += layout.size();
 
-            // Return the start address of the current allocation as a *mut u8
-            alloc_start as *mut u8
+            // Return the start address of the current allocation
+            NonNull::new(alloc_start as *mut u8)
         }
     }
+}
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: core::alloc::Layout) {
+unsafe impl GlobalAlloc for Locked<BumpAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.try_alloc(layout)
+            .map_or(core::ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: Layout) {
         // Take a mutable reference to the BumpAllocator
         let mut bump = self.lock();
 
+        // The memory is only reused after a reset, so it isn't checked when allocated again
+        #[cfg(feature = "debug-alloc")]
+        super::poison(_ptr as usize, layout.size());
+
         // Decrement the number of allocations, reset the allocator if no allocations are left
         bump.allocations -= 1;
+        bump.bytes_in_use -= layout.size();
         if bump.allocations == 0 {
             bump.next = bump.heap_start;
         }
     }
 }
+
+/// Checks whether the first allocation after a reset reuses the start of the heap
+#[test_case]
+fn test_reset_reuses_heap_start() {
+    const HEAP_SIZE: usize = 256;
+
+    let allocator =
+        unsafe { super::test_allocator(BumpAllocator::new(), BumpAllocator::init, HEAP_SIZE) };
+    let heap_start = allocator.lock().heap_start;
+
+    let layout = Layout::from_size_align(16, 1).unwrap();
+    let first = unsafe { allocator.alloc(layout) };
+    let second = unsafe { allocator.alloc(layout) };
+    assert_eq!(first as usize, heap_start);
+    assert_eq!(allocator.lock().used_bytes(), 32);
+    assert_eq!(allocator.lock().remaining_bytes(), HEAP_SIZE - 32);
+
+    // Neither allocation is used after this point
+    unsafe { allocator.lock().reset() };
+    assert_eq!(allocator.lock().used_bytes(), 0);
+    assert!(!second.is_null());
+
+    let reused = unsafe { allocator.alloc(layout) };
+    assert_eq!(reused as usize, heap_start);
+}
+
+/// Checks whether allocating from an exhausted heap returns None instead of a null pointer
+#[test_case]
+fn test_try_alloc_exhausted() {
+    const HEAP_SIZE: usize = 64;
+
+    let allocator =
+        unsafe { super::test_allocator(BumpAllocator::new(), BumpAllocator::init, HEAP_SIZE) };
+
+    let layout = Layout::from_size_align(16, 1).unwrap();
+    for _ in 0..HEAP_SIZE / 16 {
+        assert!(allocator.try_alloc(layout).is_some());
+    }
+    assert_eq!(allocator.try_alloc(layout), None);
+}
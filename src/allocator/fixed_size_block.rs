@@ -16,26 +16,47 @@ pub struct ListNode {
 /// the block alignment (alignments must always be powers of 2)
 const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
+/// The default number of freed blocks kept per block size, to reuse them
+const DEFAULT_MAX_BLOCKS_PER_CLASS: usize = 16;
+
 /// An allocator just like the list allocator, but with less efficient memory usage, but better
 /// performance.
 ///
 ///  - Prefilling the lists might improve performance.
 ///  - Storing the alignment may improve memory usage
-///  - Deallocations aren't freed, freeing them would improve memory usage
+///  - Only up to `max_blocks_per_class` freed blocks are kept per block size, the rest is returned
+///    to the fallback allocator. This bounds the memory wasted on unused blocks.
 ///  - Using a paging allocator instead of linked_list_allocator would decrease fragmentation
 ///  - A paging allocator would also improve performance predictability, improving worst-case performance
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    // The number of blocks in each list
+    list_lengths: [usize; BLOCK_SIZES.len()],
+    max_blocks_per_class: usize,
     fallback_allocator: linked_list_allocator::Heap,
+    // The number of bytes requested by allocations which haven't been freed yet
+    bytes_in_use: usize,
 }
 
 impl FixedSizeBlockAllocator {
     /// Creates an empty FixedSizeBlockAllocator.
     pub const fn new() -> Self {
+        Self::with_max_blocks_per_class(DEFAULT_MAX_BLOCKS_PER_CLASS)
+    }
+
+    /// Creates an empty FixedSizeBlockAllocator, which keeps a limited number of freed blocks
+    ///
+    /// # Arguments
+    /// ```max_blocks_per_class```: the number of freed blocks to keep per block size, further
+    /// freed blocks are returned to the fallback allocator
+    pub const fn with_max_blocks_per_class(max_blocks_per_class: usize) -> Self {
         const EMPTY: Option<&'static mut ListNode> = None;
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
+            list_lengths: [0; BLOCK_SIZES.len()],
+            max_blocks_per_class,
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            bytes_in_use: 0,
         }
     }
 
@@ -50,15 +71,30 @@ impl FixedSizeBlockAllocator {
             .init(heap_start as *mut u8, heap_size);
     }
 
+    /// Returns the number of bytes requested by allocations which haven't been freed yet.
+    /// Blocks kept in the lists for reuse don't count as in use.
+    pub fn bytes_in_use(&self) -> usize {
+        self.bytes_in_use
+    }
+
     /// Allocates using the fallback allocator
-    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
-            Err(()) => core::ptr::null_mut(),
-        }
+    fn fallback_alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        self.fallback_allocator.allocate_first_fit(layout).ok()
     }
 }
 
+/// Returns the layout blocks of a block size are allocated with from the fallback allocator
+///
+/// # Arguments
+/// ```index```: the index of the block size in `BLOCK_SIZES`
+fn block_layout(index: usize) -> Layout {
+    let block_size = BLOCK_SIZES[index];
+
+    // Only works if all block sizes are a power of 2
+    let block_align = block_size;
+    Layout::from_size_align(block_size, block_align).unwrap()
+}
+
 /// Choose an appropriate block size for the given layout.
 ///
 /// Returns an index into the `BLOCK_SIZES` array
@@ -71,35 +107,69 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
 }
 
-unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+impl Locked<FixedSizeBlockAllocator> {
+    /// Allocates memory, without returning a null pointer on failure
+    ///
+    /// # Arguments
+    /// ```layout```: the size and alignment of the memory to allocate
+    ///
+    /// # Returns
+    /// A pointer to the allocated memory, or None if there is no free block and the fallback
+    /// allocator is out of memory
+    pub fn try_alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
         let mut allocator = self.lock();
-        match list_index(&layout) {
+        let ptr = match list_index(&layout) {
             Some(index) => match allocator.list_heads[index].take() {
                 Some(node) => {
                     allocator.list_heads[index] = node.next.take();
-                    node as *mut ListNode as *mut u8
-                }
-                None => {
-                    // No block exists in list => allocate a new block
-                    let block_size = BLOCK_SIZES[index];
-
-                    // Only works if all block sizes are a power of 2
-                    let block_align = block_size;
-                    let layout = Layout::from_size_align(block_size, block_align).unwrap();
-                    allocator.fallback_alloc(layout)
+                    allocator.list_lengths[index] -= 1;
+
+                    // Everything in the block is poisoned, except for its list node
+                    #[cfg(feature = "debug-alloc")]
+                    unsafe {
+                        super::check_poison(
+                            node as *mut ListNode as usize + size_of::<ListNode>(),
+                            BLOCK_SIZES[index] - size_of::<ListNode>(),
+                        );
+                    }
+                    NonNull::new(node as *mut ListNode as *mut u8)
                 }
+                // No block exists in list => allocate a new block
+                None => allocator.fallback_alloc(block_layout(index)),
             },
             None => allocator.fallback_alloc(layout),
+        };
+
+        if ptr.is_some() {
+            allocator.bytes_in_use += layout.size();
         }
+        ptr
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.try_alloc(layout)
+            .map_or(core::ptr::null_mut(), NonNull::as_ptr)
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         // Take a mutable reference to the allocator
         let mut allocator = self.lock();
+        allocator.bytes_in_use -= layout.size();
 
         // Choose an appropriate block size, if available
         match list_index(&layout) {
+            // The list is full => return the block to the fallback allocator
+            Some(index) if allocator.list_lengths[index] >= allocator.max_blocks_per_class => {
+                #[cfg(feature = "debug-alloc")]
+                super::poison(ptr as usize, BLOCK_SIZES[index]);
+
+                let ptr = NonNull::new(ptr).unwrap();
+                allocator
+                    .fallback_allocator
+                    .deallocate(ptr, block_layout(index));
+            }
             Some(index) => {
                 // Create a new list node
                 let new_node = ListNode {
@@ -110,12 +180,23 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 assert!(size_of::<ListNode>() <= BLOCK_SIZES[index]);
                 assert!(align_of::<ListNode>() <= BLOCK_SIZES[index]);
 
+                // Poison the block after the node, the node itself is written below
+                #[cfg(feature = "debug-alloc")]
+                super::poison(
+                    ptr as usize + size_of::<ListNode>(),
+                    BLOCK_SIZES[index] - size_of::<ListNode>(),
+                );
+
                 // Prepend the node to the correct list
                 let new_node_ptr = ptr as *mut ListNode;
                 new_node_ptr.write(new_node);
                 allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                allocator.list_lengths[index] += 1;
             }
             None => {
+                #[cfg(feature = "debug-alloc")]
+                super::poison(ptr as usize, layout.size());
+
                 // Convert the pointer to a NonNull pointer
                 let ptr = NonNull::new(ptr).unwrap();
 
@@ -125,3 +206,78 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
         }
     }
 }
+
+/// Checks whether allocating from an exhausted heap returns None instead of a null pointer
+#[test_case]
+fn test_try_alloc_exhausted() {
+    const HEAP_SIZE: usize = 512;
+
+    let allocator = unsafe {
+        super::test_allocator(
+            FixedSizeBlockAllocator::new(),
+            FixedSizeBlockAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+
+    // The fallback allocator needs some space for bookkeeping, so fewer blocks may fit
+    let layout = Layout::from_size_align(64, 64).unwrap();
+    let allocated = (0..=HEAP_SIZE / 64)
+        .take_while(|_| allocator.try_alloc(layout).is_some())
+        .count();
+    assert!(allocated > 0 && allocated <= HEAP_SIZE / 64);
+    assert_eq!(allocator.try_alloc(layout), None);
+}
+
+/// Checks whether the bytes in use are counted, and freed blocks don't count as in use
+#[test_case]
+fn test_bytes_in_use() {
+    const HEAP_SIZE: usize = 512;
+
+    let allocator = unsafe {
+        super::test_allocator(
+            FixedSizeBlockAllocator::new(),
+            FixedSizeBlockAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+
+    // The requested size counts, not the size of the block
+    let layout = Layout::from_size_align(20, 4).unwrap();
+    let ptr = allocator.try_alloc(layout).expect("Allocation failed");
+    assert_eq!(allocator.lock().bytes_in_use(), 20);
+
+    unsafe { allocator.dealloc(ptr.as_ptr(), layout) };
+    assert_eq!(allocator.lock().bytes_in_use(), 0);
+}
+
+/// Checks whether freed blocks beyond the cap are returned to the fallback allocator
+#[test_case]
+fn test_max_blocks_per_class() {
+    const HEAP_SIZE: usize = 1024;
+
+    let allocator = unsafe {
+        super::test_allocator(
+            FixedSizeBlockAllocator::with_max_blocks_per_class(2),
+            FixedSizeBlockAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+    let free_at_start = allocator.lock().fallback_allocator.free();
+
+    // Allocate as many blocks as fit, then free them all
+    let layout = Layout::from_size_align(64, 64).unwrap();
+    let mut blocks = [None; HEAP_SIZE / 64];
+    for block in blocks.iter_mut() {
+        *block = allocator.try_alloc(layout);
+    }
+    assert!(blocks.iter().flatten().count() > 2);
+    for ptr in blocks.iter().flatten() {
+        unsafe { allocator.dealloc(ptr.as_ptr(), layout) };
+    }
+
+    // Only the 2 blocks in the list are still taken from the fallback allocator
+    let allocator = allocator.lock();
+    assert_eq!(allocator.list_lengths[list_index(&layout).unwrap()], 2);
+    assert_eq!(allocator.fallback_allocator.free(), free_at_start - 2 * 64);
+}
@@ -1,6 +1,7 @@
 use core::{
     alloc::{GlobalAlloc, Layout},
     mem::{align_of, size_of},
+    ptr::NonNull,
 };
 
 use x86_64::align_up;
@@ -33,6 +34,35 @@ impl ListNode {
 ///  - The list might have to be traversed to the end to find a suitable block, which is slow
 pub struct LinkedListAllocator {
     head: ListNode,
+    heap_start: usize,
+    heap_end: usize,
+    // The total size of the free regions in the list
+    free_bytes: usize,
+    // The number of bytes requested by allocations which haven't been freed yet
+    bytes_in_use: usize,
+}
+
+/// The kind of corruption found in the free list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The node isn't completely inside of the heap
+    OutOfBounds,
+    /// The node isn't aligned to the alignment of `ListNode`
+    Misaligned,
+    /// The node is too small to hold a `ListNode`
+    TooSmall,
+    /// The node overlaps with the free region starting at the given address
+    Overlapping(usize),
+    /// The list contains more nodes than fit in the heap, so it must contain a cycle
+    Cycle,
+}
+
+/// Describes corruption found in the free list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CorruptionReport {
+    /// The address of the corrupted node
+    pub address: usize,
+    pub kind: CorruptionKind,
 }
 
 impl LinkedListAllocator {
@@ -40,9 +70,36 @@ impl LinkedListAllocator {
     pub const fn new() -> Self {
         Self {
             head: ListNode::new(0),
+            heap_start: 0,
+            heap_end: 0,
+            free_bytes: 0,
+            bytes_in_use: 0,
         }
     }
 
+    /// Returns the total size of the free regions. Memory lost to alignment padding isn't
+    /// included, as it isn't part of any region.
+    pub fn free_bytes(&self) -> usize {
+        self.free_bytes
+    }
+
+    /// Returns the number of bytes requested by allocations which haven't been freed yet
+    pub fn bytes_in_use(&self) -> usize {
+        self.bytes_in_use
+    }
+
+    /// Returns the size of the largest free region, the largest allocation that may still
+    /// succeed. The smaller it is compared to `free_bytes`, the more fragmented the heap is.
+    pub fn largest_free_region(&self) -> usize {
+        let mut largest = 0;
+        let mut current = self.head.next.as_deref();
+        while let Some(node) = current {
+            largest = largest.max(node.size);
+            current = node.next.as_deref();
+        }
+        largest
+    }
+
     /// Initialize the allocator with the given heap bounds.
     ///
     /// # Safety
@@ -50,9 +107,91 @@ impl LinkedListAllocator {
     /// heap bounds are valid and that the heap is unused. This method must be
     /// called only once.
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
         self.add_free_region(heap_start, heap_size);
     }
 
+    /// Verifies that the free list is consistent, without modifying it.
+    ///
+    /// Every node must be inside of the heap, aligned, large enough to hold a `ListNode`, and
+    /// mustn't overlap with other nodes. A node is only read after its address has been checked,
+    /// so a corrupted `next` pointer is reported instead of causing a page fault. To see a report
+    /// during manual testing, corrupt the list by writing over a freed allocation, e.g. setting
+    /// its first word (the region size) to 0.
+    ///
+    /// # Returns
+    /// A report with the address of the first corrupted node found, if any
+    pub fn check_integrity(&self) -> Result<(), CorruptionReport> {
+        // A valid list can't contain more nodes than fit in the heap
+        let max_nodes = (self.heap_end - self.heap_start) / size_of::<ListNode>();
+
+        let mut current = self.head.next.as_deref();
+        let mut nodes = 0;
+        while let Some(node) = current {
+            let address = node.start_addr();
+            let report = |kind| Err(CorruptionReport { address, kind });
+
+            nodes += 1;
+            if nodes > max_nodes {
+                return report(CorruptionKind::Cycle);
+            }
+            if let Err(kind) = self.check_node_address(address) {
+                return report(kind);
+            }
+            if node.size < size_of::<ListNode>() {
+                return report(CorruptionKind::TooSmall);
+            }
+            if node.size > self.heap_end - address {
+                return report(CorruptionKind::OutOfBounds);
+            }
+
+            // Compare with every later node, as the list isn't sorted.
+            // Their size is checked in later iterations, their address before reading them.
+            let mut other = node.next.as_deref();
+            let mut compared = 0;
+            while let Some(other_node) = other {
+                let other_start = other_node.start_addr();
+                compared += 1;
+                if compared > max_nodes {
+                    return report(CorruptionKind::Cycle);
+                }
+                self.check_node_address(other_start)
+                    .map_err(|kind| CorruptionReport {
+                        address: other_start,
+                        kind,
+                    })?;
+                let other_end = other_start.saturating_add(other_node.size);
+                if other_start < node.end_addr() && address < other_end {
+                    return report(CorruptionKind::Overlapping(other_start));
+                }
+                other = other_node.next.as_deref();
+            }
+
+            current = node.next.as_deref();
+        }
+        Ok(())
+    }
+
+    /// Checks whether a node at the given address could be read, before reading it
+    ///
+    /// # Arguments
+    /// ```address```: the address of the node
+    ///
+    /// # Returns
+    /// The kind of corruption if the node isn't completely inside of the heap, or misaligned
+    fn check_node_address(&self, address: usize) -> Result<(), CorruptionKind> {
+        if address < self.heap_start || address >= self.heap_end {
+            Err(CorruptionKind::OutOfBounds)
+        } else if address % align_of::<ListNode>() != 0 {
+            Err(CorruptionKind::Misaligned)
+        } else if self.heap_end - address < size_of::<ListNode>() {
+            Err(CorruptionKind::OutOfBounds)
+        } else {
+            Ok(())
+        }
+    }
+
     /// Adds the given memory region to the front of the list
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         // Ensure that the freed region is capable of holding ListNode
@@ -62,12 +201,17 @@ impl LinkedListAllocator {
         );
         assert!(size >= size_of::<ListNode>());
 
+        // Poison the region after the node, the node itself is written below
+        #[cfg(feature = "debug-alloc")]
+        super::poison(addr + size_of::<ListNode>(), size - size_of::<ListNode>());
+
         // Create a new list node and append it at the start of the list
         let mut node = ListNode::new(size);
         node.next = self.head.next.take();
         let node_ptr = addr as *mut ListNode;
         node_ptr.write(node);
         self.head.next = Some(&mut *node_ptr);
+        self.free_bytes += size;
     }
 
     /// looks for a free region with the given size and alignment and removes it
@@ -139,24 +283,45 @@ impl LinkedListAllocator {
     }
 }
 
-unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
-    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+impl Locked<LinkedListAllocator> {
+    /// Allocates memory, without returning a null pointer on failure
+    ///
+    /// # Arguments
+    /// ```layout```: the size and alignment of the memory to allocate
+    ///
+    /// # Returns
+    /// A pointer to the allocated memory, or None if no free region is large enough
+    pub fn try_alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
         // Perform layout adjustments
         let (size, align) = LinkedListAllocator::size_align(layout);
 
         // Take a mutable reference to the LinkedListAllocator
         let mut allocator = self.lock();
 
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
-            let alloc_end = alloc_start.checked_add(size).expect("overflow");
-            let excess_size = region.end_addr() - alloc_end;
-            if excess_size > 0 {
-                allocator.add_free_region(alloc_end, excess_size);
-            }
-            alloc_start as *mut u8
-        } else {
-            core::ptr::null_mut()
+        let (region, alloc_start) = allocator.find_region(size, align)?;
+        allocator.free_bytes -= region.size;
+        allocator.bytes_in_use += layout.size();
+        let alloc_end = alloc_start.checked_add(size).expect("overflow");
+
+        // Everything in a free region is poisoned, except for its list node
+        #[cfg(feature = "debug-alloc")]
+        {
+            let check_start = alloc_start.max(region.start_addr() + size_of::<ListNode>());
+            unsafe { super::check_poison(check_start, alloc_end.saturating_sub(check_start)) };
         }
+        let excess_size = region.end_addr() - alloc_end;
+        if excess_size > 0 {
+            // Safe, as the excess region was part of the free region and is no longer in the list
+            unsafe { allocator.add_free_region(alloc_end, excess_size) };
+        }
+        NonNull::new(alloc_start as *mut u8)
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+    unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        self.try_alloc(layout)
+            .map_or(core::ptr::null_mut(), NonNull::as_ptr)
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
@@ -164,6 +329,172 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         let (size, _) = LinkedListAllocator::size_align(layout);
 
         // Add the region to the free regions
-        self.lock().add_free_region(ptr as usize, size);
+        let mut allocator = self.lock();
+        allocator.bytes_in_use -= layout.size();
+        allocator.add_free_region(ptr as usize, size);
     }
 }
+
+/// Checks whether a valid free list passes the integrity check
+#[test_case]
+fn test_check_integrity_valid_list() {
+    const HEAP_SIZE: usize = 1024;
+
+    let allocator = unsafe {
+        super::test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+    assert_eq!(allocator.lock().check_integrity(), Ok(()));
+
+    // Split the heap into several free regions
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let allocations = [
+        unsafe { allocator.alloc(layout) },
+        unsafe { allocator.alloc(layout) },
+        unsafe { allocator.alloc(layout) },
+    ];
+    assert!(allocations.iter().all(|ptr| !ptr.is_null()));
+    unsafe {
+        allocator.dealloc(allocations[0], layout);
+        allocator.dealloc(allocations[2], layout);
+    }
+    assert_eq!(allocator.lock().check_integrity(), Ok(()));
+}
+
+/// Checks whether a corrupted next pointer is reported without reading the node it points to
+#[test_case]
+fn test_check_integrity_corrupted_next() {
+    const HEAP_SIZE: usize = 256;
+    let allocator = unsafe {
+        super::test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+
+    // Free an allocation, so its node is the first of the list, followed by the rest of the heap
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let ptr = allocator.try_alloc(layout).expect("Allocation failed");
+    unsafe { allocator.dealloc(ptr.as_ptr(), layout) };
+    assert_eq!(allocator.lock().check_integrity(), Ok(()));
+
+    // Point its next pointer outside of the heap, at memory that may not even be mapped.
+    // Option<&mut ListNode> has the layout of a pointer, with None as null.
+    const OUTSIDE: usize = 0x1000;
+    let node = ptr.as_ptr() as *mut ListNode;
+    unsafe {
+        core::ptr::addr_of_mut!((*node).next)
+            .cast::<usize>()
+            .write(OUTSIDE)
+    };
+    assert_eq!(
+        allocator.lock().check_integrity(),
+        Err(CorruptionReport {
+            address: OUTSIDE,
+            kind: CorruptionKind::OutOfBounds,
+        })
+    );
+
+    // A next pointer inside of the heap, but not aligned to a node, is reported as well
+    let misaligned = ptr.as_ptr() as usize + 64 + 1;
+    unsafe {
+        core::ptr::addr_of_mut!((*node).next)
+            .cast::<usize>()
+            .write(misaligned)
+    };
+    assert_eq!(
+        allocator.lock().check_integrity(),
+        Err(CorruptionReport {
+            address: misaligned,
+            kind: CorruptionKind::Misaligned,
+        })
+    );
+}
+
+/// Checks whether allocating from an exhausted heap returns None instead of a null pointer
+#[test_case]
+fn test_try_alloc_exhausted() {
+    const HEAP_SIZE: usize = 128;
+    let allocator = unsafe {
+        super::test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    for _ in 0..HEAP_SIZE / 32 {
+        assert!(allocator.try_alloc(layout).is_some());
+    }
+    assert_eq!(allocator.try_alloc(layout), None);
+}
+
+/// Checks whether freeing every other allocation fragments the heap, leaving more free bytes
+/// than the largest free region
+#[test_case]
+fn test_fragmentation_stats() {
+    const HEAP_SIZE: usize = 256;
+    let allocator = unsafe {
+        super::test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+    assert_eq!(allocator.lock().free_bytes(), HEAP_SIZE);
+    assert_eq!(allocator.lock().largest_free_region(), HEAP_SIZE);
+
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let mut allocations = [core::ptr::null_mut(); HEAP_SIZE / 32];
+    for allocation in allocations.iter_mut() {
+        *allocation = unsafe { allocator.alloc(layout) };
+        assert!(!allocation.is_null());
+    }
+    assert_eq!(allocator.lock().free_bytes(), 0);
+
+    // Free every other allocation, the regions aren't merged
+    for &allocation in allocations.iter().step_by(2) {
+        unsafe { allocator.dealloc(allocation, layout) };
+    }
+    let allocator = allocator.lock();
+    assert_eq!(allocator.free_bytes(), HEAP_SIZE / 2);
+    assert_eq!(allocator.largest_free_region(), 32);
+    assert!(allocator.free_bytes() > allocator.largest_free_region());
+}
+
+/// Checks whether freed memory is poisoned, without overwriting the list node
+#[cfg(feature = "debug-alloc")]
+#[test_case]
+fn test_poison_freed_memory() {
+    use super::POISON_BYTE;
+
+    const HEAP_SIZE: usize = 256;
+    let allocator = unsafe {
+        super::test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            HEAP_SIZE,
+        )
+    };
+
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let ptr = allocator.try_alloc(layout).expect("Allocation failed");
+    unsafe {
+        ptr.as_ptr().write_bytes(0x42, 64);
+        allocator.dealloc(ptr.as_ptr(), layout);
+    }
+
+    let freed = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 64) };
+    assert!(freed[size_of::<ListNode>()..]
+        .iter()
+        .all(|&byte| byte == POISON_BYTE));
+    assert_eq!(allocator.lock().check_integrity(), Ok(()));
+
+    // Reusing untouched memory passes the check
+    assert!(allocator.try_alloc(layout).is_some());
+}
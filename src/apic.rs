@@ -0,0 +1,148 @@
+//! The local APIC, which replaces the PIT as source of timer interrupts when it's available.
+//! Other interrupts, like the keyboard, still arrive through the 8259 PIC, which the local APIC
+//! passes on in virtual wire mode.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::{
+    instructions::interrupts,
+    registers::model_specific::Msr,
+    structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Size4KiB},
+    PhysAddr,
+};
+
+use crate::{
+    cpu::CpuFeatures,
+    interrupts::InterruptIndex,
+    memory,
+    port::{self, PIC1_DATA},
+};
+
+/// The size of the local APIC registers, a single frame
+const APIC_REGISTERS_SIZE: usize = 4096;
+
+/// The interrupt vector of spurious interrupts, the lowest 4 bits have to be set
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+/// The model specific register containing the physical address of the local APIC
+const IA32_APIC_BASE: u32 = 0x1b;
+
+// The offsets of the local APIC registers
+const EOI_REGISTER: u64 = 0xb0;
+const SPURIOUS_REGISTER: u64 = 0xf0;
+const LVT_TIMER_REGISTER: u64 = 0x320;
+const TIMER_INITIAL_COUNT_REGISTER: u64 = 0x380;
+const TIMER_DIVIDE_REGISTER: u64 = 0x3e0;
+
+/// Enables the local APIC in the spurious interrupt vector register
+const APIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+
+/// Makes the timer restart after every interrupt in the LVT timer register
+const TIMER_PERIODIC: u32 = 1 << 17;
+
+/// Divides the bus frequency by 16 in the timer divide configuration register
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// The number of divided bus cycles between timer interrupts.
+/// The bus frequency differs per machine, so the resulting rate does too.
+const TIMER_INITIAL_COUNT: u32 = 0x20_0000;
+
+// The virtual address of the local APIC registers, 0 while the local APIC isn't used
+static APIC_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// The device generating the timer interrupts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerSource {
+    /// The legacy PIT, through the 8259 PIC
+    Pic,
+    /// The timer of the local APIC
+    Apic,
+}
+
+/// Switches the timer interrupt to the local APIC timer, if the CPU has a local APIC.
+/// Maps the local APIC registers, enables the local APIC, programs its timer in periodic mode,
+/// and masks the PIT interrupt on the PIC. Keeps using the PIT otherwise.
+///
+/// # Arguments
+/// ```mapper```: the mapper to map the local APIC registers with
+/// ```frame_allocator```: the allocator to take the frames for new page tables from
+///
+/// # Returns
+/// The device generating timer interrupts from now on, or an error if mapping the local APIC
+/// registers failed
+pub fn init(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<TimerSource, MapToError<Size4KiB>> {
+    if !CpuFeatures::detect().apic {
+        return Ok(TimerSource::Pic);
+    }
+
+    // The physical address is stored in bits 12 and up of the APIC base register
+    let physical_address = unsafe { Msr::new(IA32_APIC_BASE).read() } & 0x000f_ffff_ffff_f000;
+
+    // Safe, as the registers are memory mapped I/O in a frame reserved for the local APIC
+    let base = unsafe {
+        memory::map_mmio(
+            PhysAddr::new(physical_address),
+            APIC_REGISTERS_SIZE,
+            mapper,
+            frame_allocator,
+        )?
+    };
+
+    // Run without interrupts, as the timer mustn't fire before it's fully set up
+    interrupts::without_interrupts(|| {
+        APIC_BASE.store(base.as_u64(), Ordering::SeqCst);
+
+        // Safe, as the registers have just been mapped
+        unsafe {
+            write_register(
+                SPURIOUS_REGISTER,
+                APIC_SOFTWARE_ENABLE | u32::from(SPURIOUS_VECTOR),
+            );
+            write_register(TIMER_DIVIDE_REGISTER, TIMER_DIVIDE_BY_16);
+            write_register(
+                LVT_TIMER_REGISTER,
+                TIMER_PERIODIC | u32::from(InterruptIndex::Timer as u8),
+            );
+            write_register(TIMER_INITIAL_COUNT_REGISTER, TIMER_INITIAL_COUNT);
+
+            // Mask the PIT interrupt (IRQ 0) on the primary PIC, leaving the keyboard unmasked
+            let masks = port::read_u8(PIC1_DATA);
+            port::write_u8(PIC1_DATA, masks | 0x01);
+        }
+    });
+
+    Ok(TimerSource::Apic)
+}
+
+/// Returns whether the local APIC generates the timer interrupts
+pub fn is_enabled() -> bool {
+    APIC_BASE.load(Ordering::SeqCst) != 0
+}
+
+/// Notifies the local APIC that an interrupt has been handled, to receive the next interrupt
+///
+/// # Panics
+/// If the local APIC isn't enabled
+pub fn end_of_interrupt() {
+    assert!(is_enabled(), "The local APIC isn't enabled");
+
+    // Safe, as the registers are mapped once the local APIC is enabled
+    unsafe { write_register(EOI_REGISTER, 0) };
+}
+
+/// Writes a local APIC register
+///
+/// # Arguments
+/// ```offset```: the offset of the register
+/// ```value```: the value to write
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the local APIC registers are
+/// mapped at `APIC_BASE`, and that the value is valid for the register.
+unsafe fn write_register(offset: u64, value: u32) {
+    let address = APIC_BASE.load(Ordering::SeqCst) + offset;
+    core::ptr::write_volatile(address as *mut u32, value);
+}
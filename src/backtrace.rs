@@ -0,0 +1,58 @@
+//! Backtraces of the kernel over serial.
+//! The return addresses aren't symbolized, look them up in the map file or with `addr2line` on
+//! the kernel binary instead. Relies on the kernel being built with frame pointers, see
+//! `.cargo/config.toml`.
+
+use core::fmt;
+
+use crate::{debug::walk_stack, serial::SerialWriter};
+
+/// Prints the return address of every calling function over serial, starting with the caller
+/// of this function
+pub fn print_backtrace() {
+    let _ = write_backtrace(&mut SerialWriter);
+}
+
+/// Writes the return address of every calling function, one per line
+///
+/// # Arguments
+/// ```output```: where to write the backtrace to
+fn write_backtrace(output: &mut impl fmt::Write) -> fmt::Result {
+    writeln!(output, "Backtrace:")?;
+    let mut result = Ok(());
+    walk_stack(|address| {
+        if result.is_ok() {
+            result = writeln!(output, "  {:#018x}", address.as_u64());
+        }
+    });
+    result
+}
+
+/// Checks whether a backtrace from a few frames deep contains distinct return addresses
+#[test_case]
+fn test_backtrace_from_nested_calls() {
+    use alloc::{string::String, vec::Vec};
+
+    #[inline(never)]
+    fn nested(depth: usize, output: &mut String) {
+        if depth == 0 {
+            write_backtrace(output).expect("Writing failed");
+            print_backtrace();
+        } else {
+            nested(depth - 1, output);
+        }
+        // Keep the call from becoming a tail call, which would reuse the frame
+        core::hint::black_box(depth);
+    }
+
+    let mut output = String::new();
+    nested(3, &mut output);
+
+    let mut addresses: Vec<&str> = output
+        .lines()
+        .filter_map(|line| line.strip_prefix("  0x"))
+        .collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+    assert!(addresses.len() >= 2);
+}
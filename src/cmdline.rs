@@ -0,0 +1,129 @@
+//! The kernel command line.
+//! The bootloader doesn't pass a command line, so it's set at build time through the
+//! `BLOG_OS_CMDLINE` environment variable instead, e.g. `BLOG_OS_CMDLINE="quiet heap=256k"`.
+//! Arguments are separated by whitespace, and are either `key=value` pairs or flags.
+//!
+//! The kernel reads these arguments:
+//! - `heap=<size>`: the size of the kernel heap, like `256k`
+//! - `loglevel=<level>`: the most detailed log level shown, `error`, `warning`, `info`, or `debug`
+//! - `keymap=<layout>`: the keyboard layout, `us`, `uk`, `azerty`, or `dvorak`
+
+/// A parsed command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandLine<'a> {
+    line: &'a str,
+}
+
+impl<'a> CommandLine<'a> {
+    /// Creates a command line
+    ///
+    /// # Arguments
+    /// ```line```: the arguments, separated by whitespace
+    pub const fn new(line: &'a str) -> Self {
+        CommandLine { line }
+    }
+
+    /// Looks up the value of a `key=value` argument
+    ///
+    /// # Arguments
+    /// ```key```: the key to look up
+    ///
+    /// # Returns
+    /// The value of the last argument with the key, or None if there is none
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        // Search backwards, so later arguments override earlier ones
+        self.line
+            .split_whitespace()
+            .rev()
+            .filter_map(|argument| argument.split_once('='))
+            .find(|&(argument_key, _)| argument_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Looks up the value of a `key=value` argument as size in bytes
+    ///
+    /// # Arguments
+    /// ```key```: the key to look up
+    ///
+    /// # Returns
+    /// The size, or None if there is no argument with the key or it isn't a valid size
+    pub fn get_size(&self, key: &str) -> Option<usize> {
+        parse_size(self.get(key)?)
+    }
+
+    /// Checks whether a flag (an argument without value) is set
+    ///
+    /// # Arguments
+    /// ```name```: the name of the flag
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.line
+            .split_whitespace()
+            .any(|argument| argument == name)
+    }
+}
+
+/// Parses a size like `4096`, `256k`, or `2M`
+///
+/// # Arguments
+/// ```size```: the size, with an optional binary unit suffix
+///
+/// # Returns
+/// The size in bytes, or None if it isn't a valid size or it overflows
+fn parse_size(size: &str) -> Option<usize> {
+    let (number, multiplier) = match size.as_bytes().last()? {
+        b'k' | b'K' => (&size[..size.len() - 1], 1024),
+        b'm' | b'M' => (&size[..size.len() - 1], 1024 * 1024),
+        b'g' | b'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// The command line passed in by the build
+static KERNEL_COMMAND_LINE: CommandLine<'static> =
+    CommandLine::new(match option_env!("BLOG_OS_CMDLINE") {
+        Some(line) => line,
+        None => "",
+    });
+
+/// Returns the kernel command line
+pub fn command_line() -> &'static CommandLine<'static> {
+    &KERNEL_COMMAND_LINE
+}
+
+/// Looks up the value of a `key=value` argument on the kernel command line
+///
+/// # Arguments
+/// ```key```: the key to look up
+pub fn get(key: &str) -> Option<&'static str> {
+    KERNEL_COMMAND_LINE.get(key)
+}
+
+/// Looks up the value of a `key=value` argument on the kernel command line as size in bytes
+///
+/// # Arguments
+/// ```key```: the key to look up
+pub fn get_size(key: &str) -> Option<usize> {
+    KERNEL_COMMAND_LINE.get_size(key)
+}
+
+/// Checks whether a flag is set on the kernel command line
+///
+/// # Arguments
+/// ```name```: the name of the flag
+pub fn has_flag(name: &str) -> bool {
+    KERNEL_COMMAND_LINE.has_flag(name)
+}
+
+/// Checks whether key=value pairs, flags, and sizes are parsed
+#[test_case]
+fn test_parse_command_line() {
+    let command_line = CommandLine::new("loglevel=debug quiet heap=256k");
+    assert_eq!(command_line.get("loglevel"), Some("debug"));
+    assert_eq!(command_line.get("heap"), Some("256k"));
+    assert_eq!(command_line.get("quiet"), None);
+    assert_eq!(command_line.get_size("heap"), Some(256 * 1024));
+    assert!(command_line.has_flag("quiet"));
+    assert!(!command_line.has_flag("loglevel"));
+    assert!(!command_line.has_flag("debug"));
+}
@@ -0,0 +1,144 @@
+//! The output of `print!` and `println!`.
+//! A console can be selected once with `select`, based on the display mode the bootloader set up.
+//! Until then, and whenever no other console is selected, the VGA text buffer is used.
+//! bootloader 0.9 always starts the kernel in VGA text mode, so `try_init` doesn't select another
+//! console. That needs a bootloader that provides a framebuffer, see `framebuffer`.
+
+use core::fmt;
+
+use conquer_once::spin::OnceCell;
+
+use crate::vga_buffer::{Role, WRITER};
+
+/// A text output, which `print!` and `println!` write to
+pub trait Console: Sync {
+    /// Writes a string, handling new lines and wrapping. Writing to a console can't fail, as
+    /// there's no other place to report an error.
+    ///
+    /// # Arguments
+    /// ```s```: the string to write
+    fn write_str(&self, s: &str);
+
+    /// Writes a string in the color of a role, keeping the color of the text written after it.
+    /// Consoles without colors write it like `write_str`.
+    ///
+    /// # Arguments
+    /// ```role```: the role of the text
+    /// ```s```: the string to write
+    fn write_str_as(&self, _role: Role, s: &str) {
+        self.write_str(s);
+    }
+}
+
+/// The VGA text buffer, through `WRITER`
+pub struct VgaConsole;
+
+impl Console for VgaConsole {
+    fn write_str(&self, s: &str) {
+        use fmt::Write;
+
+        let _ = WRITER.write().write_str(s);
+    }
+
+    fn write_str_as(&self, role: Role, s: &str) {
+        WRITER
+            .write()
+            .with_role(role, |writer| writer.write_string(s));
+    }
+}
+
+// The selected console, the VGA text buffer if none was selected
+static CONSOLE: OnceCell<&'static dyn Console> = OnceCell::uninit();
+
+/// Selects the console to print to
+///
+/// # Arguments
+/// ```console```: the console which `print!` and `println!` write to from now on
+///
+/// # Panics
+/// If a console was already selected
+pub fn select(console: &'static dyn Console) {
+    CONSOLE.init_once(|| console);
+}
+
+/// Returns the selected console
+pub fn console() -> &'static dyn Console {
+    CONSOLE.get().copied().unwrap_or(&VgaConsole)
+}
+
+/// Formats text onto a console
+struct ConsoleWriter<'a>(&'a dyn Console);
+
+impl fmt::Write for ConsoleWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s);
+        Ok(())
+    }
+}
+
+/// Formats text onto a console, in the color of a role
+struct RoleWriter<'a>(&'a dyn Console, Role);
+
+impl fmt::Write for RoleWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str_as(self.1, s);
+        Ok(())
+    }
+}
+
+/// Prints formatted text to the selected console
+///
+/// # Arguments
+/// ```args```: the arguments to parse and print
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    // Run the following code without interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let _ = ConsoleWriter(console()).write_fmt(args);
+
+        #[cfg(feature = "mirror-serial")]
+        crate::serial::_mirror(args);
+    });
+}
+
+/// Prints formatted text to the selected console, in the color of a role
+///
+/// # Arguments
+/// ```role```: the role of the text
+/// ```args```: the arguments to parse and print
+#[doc(hidden)]
+pub fn _print_as(role: Role, args: fmt::Arguments) {
+    use fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    // Run the following code without interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let _ = RoleWriter(console(), role).write_fmt(args);
+
+        #[cfg(feature = "mirror-serial")]
+        crate::serial::_mirror(args);
+    });
+}
+
+/// Checks whether the console writes formatted text through `Console::write_str`
+#[test_case]
+fn test_console_writer() {
+    use alloc::string::String;
+    use fmt::Write;
+    use spin::Mutex;
+
+    struct RecordingConsole(Mutex<String>);
+
+    impl Console for RecordingConsole {
+        fn write_str(&self, s: &str) {
+            self.0.lock().push_str(s);
+        }
+    }
+
+    let console = RecordingConsole(Mutex::new(String::new()));
+    write!(ConsoleWriter(&console), "{} + {} = {}", 1, 2, 3).expect("Writing failed");
+    assert_eq!(*console.0.lock(), "1 + 2 = 3");
+}
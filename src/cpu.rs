@@ -0,0 +1,95 @@
+//! CPU feature configuration.
+
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+/// Enables the FPU and SSE, so floating point instructions don't cause exceptions
+///
+/// Clears CR0.EM to stop emulating the FPU, sets CR0.MP to monitor the coprocessor on task
+/// switches, and sets CR4.OSFXSR and CR4.OSXMMEXCPT to enable SSE instructions and their
+/// exceptions.
+pub fn enable_sse() {
+    // Safe, as these flags only enable instructions and don't change memory accesses
+    unsafe {
+        Cr0::update(|flags| {
+            flags.remove(Cr0Flags::EMULATE_COPROCESSOR);
+            flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+        });
+        Cr4::update(|flags| {
+            flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE);
+        });
+    }
+}
+
+/// The optional CPU features the kernel can make use of, as reported by CPUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuFeatures {
+    /// Whether the CPU has a local APIC
+    pub apic: bool,
+    /// Whether the CPU has a time stamp counter
+    pub tsc: bool,
+    /// Whether the rdrand instruction is available
+    pub rdrand: bool,
+    /// Whether SSE instructions are available
+    pub sse: bool,
+    /// Whether the fxsave and fxrstor instructions are available, needed to enable SSE
+    pub fxsr: bool,
+}
+
+impl CpuFeatures {
+    /// Detects the features of the current CPU
+    pub fn detect() -> Self {
+        // Safe, as CPUID leaf 1 is available on every x86_64 CPU
+        let result = unsafe { core::arch::x86_64::__cpuid(1) };
+        CpuFeatures {
+            apic: result.edx & (1 << 9) != 0,
+            tsc: result.edx & (1 << 4) != 0,
+            rdrand: result.ecx & (1 << 30) != 0,
+            sse: result.edx & (1 << 25) != 0,
+            fxsr: result.edx & (1 << 24) != 0,
+        }
+    }
+}
+
+/// Checks whether the control registers enable SSE after initialization
+#[test_case]
+fn test_sse_enabled() {
+    let cr0 = Cr0::read();
+    assert!(!cr0.contains(Cr0Flags::EMULATE_COPROCESSOR));
+    assert!(cr0.contains(Cr0Flags::MONITOR_COPROCESSOR));
+    assert!(Cr4::read().contains(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+}
+
+/// Checks whether SSE instructions run after initialization.
+/// The kernel is compiled with soft-float, so the multiply is done with mulsd directly, which
+/// would cause an invalid opcode exception if SSE wasn't enabled.
+#[test_case]
+fn test_floating_point_multiply() {
+    use x86_64::instructions::interrupts;
+
+    let a = 1.5f64;
+    let b = 4.0f64;
+    let mut result = 0.0f64;
+    // The compiler doesn't use the SSE registers, so xmm0 can be overwritten. Interrupts are
+    // disabled, so nothing else runs SSE instructions in between.
+    interrupts::without_interrupts(|| unsafe {
+        core::arch::asm!(
+            "movsd xmm0, [{a}]",
+            "mulsd xmm0, [{b}]",
+            "movsd [{result}], xmm0",
+            a = in(reg) &a,
+            b = in(reg) &b,
+            result = in(reg) &mut result,
+            options(nostack),
+        );
+    });
+    assert_eq!(result, 6.0);
+}
+
+/// Checks whether the features every x86_64 CPU has are detected
+#[test_case]
+fn test_detect_features() {
+    let features = CpuFeatures::detect();
+    assert!(features.tsc);
+    assert!(features.sse);
+    assert!(features.fxsr);
+}
@@ -0,0 +1,230 @@
+//! Helpers for inspecting the state of the kernel over serial.
+
+use x86_64::VirtAddr;
+
+use crate::serial_println;
+
+/// The number of bytes shown on a single hexdump line
+const BYTES_PER_LINE: usize = 16;
+
+/// The length of a hexdump line: the address, the hex bytes, and the ASCII gutter
+const LINE_LENGTH: usize = 16 + 2 + BYTES_PER_LINE * 3 + 2 + BYTES_PER_LINE + 1;
+
+/// A single hexdump line, formatted on the stack to avoid allocating
+struct Line {
+    bytes: [u8; LINE_LENGTH],
+    len: usize,
+}
+
+impl Line {
+    /// Creates an empty line
+    fn new() -> Self {
+        Line {
+            bytes: [0; LINE_LENGTH],
+            len: 0,
+        }
+    }
+
+    /// Appends a character to the line
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.len] = byte;
+        self.len += 1;
+    }
+
+    /// Appends a value as lowercase hexadecimal, padded with zeros
+    ///
+    /// # Arguments
+    /// ```value```: the value to append
+    /// ```digits```: the number of hexadecimal digits to append
+    fn push_hex(&mut self, value: u64, digits: usize) {
+        const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+        for digit in (0..digits).rev() {
+            self.push(HEX_DIGITS[(value >> (digit * 4)) as usize & 0xf]);
+        }
+    }
+
+    /// Returns the line as string slice
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).expect("Hexdump line isn't ASCII")
+    }
+}
+
+/// Formats up to 16 bytes as hexdump line
+///
+/// # Arguments
+/// ```address```: the address of the first byte
+/// ```bytes```: the bytes to show on this line
+///
+/// # Returns
+/// A line like `0000000000001000  48 69 ...  |Hi...|`
+fn format_line(address: usize, bytes: &[u8]) -> Line {
+    let mut line = Line::new();
+    line.push_hex(address as u64, 16);
+    line.push(b' ');
+    line.push(b' ');
+
+    // Pad the hex column of a short line, to keep the ASCII gutters aligned
+    for i in 0..BYTES_PER_LINE {
+        match bytes.get(i) {
+            Some(&byte) => line.push_hex(u64::from(byte), 2),
+            None => {
+                line.push(b' ');
+                line.push(b' ');
+            }
+        }
+        line.push(b' ');
+    }
+
+    line.push(b' ');
+    line.push(b'|');
+    for &byte in bytes {
+        match byte {
+            // printable character
+            0x20..=0x7e => line.push(byte),
+            // not part of printable ASCII range
+            _ => line.push(b'.'),
+        }
+    }
+    line.push(b'|');
+    line
+}
+
+/// Prints a memory region over serial, 16 bytes per line
+///
+/// # Arguments
+/// ```addr```: the start of the memory region
+/// ```len```: the number of bytes to print
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the complete memory region
+/// is mapped and readable.
+pub unsafe fn hexdump(addr: *const u8, len: usize) {
+    let bytes = core::slice::from_raw_parts(addr, len);
+    for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let line = format_line(addr as usize + i * BYTES_PER_LINE, chunk);
+        serial_println!("{}", line.as_str());
+    }
+}
+
+/// Walks the chain of saved frame pointers on the stack, visiting the return address of every
+/// calling function, starting with the caller of this function. Stops at the first frame pointer
+/// that isn't mapped or doesn't move up the stack, so a corrupt stack can't cause a page fault.
+///
+/// Relies on the kernel being built with frame pointers, see `.cargo/config.toml`.
+///
+/// # Arguments
+/// ```visit```: called with the return address of every frame, at most 32 times
+pub fn walk_stack(mut visit: impl FnMut(VirtAddr)) {
+    // The maximum number of frames to visit, in case the chain loops
+    const MAX_FRAMES: usize = 32;
+
+    // Without the physical memory mapping, the frame pointers can't be checked before reading
+    let Some(physical_memory_offset) = crate::memory::physical_memory_offset() else {
+        return;
+    };
+    // Safe, as the complete physical memory is mapped at the offset set by memory::init
+    let is_mapped = |address: u64| {
+        VirtAddr::try_new(address).is_ok_and(|address| unsafe {
+            crate::memory::translate_addr(address, physical_memory_offset).is_some()
+        })
+    };
+
+    let mut frame_pointer: u64;
+    // Safe, as reading rbp has no side effects
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) frame_pointer, options(nomem, nostack, preserves_flags));
+    }
+
+    for _ in 0..MAX_FRAMES {
+        // A frame stores the previous frame pointer, followed by the return address
+        let frame = frame_pointer as *const u64;
+        if frame_pointer == 0
+            || frame_pointer % 8 != 0
+            || !is_mapped(frame_pointer)
+            || !is_mapped(frame_pointer + 8)
+        {
+            break;
+        }
+
+        // Safe, as both words are mapped
+        let (next, return_address) = unsafe { (frame.read(), frame.add(1).read()) };
+        match VirtAddr::try_new(return_address) {
+            Ok(address) if return_address != 0 => visit(address),
+            _ => break,
+        }
+
+        // The stack grows down, so the frames of the callers are at higher addresses
+        if next <= frame_pointer {
+            break;
+        }
+        frame_pointer = next;
+    }
+}
+
+/// Prints the control registers, the stack and frame pointer, and the heap usage over serial, to
+/// inspect a stuck kernel without a debugger. Safe to call from an interrupt handler, as it
+/// doesn't wait for the heap lock.
+pub fn dump_state() {
+    use x86_64::registers::{
+        control::{Cr0, Cr2, Cr3, Cr4},
+        rflags,
+    };
+
+    let (stack_pointer, frame_pointer): (u64, u64);
+    // Safe, as reading rsp and rbp has no side effects
+    unsafe {
+        core::arch::asm!(
+            "mov {}, rsp",
+            "mov {}, rbp",
+            out(reg) stack_pointer,
+            out(reg) frame_pointer,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+
+    serial_println!("=== STATE DUMP ===");
+    serial_println!("RSP: {:#018x}  RBP: {:#018x}", stack_pointer, frame_pointer);
+    serial_println!("RFLAGS: {:?}", rflags::read());
+    serial_println!("CR0: {:?}", Cr0::read());
+    serial_println!("CR2: {:?}", Cr2::read());
+    serial_println!("CR3: {:?}", Cr3::read());
+    serial_println!("CR4: {:?}", Cr4::read());
+
+    if !crate::allocator::is_heap_initialized() {
+        serial_println!("Heap: not initialized");
+    } else if let Some(allocator) = unsafe { crate::allocator::ALLOCATOR.try_lock() } {
+        serial_println!("Heap: {} bytes in use", allocator.bytes_in_use());
+    } else {
+        // The dump may interrupt an allocation, which would never release the lock
+        serial_println!("Heap: locked");
+    }
+    serial_println!("=== END STATE DUMP ===");
+}
+
+/// Checks the format of full and partial hexdump lines
+#[test_case]
+fn test_hexdump_format() {
+    static DATA: [u8; 18] = *b"Hello, World!\n\x00\xffAB";
+
+    assert_eq!(
+        format_line(0x1000, &DATA[..16]).as_str(),
+        "0000000000001000  48 65 6c 6c 6f 2c 20 57 6f 72 6c 64 21 0a 00 ff  |Hello, World!...|"
+    );
+    assert_eq!(
+        format_line(0x1010, &DATA[16..]).as_str(),
+        "0000000000001010  41 42                                            |AB|"
+    );
+
+    unsafe { hexdump(DATA.as_ptr(), DATA.len()) };
+}
+
+/// Checks whether the stack walk finds the return address into the test runner
+#[test_case]
+fn test_walk_stack() {
+    let mut frames = 0;
+    walk_stack(|address| {
+        assert_ne!(address.as_u64(), 0);
+        frames += 1;
+    });
+    assert!(frames > 0);
+}
@@ -0,0 +1,439 @@
+//! Graphics on a linear framebuffer, as an alternative to the `vga_buffer` text mode.
+//!
+//! The bootloader has to be configured to set up a framebuffer (VBE or GOP) and describe it to
+//! the kernel. bootloader 0.9, as used now, always starts the kernel in VGA text mode, so this
+//! only works after switching to a bootloader that passes framebuffer information, like the
+//! `framebuffer` field of the boot information of bootloader 0.11. That information is then
+//! converted to a `FrameBufferInfo`.
+
+use crate::console::Console;
+
+/// The width and height of a character of the font, in pixels
+pub const FONT_SIZE: usize = 8;
+
+/// The first character in the font
+const FONT_FIRST_CHAR: u8 = 0x20;
+
+/// An 8x8 bitmap font for printable ASCII (0x20 - 0x7e), from the public domain font8x8.
+/// Every glyph is 8 rows, top row first, with the least significant bit as leftmost pixel.
+#[rustfmt::skip]
+const FONT: [[u8; FONT_SIZE]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7f, 0x36, 0x7f, 0x36, 0x36, 0x00], // '#'
+    [0x0c, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x0c, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0c, 0x66, 0x63, 0x00], // '%'
+    [0x1c, 0x36, 0x1c, 0x6e, 0x3b, 0x33, 0x6e, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0c, 0x06, 0x06, 0x06, 0x0c, 0x18, 0x00], // '('
+    [0x06, 0x0c, 0x18, 0x18, 0x18, 0x0c, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0c, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3e, 0x63, 0x73, 0x7b, 0x6f, 0x67, 0x3e, 0x00], // '0'
+    [0x0c, 0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x3f, 0x00], // '1'
+    [0x1e, 0x33, 0x30, 0x1c, 0x06, 0x33, 0x3f, 0x00], // '2'
+    [0x1e, 0x33, 0x30, 0x1c, 0x30, 0x33, 0x1e, 0x00], // '3'
+    [0x38, 0x3c, 0x36, 0x33, 0x7f, 0x30, 0x78, 0x00], // '4'
+    [0x3f, 0x03, 0x1f, 0x30, 0x30, 0x33, 0x1e, 0x00], // '5'
+    [0x1c, 0x06, 0x03, 0x1f, 0x33, 0x33, 0x1e, 0x00], // '6'
+    [0x3f, 0x33, 0x30, 0x18, 0x0c, 0x0c, 0x0c, 0x00], // '7'
+    [0x1e, 0x33, 0x33, 0x1e, 0x33, 0x33, 0x1e, 0x00], // '8'
+    [0x1e, 0x33, 0x33, 0x3e, 0x30, 0x18, 0x0e, 0x00], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ';'
+    [0x18, 0x0c, 0x06, 0x03, 0x06, 0x0c, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3f, 0x00, 0x00, 0x3f, 0x00, 0x00], // '='
+    [0x06, 0x0c, 0x18, 0x30, 0x18, 0x0c, 0x06, 0x00], // '>'
+    [0x1e, 0x33, 0x30, 0x18, 0x0c, 0x00, 0x0c, 0x00], // '?'
+    [0x3e, 0x63, 0x7b, 0x7b, 0x7b, 0x03, 0x1e, 0x00], // '@'
+    [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00], // 'A'
+    [0x3f, 0x66, 0x66, 0x3e, 0x66, 0x66, 0x3f, 0x00], // 'B'
+    [0x3c, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3c, 0x00], // 'C'
+    [0x1f, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1f, 0x00], // 'D'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x46, 0x7f, 0x00], // 'E'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x06, 0x0f, 0x00], // 'F'
+    [0x3c, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7c, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1e, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0f, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7f, 0x00], // 'L'
+    [0x63, 0x77, 0x7f, 0x7f, 0x6b, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1c, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1c, 0x00], // 'O'
+    [0x3f, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x0f, 0x00], // 'P'
+    [0x1e, 0x33, 0x33, 0x33, 0x3b, 0x1e, 0x38, 0x00], // 'Q'
+    [0x3f, 0x66, 0x66, 0x3e, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1e, 0x33, 0x07, 0x0e, 0x38, 0x33, 0x1e, 0x00], // 'S'
+    [0x3f, 0x2d, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6b, 0x7f, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1c, 0x1c, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1e, 0x0c, 0x0c, 0x1e, 0x00], // 'Y'
+    [0x7f, 0x63, 0x31, 0x18, 0x4c, 0x66, 0x7f, 0x00], // 'Z'
+    [0x1e, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1e, 0x00], // '['
+    [0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1e, 0x00], // ']'
+    [0x08, 0x1c, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff], // '_'
+    [0x0c, 0x0c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x3b, 0x00], // 'b'
+    [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6e, 0x00], // 'd'
+    [0x00, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // 'e'
+    [0x1c, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0f, 0x00], // 'f'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'g'
+    [0x07, 0x06, 0x36, 0x6e, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0c, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1e, 0x36, 0x67, 0x00], // 'k'
+    [0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7f, 0x7f, 0x6b, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // 'o'
+    [0x00, 0x00, 0x3b, 0x66, 0x66, 0x3e, 0x06, 0x0f], // 'p'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3b, 0x6e, 0x66, 0x06, 0x0f, 0x00], // 'r'
+    [0x00, 0x00, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x00], // 's'
+    [0x08, 0x0c, 0x3e, 0x0c, 0x0c, 0x2c, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6b, 0x7f, 0x7f, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1c, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'y'
+    [0x00, 0x00, 0x3f, 0x19, 0x0c, 0x26, 0x3f, 0x00], // 'z'
+    [0x38, 0x0c, 0x0c, 0x07, 0x0c, 0x0c, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0c, 0x0c, 0x38, 0x0c, 0x0c, 0x07, 0x00], // '}'
+    [0x6e, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+/// The glyph drawn for characters that aren't in the font, a filled box
+const REPLACEMENT_GLYPH: [u8; FONT_SIZE] = [0x00, 0x7e, 0x7e, 0x7e, 0x7e, 0x7e, 0x7e, 0x00];
+
+/// The order of the color bytes of a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red, green, blue, optionally followed by a padding byte
+    Rgb,
+    /// Blue, green, red, optionally followed by a padding byte
+    Bgr,
+    /// A single grayscale byte
+    U8,
+}
+
+/// The layout of a framebuffer, as described by the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBufferInfo {
+    /// The width of the visible area in pixels
+    pub width: usize,
+    /// The height of the visible area in pixels
+    pub height: usize,
+    /// The number of pixels per row in memory, at least the width
+    pub stride: usize,
+    /// The number of bytes of a single pixel
+    pub bytes_per_pixel: usize,
+    /// The order of the color bytes of a pixel
+    pub pixel_format: PixelFormat,
+}
+
+/// A color, with 8 bits per channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(0xff, 0xff, 0xff);
+
+    /// Creates a color
+    ///
+    /// # Arguments
+    /// ```r```: the intensity of red
+    /// ```g```: the intensity of green
+    /// ```b```: the intensity of blue
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+}
+
+/// Draws on a linear framebuffer
+pub struct FrameBuffer {
+    buffer: &'static mut [u8],
+    info: FrameBufferInfo,
+}
+
+impl FrameBuffer {
+    /// Creates a framebuffer
+    ///
+    /// # Arguments
+    /// ```buffer```: the memory of the framebuffer
+    /// ```info```: the layout of the framebuffer
+    ///
+    /// # Panics
+    /// If the buffer is too small for the layout
+    pub fn new(buffer: &'static mut [u8], info: FrameBufferInfo) -> FrameBuffer {
+        assert!(
+            info.stride >= info.width
+                && buffer.len() >= info.stride * info.height * info.bytes_per_pixel,
+            "The framebuffer is too small for its layout"
+        );
+        assert!(
+            info.bytes_per_pixel >= usize::from(info.pixel_format != PixelFormat::U8) * 2 + 1,
+            "The pixels are too small for their format"
+        );
+        FrameBuffer { buffer, info }
+    }
+
+    /// Returns the layout of the framebuffer
+    pub fn info(&self) -> FrameBufferInfo {
+        self.info
+    }
+
+    /// Sets the color of a pixel, pixels outside of the visible area are ignored
+    ///
+    /// # Arguments
+    /// ```x```: the column of the pixel, from the left
+    /// ```y```: the row of the pixel, from the top
+    /// ```rgb```: the color of the pixel
+    pub fn put_pixel(&mut self, x: usize, y: usize, rgb: Rgb) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+
+        let offset = (y * self.info.stride + x) * self.info.bytes_per_pixel;
+        let gray;
+        let bytes: &[u8] = match self.info.pixel_format {
+            PixelFormat::Rgb => &[rgb.r, rgb.g, rgb.b],
+            PixelFormat::Bgr => &[rgb.b, rgb.g, rgb.r],
+            PixelFormat::U8 => {
+                // Weigh the channels by how bright they look
+                gray = ((u16::from(rgb.r) * 77 + u16::from(rgb.g) * 150 + u16::from(rgb.b) * 29)
+                    >> 8) as u8;
+                core::slice::from_ref(&gray)
+            }
+        };
+
+        // The framebuffer is memory mapped, so write it volatile like the VGA buffer
+        for (i, &byte) in bytes.iter().enumerate() {
+            unsafe { core::ptr::write_volatile(&mut self.buffer[offset + i], byte) };
+        }
+    }
+
+    /// Fills the visible area with a color
+    ///
+    /// # Arguments
+    /// ```rgb```: the color to fill with
+    pub fn clear(&mut self, rgb: Rgb) {
+        for y in 0..self.info.height {
+            for x in 0..self.info.width {
+                self.put_pixel(x, y, rgb);
+            }
+        }
+    }
+
+    /// Draws a character of the 8x8 font, clipped at the edges of the visible area
+    ///
+    /// # Arguments
+    /// ```x```: the column of the top left pixel of the character
+    /// ```y```: the row of the top left pixel of the character
+    /// ```c```: the character to draw, characters outside of printable ASCII are drawn as a box
+    /// ```fg```: the color of the character
+    /// ```bg```: the color of the background of the character
+    pub fn draw_char(&mut self, x: usize, y: usize, c: char, fg: Rgb, bg: Rgb) {
+        let glyph = u8::try_from(c)
+            .ok()
+            .and_then(|byte| FONT.get(usize::from(byte.checked_sub(FONT_FIRST_CHAR)?)))
+            .unwrap_or(&REPLACEMENT_GLYPH);
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..FONT_SIZE {
+                let color = if bits & (1 << col) != 0 { fg } else { bg };
+                self.put_pixel(x + col, y + row, color);
+            }
+        }
+    }
+
+    /// Draws a string of the 8x8 font, starting a new line on `\n`. Characters past the right
+    /// edge are clipped.
+    ///
+    /// # Arguments
+    /// ```x```: the column of the top left pixel of the first character
+    /// ```y```: the row of the top left pixel of the first character
+    /// ```s```: the string to draw
+    /// ```fg```: the color of the characters
+    /// ```bg```: the color of the background of the characters
+    pub fn draw_string(&mut self, x: usize, y: usize, s: &str, fg: Rgb, bg: Rgb) {
+        for (line, text) in s.split('\n').enumerate() {
+            for (i, c) in text.chars().enumerate() {
+                self.draw_char(x + i * FONT_SIZE, y + line * FONT_SIZE, c, fg, bg);
+            }
+        }
+    }
+}
+
+/// A text console on a framebuffer, which can be selected with `console::select`
+pub struct FrameBufferConsole {
+    text: spin::Mutex<TextCursor>,
+}
+
+/// The framebuffer of a console, with the position of the next character in characters
+struct TextCursor {
+    frame_buffer: FrameBuffer,
+    row: usize,
+    column: usize,
+}
+
+impl FrameBufferConsole {
+    /// Creates a console, clearing the framebuffer
+    ///
+    /// # Arguments
+    /// ```frame_buffer```: the framebuffer to draw the text on
+    pub fn new(mut frame_buffer: FrameBuffer) -> FrameBufferConsole {
+        frame_buffer.clear(Rgb::BLACK);
+        FrameBufferConsole {
+            text: spin::Mutex::new(TextCursor {
+                frame_buffer,
+                row: 0,
+                column: 0,
+            }),
+        }
+    }
+}
+
+impl Console for FrameBufferConsole {
+    fn write_str(&self, s: &str) {
+        let mut text = self.text.lock();
+        for c in s.chars() {
+            text.write_char(c);
+        }
+    }
+}
+
+impl TextCursor {
+    /// Draws a character at the cursor, wrapping at the right edge and scrolling at the bottom
+    ///
+    /// # Arguments
+    /// ```c```: the character to draw, `\n` starts a new line
+    fn write_char(&mut self, c: char) {
+        let info = self.frame_buffer.info();
+        if c == '\n' || self.column >= info.width / FONT_SIZE {
+            self.new_line();
+        }
+        if c != '\n' {
+            let (x, y) = (self.column * FONT_SIZE, self.row * FONT_SIZE);
+            self.frame_buffer.draw_char(x, y, c, Rgb::WHITE, Rgb::BLACK);
+            self.column += 1;
+        }
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling up a line at the bottom
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.frame_buffer.info().height / FONT_SIZE {
+            self.row += 1;
+            return;
+        }
+
+        let info = self.frame_buffer.info();
+        let line_bytes = info.stride * FONT_SIZE * info.bytes_per_pixel;
+        let text_bytes = self.row * line_bytes;
+        self.frame_buffer
+            .buffer
+            .copy_within(line_bytes..line_bytes + text_bytes, 0);
+        for y in self.row * FONT_SIZE..(self.row + 1) * FONT_SIZE {
+            for x in 0..info.width {
+                self.frame_buffer.put_pixel(x, y, Rgb::BLACK);
+            }
+        }
+    }
+}
+
+/// Creates a framebuffer in normal memory, with 4 padding pixels per row
+#[cfg(test)]
+fn test_frame_buffer(pixel_format: PixelFormat) -> FrameBuffer {
+    const WIDTH: usize = 16;
+    const STRIDE: usize = 20;
+    const HEIGHT: usize = 8;
+    static mut TEST_BUFFER: [u8; STRIDE * HEIGHT * 4] = [0; STRIDE * HEIGHT * 4];
+
+    // Every test starts by clearing the buffer, so reusing it is fine
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(TEST_BUFFER) };
+    buffer.fill(0);
+    FrameBuffer::new(
+        buffer,
+        FrameBufferInfo {
+            width: WIDTH,
+            height: HEIGHT,
+            stride: STRIDE,
+            bytes_per_pixel: 4,
+            pixel_format,
+        },
+    )
+}
+
+/// Checks whether the color bytes of a pixel are written in the order of the pixel format
+#[test_case]
+fn test_put_pixel_formats() {
+    let color = Rgb::new(1, 2, 3);
+
+    let mut frame_buffer = test_frame_buffer(PixelFormat::Rgb);
+    frame_buffer.put_pixel(1, 1, color);
+    assert_eq!(frame_buffer.buffer[(20 + 1) * 4..][..3], [1, 2, 3]);
+
+    let mut frame_buffer = test_frame_buffer(PixelFormat::Bgr);
+    frame_buffer.put_pixel(1, 1, color);
+    assert_eq!(frame_buffer.buffer[(20 + 1) * 4..][..3], [3, 2, 1]);
+
+    // Pixels in the padding of a row aren't visible, so they're ignored
+    frame_buffer.put_pixel(16, 0, color);
+    assert!(frame_buffer.buffer[16 * 4..20 * 4]
+        .iter()
+        .all(|&byte| byte == 0));
+}
+
+/// Checks whether the console scrolls up once the bottom line is full
+#[test_case]
+fn test_console_scrolls() {
+    let console = FrameBufferConsole::new(test_frame_buffer(PixelFormat::Rgb));
+
+    // The framebuffer fits a single line of 2 characters
+    console.write_str("ABC");
+    let text = console.text.lock();
+    assert_eq!((text.row, text.column), (0, 1));
+
+    // 'A' and 'B' scrolled out, and 'C' starts the line again. The top row of 'C' is 0x3c.
+    let pixel = |x: usize, y: usize| text.frame_buffer.buffer[(y * 20 + x) * 4];
+    assert_eq!(pixel(0, 0), 0);
+    assert_eq!(pixel(2, 0), 0xff);
+    assert_eq!(pixel(FONT_SIZE, 0), 0);
+}
+
+/// Checks whether characters are drawn with the pixels of their glyph
+#[test_case]
+fn test_draw_string() {
+    let mut frame_buffer = test_frame_buffer(PixelFormat::Rgb);
+    frame_buffer.draw_string(0, 0, "AB", Rgb::WHITE, Rgb::BLACK);
+
+    // The top row of 'A' is 0x0c, so only its pixels 2 and 3 are set
+    let pixel = |x: usize, y: usize| frame_buffer.buffer[(y * 20 + x) * 4];
+    assert_eq!(
+        [pixel(1, 0), pixel(2, 0), pixel(3, 0), pixel(4, 0)],
+        [0, 0xff, 0xff, 0]
+    );
+
+    // The top row of 'B' is 0x3f, so its pixels 0 up to 5 are set
+    assert_eq!(pixel(FONT_SIZE, 0), 0xff);
+    assert_eq!(pixel(FONT_SIZE + 5, 0), 0xff);
+    assert_eq!(pixel(FONT_SIZE + 6, 0), 0);
+}
@@ -0,0 +1,139 @@
+//! A minimal file system interface, with a file system stored in memory.
+//! Paths are absolute and separated by `/`, like `/etc/motd`. Directories only exist implicitly,
+//! as the common prefix of the files in them.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// An open file, which keeps track of the position to read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHandle {
+    path: String,
+    offset: usize,
+}
+
+impl FileHandle {
+    /// Returns the path of the file
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// A file system which files can be read from
+pub trait FileSystem {
+    /// Opens a file for reading
+    ///
+    /// # Arguments
+    /// ```path```: the absolute path of the file
+    ///
+    /// # Returns
+    /// A handle to the file, positioned at its start, or None if the file doesn't exist
+    fn open(&self, path: &str) -> Option<FileHandle>;
+
+    /// Reads from a file, starting where the previous read stopped
+    ///
+    /// # Arguments
+    /// ```handle```: the file to read from
+    /// ```buf```: the buffer to read into
+    ///
+    /// # Returns
+    /// The number of bytes read, 0 at the end of the file
+    fn read(&self, handle: &mut FileHandle, buf: &mut [u8]) -> usize;
+
+    /// Lists the files and directories directly inside a directory
+    ///
+    /// # Arguments
+    /// ```dir```: the absolute path of the directory
+    ///
+    /// # Returns
+    /// The names of the entries, sorted and without duplicates
+    fn list(&self, dir: &str) -> Vec<&str>;
+}
+
+/// A file system stored on the heap
+#[derive(Debug, Default)]
+pub struct RamFs {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl RamFs {
+    /// Creates an empty file system
+    pub const fn new() -> Self {
+        RamFs {
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a file, replacing the file at the same path if there is one
+    ///
+    /// # Arguments
+    /// ```path```: the absolute path of the file
+    /// ```bytes```: the contents of the file
+    pub fn insert(&mut self, path: &str, bytes: impl Into<Vec<u8>>) {
+        self.files.insert(String::from(path), bytes.into());
+    }
+}
+
+impl FileSystem for RamFs {
+    fn open(&self, path: &str) -> Option<FileHandle> {
+        self.files.contains_key(path).then(|| FileHandle {
+            path: String::from(path),
+            offset: 0,
+        })
+    }
+
+    fn read(&self, handle: &mut FileHandle, buf: &mut [u8]) -> usize {
+        // A file removed while open reads as empty
+        let Some(contents) = self.files.get(&handle.path) else {
+            return 0;
+        };
+
+        let remaining = contents.get(handle.offset..).unwrap_or_default();
+        let len = remaining.len().min(buf.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        handle.offset += len;
+        len
+    }
+
+    fn list(&self, dir: &str) -> Vec<&str> {
+        let dir = dir.trim_end_matches('/');
+        let mut entries: Vec<&str> = self
+            .files
+            .keys()
+            .filter_map(|path| path.strip_prefix(dir)?.strip_prefix('/'))
+            // Only keep the first component, so files in subdirectories show their directory
+            .filter_map(|relative| relative.split('/').next())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        // Files in the same subdirectory result in the same entry
+        entries.sort_unstable();
+        entries.dedup();
+        entries
+    }
+}
+
+/// Checks whether inserted files can be listed and read back
+#[test_case]
+fn test_ram_fs() {
+    let mut fs = RamFs::new();
+    fs.insert("/etc/motd", "Welcome to blog_os\n");
+    fs.insert("/etc/hostname", "blog_os");
+    fs.insert("/etc/init/rc", "");
+
+    assert_eq!(fs.list("/etc"), ["hostname", "init", "motd"]);
+    assert_eq!(fs.list("/"), ["etc"]);
+    assert_eq!(fs.open("/etc/missing"), None);
+
+    // Read in pieces smaller than the file, to check the offset is kept
+    let mut handle = fs.open("/etc/motd").expect("File not found");
+    let mut contents = Vec::new();
+    let mut buf = [0; 8];
+    loop {
+        let len = fs.read(&mut handle, &mut buf);
+        if len == 0 {
+            break;
+        }
+        contents.extend_from_slice(&buf[..len]);
+    }
+    assert_eq!(contents, b"Welcome to blog_os\n");
+}
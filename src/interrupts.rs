@@ -1,8 +1,10 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
-use crate::{gdt, hlt_loop, println};
+use crate::{apic, gdt, hlt_loop, println};
 
 // The offsets at which to receive interrupts from the Programmable Interrupt Controllers.
 // The usual range is 32 - 47 as 0 - 31 are used for exceptions.
@@ -13,11 +15,18 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 1;
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+// Set while a page fault is being reported, to detect page faults caused by the reporting itself
+static IN_PAGE_FAULT: AtomicBool = AtomicBool::new(false);
+
+// The number of timer interrupts since boot
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Serial1 = PIC_1_OFFSET + 4,
 }
 
 impl InterruptIndex {
@@ -51,9 +60,17 @@ lazy_static! {
         idt[InterruptIndex::Keyboard.as_usize()]
             .set_handler_fn(keyboard_interrupt_handler);
 
+        // Set an interrupt for data received on the first serial port
+        idt[InterruptIndex::Serial1.as_usize()]
+            .set_handler_fn(serial_interrupt_handler);
+
         // Set a page fault handler
         idt.page_fault.set_handler_fn(page_fault_handler);
 
+        // Set a handler for spurious interrupts of the local APIC
+        idt[usize::from(apic::SPURIOUS_VECTOR)]
+            .set_handler_fn(spurious_interrupt_handler);
+
         idt
     };
 }
@@ -62,6 +79,86 @@ pub fn init_idt() {
     IDT.load();
 }
 
+/// Unmasks the interrupt of the first serial port (IRQ 4) on the primary PIC, which the firmware
+/// leaves masked
+pub fn enable_serial_interrupts() {
+    use crate::port::{self, PIC1_DATA};
+
+    // Unsafe as unmasking an interrupt without handler would cause a double fault
+    unsafe {
+        let masks = port::read_u8(PIC1_DATA);
+        port::write_u8(PIC1_DATA, masks & !(1 << 4));
+    }
+}
+
+/// Checks whether the loaded IDT has a handler for every exception and interrupt the kernel
+/// handles, to catch wiring regressions before they turn into triple faults
+///
+/// # Panics
+/// If an entry is missing, or the double fault handler doesn't run on its own stack
+pub fn verify_idt() {
+    let pointer = x86_64::instructions::tables::sidt();
+    assert_eq!(
+        usize::from(pointer.limit) + 1,
+        core::mem::size_of::<InterruptDescriptorTable>(),
+        "The loaded IDT has an unexpected size"
+    );
+
+    // Safe, as the IDT register points to a complete table, as checked above
+    let idt = unsafe { &*pointer.base.as_ptr::<InterruptDescriptorTable>() };
+
+    assert_ne!(
+        idt.breakpoint.handler_addr().as_u64(),
+        0,
+        "Breakpoint handler missing"
+    );
+    assert_ne!(
+        idt.double_fault.handler_addr().as_u64(),
+        0,
+        "Double fault handler missing"
+    );
+    assert_ne!(
+        idt.page_fault.handler_addr().as_u64(),
+        0,
+        "Page fault handler missing"
+    );
+    assert_ne!(
+        idt[InterruptIndex::Timer.as_usize()]
+            .handler_addr()
+            .as_u64(),
+        0,
+        "Timer handler missing"
+    );
+    assert_ne!(
+        idt[InterruptIndex::Serial1.as_usize()]
+            .handler_addr()
+            .as_u64(),
+        0,
+        "Serial handler missing"
+    );
+    assert_ne!(
+        idt[InterruptIndex::Keyboard.as_usize()]
+            .handler_addr()
+            .as_u64(),
+        0,
+        "Keyboard handler missing"
+    );
+
+    // The entry options don't expose the stack index, so read it from the raw entry instead.
+    // The options are the third 16-bit word of an entry, bits 0-2 store the IST index + 1.
+    let options = unsafe {
+        core::ptr::addr_of!(idt.double_fault)
+            .cast::<u16>()
+            .add(2)
+            .read()
+    };
+    assert_eq!(
+        options & 0b111,
+        gdt::DOUBLE_FAULT_IST_INDEX + 1,
+        "Double fault handler doesn't use the double fault stack"
+    );
+}
+
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
@@ -79,48 +176,147 @@ extern "x86-interrupt" fn page_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode, // Provides more information about the type of memory access
 ) {
+    report_page_fault(&stack_frame, error_code);
+
+    // Halt execution as execution can't continue before the page fault is handled
+    hlt_loop();
+}
+
+/// Disables interrupts while it's alive, restoring the previous state when it's dropped.
+/// Unlike `without_interrupts`, early returns and `?` can't skip re-enabling interrupts.
+pub struct InterruptGuard {
+    // Whether interrupts were enabled when the guard was created
+    were_enabled: bool,
+}
+
+impl InterruptGuard {
+    /// Disables interrupts, remembering whether they were enabled
+    #[must_use = "interrupts are enabled again as soon as the guard is dropped"]
+    pub fn new() -> Self {
+        use x86_64::instructions::interrupts;
+
+        let were_enabled = interrupts::are_enabled();
+        if were_enabled {
+            interrupts::disable();
+        }
+        InterruptGuard { were_enabled }
+    }
+}
+
+impl Default for InterruptGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for InterruptGuard {
+    /// Enables interrupts again, if they were enabled when the guard was created
+    fn drop(&mut self) {
+        if self.were_enabled {
+            x86_64::instructions::interrupts::enable();
+        }
+    }
+}
+
+/// Disables interrupts until the returned guard is dropped
+///
+/// # Returns
+/// The guard, which restores the previous interrupt state when it's dropped
+#[must_use = "interrupts are enabled again as soon as the guard is dropped"]
+pub fn without_interrupts_guard() -> InterruptGuard {
+    InterruptGuard::new()
+}
+
+/// Prints the information of a page fault to the screen
+///
+/// If a page fault occurs while reporting another one, a short message is written directly to
+/// the VGA buffer and execution is halted, instead of recursing until the CPU triple faults.
+///
+/// # Arguments
+/// ```stack_frame```: the stack frame of the faulting code
+/// ```error_code```: the type of memory access that caused the page fault
+pub fn report_page_fault(stack_frame: &InterruptStackFrame, error_code: PageFaultErrorCode) {
     // CR2 is set by the CPU on a page fault and contains the accessed virtual address that caused
     // the page fault.
     use x86_64::registers::control::Cr2;
 
+    if IN_PAGE_FAULT.swap(true, Ordering::SeqCst) {
+        // Bypass the WRITER, as it may be what caused the nested page fault
+        crate::vga_buffer::emergency_print("EXCEPTION: NESTED PAGE FAULT");
+        hlt_loop();
+    }
+
     println!("EXCEPTION: PAGE FAULT");
     // Use CR2::read to read the accessed virtual address
     println!("Accessed Address: {:?}", Cr2::read());
     println!("Error Code: {error_code:?}");
     println!("{stack_frame:#?}");
 
-    // Halt execution as execution can't continue before the page fault is handled
-    hlt_loop();
+    IN_PAGE_FAULT.store(false, Ordering::SeqCst);
+}
+
+/// Returns whether a page fault is currently being reported
+pub fn in_page_fault() -> bool {
+    IN_PAGE_FAULT.load(Ordering::SeqCst)
+}
+
+/// Returns the number of timer interrupts since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
     print!(".");
+    crate::time::wake_sleepers();
+
+    if apic::is_enabled() {
+        // The timer interrupt came from the local APIC, which needs its own end of interrupt
+        apic::end_of_interrupt();
+    } else {
+        // Notify the PIC that a interrupt has been handled, to receive the next interrupt.
+        // Unsafe as sending the wrong interrupt vector number, could delete an important unsent
+        // interrupt or cause the system to hang.
+        unsafe {
+            PICS.lock()
+                .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+        }
+    }
+}
+
+// Spurious interrupts don't need an end of interrupt, so they can simply be ignored
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // Read the scancode from the PS/2 data port
+    let scancode = unsafe { crate::port::read_u8(crate::port::PS2_DATA) };
+    crate::task::keyboard::add_scancode(scancode);
 
     // Notify the PIC that a interrupt has been handled, to receive the next interrupt.
     // Unsafe as sending the wrong interrupt vector number, could delete an important unsent
     // interrupt or cause the system to hang.
     unsafe {
         PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
     }
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use x86_64::instructions::port::Port;
-
-    // Create a port with code 0x60 (6 * 16 = 3 * 32 = 96)
-    let mut port = Port::new(0x60);
+extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    use crate::port::{self, COM1, COM1_LINE_STATUS};
 
-    // Read the scancode
-    let scancode: u8 = unsafe { port.read() };
-    crate::task::keyboard::add_scancode(scancode);
+    // Read every received byte, as long as the data ready bit of the line status is set.
+    // The port is accessed directly, as SERIAL1 may be locked by the interrupted code.
+    while unsafe { port::read_u8(COM1_LINE_STATUS) } & 0x01 != 0 {
+        let byte = unsafe { port::read_u8(COM1) };
+        crate::serial::add_received_byte(byte);
+    }
 
     // Notify the PIC that a interrupt has been handled, to receive the next interrupt.
     // Unsafe as sending the wrong interrupt vector number, could delete an important unsent
     // interrupt or cause the system to hang.
     unsafe {
         PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+            .notify_end_of_interrupt(InterruptIndex::Serial1.as_u8());
     }
 }
 
@@ -129,3 +325,27 @@ fn test_breakpoint_exception() {
     // invoke a breakpoint exception
     x86_64::instructions::interrupts::int3();
 }
+
+/// Checks whether the interrupt guard disables interrupts and restores them when it's dropped
+#[test_case]
+fn test_interrupt_guard_restores_state() {
+    use x86_64::instructions::interrupts;
+
+    assert!(interrupts::are_enabled());
+    {
+        let _guard = without_interrupts_guard();
+        assert!(!interrupts::are_enabled());
+        {
+            // A nested guard mustn't enable interrupts when it's dropped
+            let _nested = InterruptGuard::new();
+        }
+        assert!(!interrupts::are_enabled());
+    }
+    assert!(interrupts::are_enabled());
+}
+
+/// Checks whether every handler is wired up in the IDT loaded by init
+#[test_case]
+fn test_verify_idt() {
+    verify_idt();
+}
@@ -7,15 +7,38 @@
 #[macro_use]
 pub mod vga_buffer;
 pub mod allocator;
+pub mod apic;
+pub mod backtrace;
+pub mod cmdline;
+pub mod console;
+pub mod cpu;
+pub mod debug;
+pub mod framebuffer;
+pub mod fs;
 pub mod gdt; // Global Descriptor table
 pub mod interrupts;
+pub mod logger;
 pub mod memory;
+pub mod port;
+pub mod power;
+pub mod rand;
 pub mod serial;
+pub mod serial_cmd;
+pub mod shell;
 pub mod task;
+pub mod time;
 
 extern crate alloc;
 
-use core::panic::PanicInfo;
+use core::{fmt, panic::PanicInfo};
+
+use bootloader::BootInfo;
+use x86_64::{
+    structures::paging::{mapper::MapToError, OffsetPageTable, Size4KiB},
+    VirtAddr,
+};
+
+use memory::BootInfoFrameAllocator;
 
 /// This function is called on panic, when testing
 ///
@@ -43,14 +66,8 @@ pub enum QemuExitCode {
 /// # Arguments
 /// ```exit_code```: the exit code to use
 pub fn exit_qemu(exit_code: QemuExitCode) {
-    // make the Port struct easily accessible
-    use x86_64::instructions::port::Port;
-
-    unsafe {
-        // Open a port on address 0xf4, and write the exit code to it
-        let mut port = Port::new(0xf4);
-        port.write(exit_code as u32);
-    }
+    // Write the exit code to the isa-debug-exit device
+    unsafe { port::write_u32(port::QEMU_EXIT, exit_code as u32) };
 }
 
 /// A trait which adds test information
@@ -60,11 +77,14 @@ pub trait Testable {
 
 /// implement the testable trait for functions
 impl<T: Fn()> Testable for T {
-    /// Runs the function with test information
+    /// Runs the function with test information, and the number of time stamp counter cycles
+    /// it took
     fn run(&self) {
         serial_print!("{}...\t", core::any::type_name::<T>());
+        let start = time::rdtsc();
         self();
-        serial_println!("[ok]");
+        let cycles = time::rdtsc().wrapping_sub(start);
+        serial_println!("[ok] ({} cycles)", cycles);
     }
 }
 
@@ -73,6 +93,9 @@ impl<T: Fn()> Testable for T {
 /// # Arguments
 /// An array slice containing functions
 pub fn test_runner(tests: &[&dyn Testable]) {
+    // The results are only reported over serial, so don't drop them silently
+    serial::set_strict(true);
+
     // print the number of tests to run
     serial_println!("Running {} tests", tests.len());
 
@@ -84,6 +107,10 @@ pub fn test_runner(tests: &[&dyn Testable]) {
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    // The test may have panicked while printing over serial.
+    // Safe, as the test stopped running.
+    unsafe { serial::force_unlock() };
+
     serial_println!("[failed]");
     serial_println!("Error: {}\n", info);
     exit_qemu(QemuExitCode::Failed);
@@ -96,16 +123,192 @@ fn trivial_assertion() {
     assert_eq!(1, 1);
 }
 
-pub fn init() {
+/// A writer whose every write fails, for the tests of failed writes
+#[cfg(test)]
+pub(crate) struct FailingWriter;
+
+#[cfg(test)]
+impl fmt::Write for FailingWriter {
+    fn write_str(&mut self, _s: &str) -> fmt::Result {
+        Err(fmt::Error)
+    }
+}
+
+/// Keeps the CPU busy, so the runner reports a clearly nonzero cycle count, and checks whether
+/// the time stamp counter keeps increasing while doing so
+#[test_case]
+fn busy_test_cycles() {
+    let mut previous = time::rdtsc();
+    for i in 0..1000u64 {
+        core::hint::black_box(i.wrapping_mul(i));
+        let now = time::rdtsc();
+        assert!(now > previous);
+        previous = now;
+    }
+}
+
+/// Initializes the CPU tables and interrupts, the memory management, and the heap
+///
+/// # Arguments
+/// ```boot_info```: the information passed by the bootloader
+///
+/// # Returns
+/// The page table and frame allocator
+///
+/// # Panics
+/// If a step of the initialization fails, see `try_init`
+pub fn init(boot_info: &'static BootInfo) -> KernelResources {
+    try_init(boot_info).unwrap_or_else(|error| panic!("Kernel initialization failed: {}", error))
+}
+
+/// Sets up the CPU tables and interrupts, and applies the settings from the command line
+///
+/// # Arguments
+/// ```trace```: where to write a progress marker after every step, see `trace_init`
+///
+/// # Returns
+/// An error if the CPU lacks a feature the kernel needs
+fn init_cpu(trace: &mut impl fmt::Write) -> Result<(), InitError> {
     interrupts::init_idt();
+    trace_init(trace, "idt ok");
     gdt::init();
+    trace_init(trace, "gdt ok");
+
+    // The kernel saves and uses the SSE registers, which needs FXSAVE as well
+    let features = cpu::CpuFeatures::detect();
+    if !features.sse {
+        return Err(InitError::MissingCpuFeature("SSE"));
+    }
+    if !features.fxsr {
+        return Err(InitError::MissingCpuFeature("FXSAVE/FXRSTOR"));
+    }
+    cpu::enable_sse();
 
     // Initialize the PICs.
     // Unsafe as it can cause undefined behavior if the PIC is misconfigured
     unsafe { interrupts::PICS.lock().initialize() };
+    interrupts::enable_serial_interrupts();
+    trace_init(trace, "pics ok");
 
     // Enable interrupts on the CPU
     x86_64::instructions::interrupts::enable();
+    trace_init(trace, "interrupts enabled");
+
+    // Apply the settings from the kernel command line
+    logger::init();
+    task::keyboard::init_layout();
+    Ok(())
+}
+
+/// Writes a progress marker of `init`. A fault before the IDT is complete resets the machine
+/// without any message, so the last marker shows which step faulted.
+///
+/// # Arguments
+/// ```trace```: where to write the marker to
+/// ```marker```: the step that completed
+fn trace_init(trace: &mut impl fmt::Write, marker: &str) {
+    // Tracing is best-effort, so a failed write is ignored
+    let _ = writeln!(trace, "{}", marker);
+}
+
+/// Prints the progress markers of `init` over serial with the `init-trace` feature, and drops
+/// them otherwise
+struct InitTrace;
+
+impl fmt::Write for InitTrace {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        #[cfg(feature = "init-trace")]
+        serial_print!("{}", s);
+        #[cfg(not(feature = "init-trace"))]
+        let _ = s;
+        Ok(())
+    }
+}
+
+/// The memory management state set up by `try_init`, needed to map more memory later
+pub struct KernelResources {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: BootInfoFrameAllocator,
+}
+
+/// The step of the kernel initialization that failed
+#[derive(Debug)]
+pub enum InitError {
+    /// The CPU lacks a feature the kernel needs
+    MissingCpuFeature(&'static str),
+    /// The bootloader didn't map the physical memory
+    PhysicalMemoryNotMapped,
+    /// The memory map doesn't contain any usable memory
+    NoUsableMemory,
+    /// The heap couldn't be mapped
+    Heap(MapToError<Size4KiB>),
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InitError::MissingCpuFeature(feature) => write!(f, "cpu: {} isn't supported", feature),
+            InitError::PhysicalMemoryNotMapped => {
+                write!(f, "memory: the physical memory isn't mapped")
+            }
+            InitError::NoUsableMemory => write!(f, "memory: no usable memory"),
+            InitError::Heap(error) => write!(f, "heap: {:?}", error),
+        }
+    }
+}
+
+/// Initializes the CPU tables and interrupts, the memory management, and the heap
+///
+/// Unlike `init`, this reports which step failed instead of panicking.
+///
+/// # Arguments
+/// ```boot_info```: the information passed by the bootloader
+///
+/// # Returns
+/// The page table and frame allocator, or the step that failed
+pub fn try_init(boot_info: &'static BootInfo) -> Result<KernelResources, InitError> {
+    try_init_traced(boot_info, &mut InitTrace)
+}
+
+/// Initializes the kernel like `try_init`, writing a progress marker after every step of the CPU
+/// setup to a writer instead of serial
+///
+/// # Arguments
+/// ```boot_info```: the information passed by the bootloader
+/// ```trace```: where to write the progress markers to, which can't use the heap yet
+///
+/// # Returns
+/// The page table and frame allocator, or the step that failed
+pub fn try_init_traced(
+    boot_info: &'static BootInfo,
+    trace: &mut impl fmt::Write,
+) -> Result<KernelResources, InitError> {
+    init_cpu(trace)?;
+
+    // The bootloader maps the physical memory at a non-zero offset
+    if boot_info.physical_memory_offset == 0 {
+        return Err(InitError::PhysicalMemoryNotMapped);
+    }
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+
+    // No console is selected, so `print!` keeps using the VGA text buffer: bootloader 0.9 always
+    // starts the kernel in VGA text mode, and passes no framebuffer to select a
+    // `framebuffer::FrameBufferConsole` for with `console::select`
+
+    // Safe, as the bootloader mapped the complete physical memory at the offset, and marked only
+    // unused memory as usable
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    if frame_allocator.total_frame_count() == 0 {
+        return Err(InitError::NoUsableMemory);
+    }
+
+    allocator::init_heap(&mut mapper, &mut frame_allocator).map_err(InitError::Heap)?;
+
+    Ok(KernelResources {
+        mapper,
+        frame_allocator,
+    })
 }
 
 /// Blocks for ever, while still allowing interrupts.
@@ -117,14 +320,31 @@ pub fn hlt_loop() -> ! {
 }
 
 #[cfg(test)]
-use bootloader::{entry_point, BootInfo};
+use bootloader::entry_point;
 
 #[cfg(test)]
 entry_point!(test_kernel_main);
 
+// The boot information, stored for tests that need it
 #[cfg(test)]
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
-    init();
+static TEST_BOOT_INFO: conquer_once::spin::OnceCell<&'static BootInfo> =
+    conquer_once::spin::OnceCell::uninit();
+
+/// Returns the boot information passed to the test kernel
+#[cfg(test)]
+pub(crate) fn test_boot_info() -> &'static BootInfo {
+    TEST_BOOT_INFO
+        .get()
+        .expect("Boot information not initialized")
+}
+
+#[cfg(test)]
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    TEST_BOOT_INFO.init_once(|| boot_info);
+
+    // Also initialize the heap, so tests can allocate
+    init(boot_info);
+
     test_main();
     hlt_loop();
 }
@@ -0,0 +1,264 @@
+//! A kernel logger, which writes every line to the screen and over serial.
+//! Once the heap is initialized, the most recent lines are also kept in memory, so they can be
+//! replayed with `dmesg`. Lines logged before the heap is initialized are only printed.
+//! Lines more detailed than the log level, set with `loglevel=` on the kernel command line, are
+//! dropped.
+
+use alloc::{collections::VecDeque, string::String, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use spin::Mutex;
+
+use crate::{
+    allocator,
+    cmdline::{self, CommandLine},
+    console, serial_println,
+    vga_buffer::Role,
+};
+
+/// The number of lines kept in the kernel log buffer
+pub const LOG_CAPACITY: usize = 64;
+
+/// A ring buffer with the most recent log lines
+pub struct LogBuffer {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// Creates an empty log buffer, without allocating
+    ///
+    /// # Arguments
+    /// ```capacity```: the maximum number of lines to keep
+    pub const fn new(capacity: usize) -> Self {
+        LogBuffer {
+            lines: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Appends a line, dropping the oldest line if the buffer is full
+    ///
+    /// # Arguments
+    /// ```line```: the line to append, without a trailing new line
+    pub fn push(&mut self, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+
+    /// Returns the lines, from oldest to newest
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+}
+
+// The kernel log buffer
+static LOG_BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new(LOG_CAPACITY));
+
+/// How important a log line is, from most to least important
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error,
+    Warning,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Looks up a level by its name on the command line
+    ///
+    /// # Arguments
+    /// ```name```: the name of the level, like `warning`
+    ///
+    /// # Returns
+    /// The level, or None if there is no level with the name
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warning" => Some(Level::Warning),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    /// Returns the role lines of this level are shown in on the screen
+    fn role(self) -> Role {
+        match self {
+            Level::Error => Role::Error,
+            Level::Warning => Role::Warning,
+            Level::Info | Level::Debug => Role::Normal,
+        }
+    }
+}
+
+// The most detailed level that is logged
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+
+/// Sets the most detailed level that is logged, more detailed lines are dropped
+///
+/// # Arguments
+/// ```level```: the most detailed level to log
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Sets the log level from `loglevel=` on the kernel command line, if it's there
+pub fn init() {
+    init_from(cmdline::command_line());
+}
+
+/// Sets the log level from `loglevel=` on a command line, if it's there
+///
+/// # Arguments
+/// ```command_line```: the command line with the `loglevel=` argument
+fn init_from(command_line: &CommandLine) {
+    if let Some(name) = command_line.get("loglevel") {
+        match Level::from_name(name) {
+            Some(level) => set_max_level(level),
+            None => crate::warning!("Unknown log level {:?}, keeping the default", name),
+        }
+    }
+}
+
+/// Checks whether lines of a level are logged
+///
+/// # Arguments
+/// ```level```: the level of the line
+pub fn is_enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Logs a line to the screen, over serial, and to the log buffer
+///
+/// # Arguments
+/// ```args```: the arguments to format, without a trailing new line
+#[doc(hidden)]
+pub fn _log(args: fmt::Arguments) {
+    _log_at(Level::Info, args);
+}
+
+/// Logs a line like `_log`, unless the level is more detailed than the log level. The line is
+/// shown on the screen in the color of the level.
+///
+/// # Arguments
+/// ```level```: the level of the line, like an error
+/// ```args```: the arguments to format, without a trailing new line
+#[doc(hidden)]
+pub fn _log_at(level: Level, args: fmt::Arguments) {
+    use x86_64::instructions::interrupts;
+
+    if !is_enabled(level) {
+        return;
+    }
+
+    console::_print_as(level.role(), format_args!("{}\n", args));
+    serial_println!("{}", args);
+
+    // Storing the line needs the heap, so skip it until the heap is initialized
+    if allocator::is_heap_initialized() {
+        let line = alloc::format!("{}", args);
+
+        // Run without interrupts to prevent deadlocks with interrupt handlers that log
+        interrupts::without_interrupts(|| LOG_BUFFER.lock().push(line));
+    }
+}
+
+/// Returns a copy of the lines in the kernel log buffer, from oldest to newest
+pub fn recent_lines() -> Vec<String> {
+    use x86_64::instructions::interrupts;
+
+    // Run without interrupts to prevent deadlocks with interrupt handlers that log
+    interrupts::without_interrupts(|| LOG_BUFFER.lock().lines().map(String::from).collect())
+}
+
+/// Prints the lines in the kernel log buffer over serial, from oldest to newest
+pub fn dmesg() {
+    for line in recent_lines() {
+        serial_println!("{}", line);
+    }
+}
+
+// logs a line to the screen, over serial, and to the kernel log buffer
+#[macro_export]
+macro_rules! log {
+    ($($arg:tt)*) => ($crate::logger::_log(format_args!($($arg)*)));
+}
+
+// logs an error line, shown in the error color of the color scheme
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => ($crate::logger::_log_at(
+        $crate::logger::Level::Error,
+        format_args!("ERROR: {}", format_args!($($arg)*))
+    ));
+}
+
+// logs a warning line, shown in the warning color of the color scheme
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => ($crate::logger::_log_at(
+        $crate::logger::Level::Warning,
+        format_args!("WARNING: {}", format_args!($($arg)*))
+    ));
+}
+
+/// Checks whether the oldest lines are dropped once the buffer is full
+#[test_case]
+fn test_log_buffer_capacity() {
+    let mut buffer = LogBuffer::new(2);
+    buffer.push(String::from("first"));
+    buffer.push(String::from("second"));
+    buffer.push(String::from("third"));
+    assert!(buffer.lines().eq(["second", "third"]));
+}
+
+/// Checks whether logged lines can be read back from the kernel log buffer
+#[test_case]
+fn test_log_read_back() {
+    for i in 0..3 {
+        log!("test_log_read_back line {}", i);
+    }
+
+    let lines = recent_lines();
+    assert!(lines.len() >= 3);
+    assert_eq!(
+        lines[lines.len() - 3..],
+        [
+            "test_log_read_back line 0",
+            "test_log_read_back line 1",
+            "test_log_read_back line 2"
+        ]
+    );
+    dmesg();
+}
+
+/// Checks whether the log level from the command line drops the more detailed lines
+#[test_case]
+fn test_log_level_from_command_line() {
+    init_from(&CommandLine::new("loglevel=warning"));
+    log!("test_log_level info line");
+    crate::warning!("test_log_level warning line");
+    set_max_level(Level::Info);
+
+    let lines = recent_lines();
+    assert_eq!(
+        lines.last().map(String::as_str),
+        Some("WARNING: test_log_level warning line")
+    );
+    assert!(!lines.iter().any(|line| line == "test_log_level info line"));
+
+    // An unknown level keeps the current one
+    init_from(&CommandLine::new("loglevel=verbose"));
+    assert!(is_enabled(Level::Info));
+    assert!(!is_enabled(Level::Debug));
+}
@@ -8,17 +8,12 @@ extern crate alloc;
 
 use core::panic::PanicInfo;
 
-#[cfg(not(test))]
-use blog_os::hlt_loop;
-
 use blog_os::{
-    allocator,
-    memory::{self, BootInfoFrameAllocator},
-    print, println,
-    task::{executor::Executor, keyboard, Task},
+    apic, hlt_loop, memory, power, print, println, serial_cmd, serial_println, shell,
+    task::{executor::Executor, Task},
+    time,
 };
 use bootloader::{entry_point, BootInfo};
-use x86_64::VirtAddr;
 
 /// This function is called on panic, only run whe not testing
 ///
@@ -30,10 +25,35 @@ use x86_64::VirtAddr;
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    hlt_loop();
+    // Count the panic before printing, so a panic while printing also counts towards the limit
+    #[cfg(feature = "reboot-on-panic")]
+    let panics = blog_os::power::count_panic();
+
+    // The panic may have happened while printing, so release the locks by force.
+    // Safe, as normal execution has stopped.
+    unsafe { blog_os::vga_buffer::panic_screen(info) };
+    // The screen only fits a few frames, so also send the backtrace to the host
+    blog_os::backtrace::print_backtrace();
+
+    #[cfg(feature = "reboot-on-panic")]
+    if panics < PANIC_REBOOT_THRESHOLD {
+        println!("Panicked {} times, rebooting", panics);
+        blog_os::power::reboot();
+    } else {
+        // Start counting again on the next boot, which needs a reset by hand now
+        println!("Panicked {} times, halting", panics);
+        blog_os::power::reset_panic_count();
+    }
+
+    // Halts by default, unless the panic action has been set to reboot
+    blog_os::power::after_panic();
 }
 
+/// The number of panics, counted across reboots in CMOS RAM, after which the kernel halts
+/// instead of rebooting
+#[cfg(all(not(test), feature = "reboot-on-panic"))]
+const PANIC_REBOOT_THRESHOLD: u8 = 3;
+
 /// This function is called on panic, only run whe not testing
 ///
 /// # Arguments
@@ -65,18 +85,39 @@ async fn example_task() {
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("Hello, World{}", "!");
 
-    blog_os::init();
+    #[cfg(feature = "print-memory-map")]
+    memory::print_memory_map(&boot_info.memory_map);
+
+    let mut resources = match blog_os::try_init(boot_info) {
+        Ok(resources) => resources,
+        Err(error) => {
+            serial_println!("Kernel initialization failed: {}", error);
+            println!("Kernel initialization failed: {}", error);
+            hlt_loop();
+        }
+    };
 
-    // Get the physical memory offset and retrieve the l4 table
-    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    serial_println!(
+        "Page tables: {:?}",
+        memory::page_table_stats(resources.mapper.phys_offset())
+    );
 
-    let mut mapper = unsafe { memory::init(physical_memory_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    // Measure the TSC before the local APIC timer takes over, for the uptime of the shell
+    time::calibrate_tsc();
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    // Use the local APIC timer when available, the PIT otherwise
+    apic::init(&mut resources.mapper, &mut resources.frame_allocator)
+        .expect("APIC initialization failed");
 
     let mut executor = Executor::new();
     executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    // The shell reads the keyboard, so the keypresses aren't printed by another task.
+    // Nothing reads the keyboard once it ends, with Ctrl-Q or Ctrl-D, so power off then.
+    let usable_memory = memory::usable_memory(&boot_info.memory_map);
+    executor.spawn(Task::new(async move {
+        shell::shell(usable_memory).await;
+        power::shutdown();
+    }));
+    executor.spawn(Task::new(serial_cmd::serial_commands()));
     executor.run();
 }
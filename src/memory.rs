@@ -1,9 +1,37 @@
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use conquer_once::spin::OnceCell;
 use x86_64::{
-    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        mapper::MapToError, page_table::PageTableEntry, FrameAllocator, FrameDeallocator, Mapper,
+        OffsetPageTable, Page, PageTable, PageTableFlags, PageTableIndex, PhysFrame, Size4KiB,
+    },
     PhysAddr, VirtAddr,
 };
 
+use crate::serial_println;
+
+/// The start of the virtual address range `map_mmio` maps device memory into
+pub const MMIO_START: u64 = 0x_6666_0000_0000;
+/// The size of the virtual address range for device memory
+pub const MMIO_SIZE: u64 = 1024 * 1024 * 1024;
+
+// The virtual address at which the physical memory is mapped, set by `init`
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+
+/// The start of the virtual address range `map_anonymous` maps memory into
+pub const ANONYMOUS_START: u64 = 0x_7777_0000_0000;
+/// The size of the virtual address range for anonymous memory
+pub const ANONYMOUS_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+// The start of the unused part of the device memory range
+static NEXT_MMIO: AtomicU64 = AtomicU64::new(MMIO_START);
+
+// The ranges mapped by `map_anonymous`, as start address and number of pages, sorted by address
+static ANONYMOUS_RANGES: spin::Mutex<Vec<(u64, u64)>> = spin::Mutex::new(Vec::new());
+
 /// Initialize a new OffsetPageTable
 ///
 /// # Safety
@@ -12,6 +40,7 @@ use x86_64::{
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.init_once(|| physical_memory_offset);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
@@ -35,10 +64,586 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
     &mut *page_table_ptr // Only unsafe operation
 }
 
+/// Returns the virtual address at which the physical memory is mapped
+///
+/// # Returns
+/// The offset, or None if `init` hasn't been called yet
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    PHYSICAL_MEMORY_OFFSET.get().copied()
+}
+
+/// Removes the cached translation of a single page from the TLB, after its page table entry was
+/// changed by hand. Mappings changed through a `Mapper` are flushed with the returned
+/// `MapperFlush` instead.
+///
+/// # Arguments
+/// ```addr```: an address in the page to flush
+pub fn flush_tlb(addr: VirtAddr) {
+    x86_64::instructions::tlb::flush(addr);
+}
+
+/// Removes every cached translation from the TLB by reloading CR3, except for global pages.
+/// This is expensive, as every following memory access has to walk the page tables again, so
+/// prefer `flush_tlb` when only a few pages changed.
+pub fn flush_tlb_all() {
+    x86_64::instructions::tlb::flush_all();
+}
+
+/// Prints the page table entries used to translate a virtual address over serial
+///
+/// # Arguments
+/// ```addr```: the virtual address to describe the mapping of
+/// ```mapper```: the page table the address is mapped in
+///
+/// # Returns
+/// The flags of the entry mapping the address, or None if it isn't mapped
+pub fn describe_mapping(addr: VirtAddr, mapper: &OffsetPageTable) -> Option<PageTableFlags> {
+    walk_mapping(addr, mapper.phys_offset())
+}
+
+/// Walks the active page tables for a virtual address, printing every entry on the way
+///
+/// # Arguments
+/// ```addr```: the virtual address to describe the mapping of
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+///
+/// # Returns
+/// The flags of the entry mapping the address, or None if it isn't mapped
+fn walk_mapping(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PageTableFlags> {
+    serial_println!("Mapping of {:?}:", addr);
+
+    let mapping = walk_page_tables(addr, physical_memory_offset, |level, index, entry| {
+        if entry.flags().contains(PageTableFlags::PRESENT) {
+            serial_println!(
+                "  P{} index {}: {:?} {:?}",
+                level,
+                u16::from(index),
+                entry.addr(),
+                entry.flags()
+            );
+        } else {
+            serial_println!("  P{} index {}: not present", level, u16::from(index));
+        }
+    });
+
+    let (physical_address, flags) = mapping?;
+    serial_println!("  -> {:?}", physical_address);
+    Some(flags)
+}
+
+/// Translates a virtual address to the physical address it's mapped to.
+/// Addresses in 2 MiB and 1 GiB huge pages are translated as well.
+///
+/// # Arguments
+/// ```addr```: the virtual address to translate
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+///
+/// # Returns
+/// The physical address, or None if the address isn't mapped
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the
+/// complete physical memory is mapped to virtual memory at the passed
+/// `physical_memory_offset`.
+pub unsafe fn translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PhysAddr> {
+    walk_page_tables(addr, physical_memory_offset, |_, _, _| {})
+        .map(|(physical_address, _)| physical_address)
+}
+
+/// Walks the active page tables for a virtual address
+///
+/// # Arguments
+/// ```addr```: the virtual address to look up
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+/// ```visit```: called with the level, index, and entry of every table entry on the way
+///
+/// # Returns
+/// The physical address and the flags of the entry mapping it, or None if it isn't mapped
+fn walk_page_tables(
+    addr: VirtAddr,
+    physical_memory_offset: VirtAddr,
+    mut visit: impl FnMut(u32, PageTableIndex, &PageTableEntry),
+) -> Option<(PhysAddr, PageTableFlags)> {
+    use x86_64::registers::control::Cr3;
+
+    let (mut table_frame, _) = Cr3::read();
+
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+    for (level, index) in (1..=4).rev().zip(table_indexes) {
+        // Access the table through the physical memory mapping, like `active_level_4_table`
+        let virtual_address = physical_memory_offset + table_frame.start_address().as_u64();
+        let table: &PageTable = unsafe { &*virtual_address.as_ptr() };
+
+        let entry = &table[index];
+        visit(level, index, entry);
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+
+        // A level 1 entry or a huge page maps the address directly, without a lower level table.
+        // The page offset is 12 bits for 4 KiB pages, 21 bits for 2 MiB and 30 bits for 1 GiB.
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            let offset_mask = (1u64 << (12 + 9 * (level - 1))) - 1;
+            return Some((entry.addr() + (addr.as_u64() & offset_mask), flags));
+        }
+        table_frame = PhysFrame::containing_address(entry.addr());
+    }
+
+    // Unreachable, as level 1 entries always return
+    None
+}
+
+/// Prints the mappings of the active page tables over serial
+///
+/// Only mapped pages are printed, with their virtual range, physical frame, and flags.
+/// Huge pages are printed as a single entry, without descending into them.
+///
+/// # Arguments
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+/// ```max_entries```: the maximum number of entries to print
+pub fn dump_page_tables(physical_memory_offset: VirtAddr, max_entries: usize) {
+    use x86_64::registers::control::Cr3;
+
+    serial_println!("Page tables:");
+
+    // Only take a shared reference, as `active_level_4_table` may only be called once
+    let (level_4_table_frame, _) = Cr3::read();
+    let virtual_address = physical_memory_offset + level_4_table_frame.start_address().as_u64();
+    let level_4_table: &PageTable = unsafe { &*virtual_address.as_ptr() };
+    let mut remaining = max_entries;
+    dump_page_table(level_4_table, 4, 0, physical_memory_offset, &mut remaining);
+    if remaining == 0 {
+        serial_println!("  ... output limited to {} entries", max_entries);
+    }
+}
+
+/// Prints the mappings of a page table and the tables below it
+///
+/// # Arguments
+/// ```table```: the table to print
+/// ```level```: the level of the table, 4 for the level 4 table
+/// ```base```: the first virtual address mapped by the table
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+/// ```remaining```: the number of entries that can still be printed
+fn dump_page_table(
+    table: &PageTable,
+    level: u32,
+    base: u64,
+    physical_memory_offset: VirtAddr,
+    remaining: &mut usize,
+) {
+    // The size of the memory mapped by a single entry of this table
+    let entry_size = 1u64 << (12 + 9 * (level - 1));
+
+    for (index, entry) in table.iter().enumerate() {
+        if *remaining == 0 {
+            return;
+        }
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        // Sign extend the address, as required for canonical addresses
+        let start = VirtAddr::new_truncate(base + index as u64 * entry_size);
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            serial_println!(
+                "  {:?} - {:?} ({} KiB) -> {:?} {:?}",
+                start,
+                start + (entry_size - 1),
+                entry_size / 1024,
+                entry.addr(),
+                flags
+            );
+            *remaining -= 1;
+        } else {
+            // Access the next table through the physical memory mapping
+            let virtual_address = physical_memory_offset + entry.addr().as_u64();
+            let next_table: &PageTable = unsafe { &*virtual_address.as_ptr() };
+            dump_page_table(
+                next_table,
+                level - 1,
+                start.as_u64(),
+                physical_memory_offset,
+                remaining,
+            );
+        }
+    }
+}
+
+/// The number of entries and pages mapped by the active page tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageTableStats {
+    /// The number of present entries in the tables of every level, level 1 first
+    pub present_entries: [usize; 4],
+    /// The number of mapped pages of any size
+    pub mapped_pages: usize,
+    /// The number of mapped 2 MiB and 1 GiB pages
+    pub huge_pages: usize,
+    /// The total size of the mapped pages in bytes
+    pub mapped_bytes: u64,
+}
+
+/// Counts the entries and pages mapped by the active page tables, without allocating or
+/// changing them
+///
+/// # Arguments
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+///
+/// # Returns
+/// The counts of the entries at every level and of the mapped pages
+pub fn page_table_stats(physical_memory_offset: VirtAddr) -> PageTableStats {
+    use x86_64::registers::control::Cr3;
+
+    // Only take a shared reference, as `active_level_4_table` may only be called once
+    let (level_4_table_frame, _) = Cr3::read();
+    let virtual_address = physical_memory_offset + level_4_table_frame.start_address().as_u64();
+    let level_4_table: &PageTable = unsafe { &*virtual_address.as_ptr() };
+
+    let mut stats = PageTableStats::default();
+    count_page_table(level_4_table, 4, physical_memory_offset, &mut stats);
+    stats
+}
+
+/// Counts the entries and pages mapped by a page table and the tables below it.
+/// The recursion is bounded by the 4 levels of tables.
+///
+/// # Arguments
+/// ```table```: the table to count
+/// ```level```: the level of the table, 4 for the level 4 table
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+/// ```stats```: the counts to add to
+fn count_page_table(
+    table: &PageTable,
+    level: u32,
+    physical_memory_offset: VirtAddr,
+    stats: &mut PageTableStats,
+) {
+    // The size of the memory mapped by a single entry of this table
+    let entry_size = 1u64 << (12 + 9 * (level - 1));
+
+    for entry in table.iter() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+        stats.present_entries[level as usize - 1] += 1;
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            stats.mapped_pages += 1;
+            stats.mapped_bytes += entry_size;
+            if level > 1 {
+                stats.huge_pages += 1;
+            }
+        } else {
+            // Access the next table through the physical memory mapping
+            let virtual_address = physical_memory_offset + entry.addr().as_u64();
+            let next_table: &PageTable = unsafe { &*virtual_address.as_ptr() };
+            count_page_table(next_table, level - 1, physical_memory_offset, stats);
+        }
+    }
+}
+
+/// Calculates the amount of usable memory in the memory map
+///
+/// # Arguments
+/// ```memory_map```: the memory map passed by the bootloader
+///
+/// # Returns
+/// The total size of the usable regions in bytes
+pub fn usable_memory(memory_map: &MemoryMap) -> u64 {
+    memory_map
+        .iter()
+        .filter(|r| r.region_type == MemoryRegionType::Usable)
+        .map(|r| r.range.end_addr() - r.range.start_addr())
+        .sum()
+}
+
+/// A size in bytes, displayed in the largest of B, KiB, and MiB that fits at least once.
+/// The size is rounded down to that unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl core::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const KIB: u64 = 1024;
+        const MIB: u64 = 1024 * KIB;
+
+        let (value, unit) = match self.0 {
+            size if size >= MIB => (size / MIB, "MiB"),
+            size if size >= KIB => (size / KIB, "KiB"),
+            size => (size, "B"),
+        };
+
+        // Right align the whole size in the requested width, so it lines up in tables
+        let width = f.width().unwrap_or(0).saturating_sub(unit.len() + 1);
+        write!(f, "{value:>width$} {unit}")
+    }
+}
+
+/// Prints a line over serial and to the screen
+///
+/// # Arguments
+/// ```args```: the arguments to parse and print
+fn print_line(args: core::fmt::Arguments) {
+    serial_println!("{}", args);
+    crate::println!("{}", args);
+}
+
+/// Prints a table with the start, end, size, and type of every region in the memory map over
+/// serial and to the screen
+///
+/// # Arguments
+/// ```memory_map```: the memory map passed by the bootloader
+pub fn print_memory_map(memory_map: &MemoryMap) {
+    print_line(format_args!(
+        "{:<12}  {:<12}  {:>8}  Type",
+        "Start", "End", "Size"
+    ));
+    for region in memory_map.iter() {
+        let start = region.range.start_addr();
+        let end = region.range.end_addr();
+        print_line(format_args!(
+            "{:#012x}  {:#012x}  {:>8}  {:?}",
+            start,
+            end,
+            ByteSize(end - start),
+            region.region_type
+        ));
+    }
+    print_line(format_args!(
+        "Usable memory: {}",
+        ByteSize(usable_memory(memory_map))
+    ));
+}
+
+/// Maps the registers or memory of a device, without caching, so every access reaches the
+/// device. The pages are taken from the device memory range, starting at `MMIO_START`, and are
+/// never unmapped.
+///
+/// # Arguments
+/// ```phys```: the physical address of the device memory, doesn't have to be page aligned
+/// ```size```: the number of bytes to map
+/// ```mapper```: the mapper to create the mappings with
+/// ```frame_allocator```: the allocator to take the frames for new page tables from
+///
+/// # Returns
+/// The virtual address `phys` is mapped at, or an error if a page table couldn't be allocated
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the physical range belongs to
+/// a device, and isn't used as normal memory, which is accessed cached through other mappings.
+///
+/// # Panics
+/// If the device memory range is full
+pub unsafe fn map_mmio(
+    phys: PhysAddr,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    // Map every frame touched by the range, also when it doesn't start or end on a frame boundary
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let last = PhysFrame::<Size4KiB>::containing_address(phys + (size.max(1) as u64 - 1));
+    let length = last.start_address() - first.start_address() + first.size();
+
+    let start = NEXT_MMIO.fetch_add(length, Ordering::SeqCst);
+    assert!(
+        start + length <= MMIO_START + MMIO_SIZE,
+        "The device memory range is full"
+    );
+
+    // Write through and don't cache, as reads and writes have side effects on the device
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH;
+    for (i, frame) in PhysFrame::range_inclusive(first, last).enumerate() {
+        let page = Page::containing_address(VirtAddr::new(start + i as u64 * frame.size()));
+        mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+    }
+
+    Ok(VirtAddr::new(start + (phys - first.start_address())))
+}
+
+/// The reason anonymous memory couldn't be mapped or unmapped
+#[derive(Debug)]
+pub enum MapError {
+    /// No memory was requested
+    ZeroSize,
+    /// The anonymous memory range doesn't have a large enough free range left
+    NoVirtualSpace,
+    /// The frame allocator ran out of frames
+    OutOfFrames,
+    /// A page couldn't be mapped
+    Map(MapToError<Size4KiB>),
+    /// The address isn't the start of a range mapped by `map_anonymous`
+    NotMapped,
+}
+
+/// Maps zeroed memory into a free range of the anonymous memory range, starting at
+/// `ANONYMOUS_START`. Unlike the heap, the memory is a whole number of pages, and it's given
+/// back to the frame allocator by `unmap_anonymous`.
+///
+/// # Arguments
+/// ```size```: the number of bytes to map, rounded up to whole pages
+/// ```flags```: the flags of the pages, which are always present
+/// ```mapper```: the mapper to create the mappings with
+/// ```frame_allocator```: the allocator to take the frames from
+///
+/// # Returns
+/// The start of the mapped memory, or why it couldn't be mapped
+///
+/// # Panics
+/// If the physical memory hasn't been mapped by `init` yet
+pub fn map_anonymous(
+    size: usize,
+    flags: PageTableFlags,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+) -> Result<VirtAddr, MapError> {
+    if size == 0 {
+        return Err(MapError::ZeroSize);
+    }
+    let physical_memory_offset = physical_memory_offset().expect("Memory not initialized");
+    let pages = (size as u64).div_ceil(4096);
+
+    // Reject sizes larger than the whole range up front, so the addresses below can't overflow
+    if pages > ANONYMOUS_SIZE / 4096 {
+        return Err(MapError::NoVirtualSpace);
+    }
+
+    // Take the first gap between the mapped ranges that is large enough
+    let mut ranges = ANONYMOUS_RANGES.lock();
+    let mut start = ANONYMOUS_START;
+    let mut index = 0;
+    for &(range_start, range_pages) in ranges.iter() {
+        if range_start - start >= pages * 4096 {
+            break;
+        }
+        start = range_start + range_pages * 4096;
+        index += 1;
+    }
+    if start + pages * 4096 > ANONYMOUS_START + ANONYMOUS_SIZE {
+        return Err(MapError::NoVirtualSpace);
+    }
+
+    for i in 0..pages {
+        let page = Page::containing_address(VirtAddr::new(start + i * 4096));
+        let Some(frame) = frame_allocator.allocate_frame() else {
+            unmap_pages(start, i, mapper, frame_allocator);
+            return Err(MapError::OutOfFrames);
+        };
+
+        // Safe, as the frame was just allocated, and the page isn't mapped yet
+        let result = unsafe {
+            zero_frame(frame, physical_memory_offset);
+            mapper.map_to(
+                page,
+                frame,
+                flags | PageTableFlags::PRESENT,
+                frame_allocator,
+            )
+        };
+        match result {
+            Ok(flush) => flush.flush(),
+            Err(error) => {
+                // Safe, as the frame isn't mapped
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                unmap_pages(start, i, mapper, frame_allocator);
+                return Err(MapError::Map(error));
+            }
+        }
+    }
+
+    ranges.insert(index, (start, pages));
+    Ok(VirtAddr::new(start))
+}
+
+/// Unmaps memory mapped by `map_anonymous`, giving its frames back to the frame allocator
+///
+/// # Arguments
+/// ```start```: the address returned by `map_anonymous`
+/// ```mapper```: the mapper the memory was mapped with
+/// ```frame_allocator```: the allocator to give the frames back to
+///
+/// # Returns
+/// An error if the address isn't the start of mapped anonymous memory
+pub fn unmap_anonymous(
+    start: VirtAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> Result<(), MapError> {
+    let mut ranges = ANONYMOUS_RANGES.lock();
+    let index = ranges
+        .iter()
+        .position(|&(range_start, _)| range_start == start.as_u64())
+        .ok_or(MapError::NotMapped)?;
+    let (start, pages) = ranges.remove(index);
+    unmap_pages(start, pages, mapper, frame_allocator);
+    Ok(())
+}
+
+/// Unmaps consecutive pages, giving their frames back to the frame allocator.
+/// Pages that can't be unmapped, e.g. as they were already unmapped elsewhere, are logged and
+/// skipped.
+///
+/// # Arguments
+/// ```start```: the address of the first page
+/// ```pages```: the number of pages
+/// ```mapper```: the mapper the pages are mapped with
+/// ```frame_allocator```: the allocator to give the frames back to
+fn unmap_pages(
+    start: u64,
+    pages: u64,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    for i in 0..pages {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(start + i * 4096));
+        match mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                // Safe, as the frame isn't mapped anymore
+                unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+            Err(error) => crate::warning!("Unmapping {:?} failed: {:?}", page, error),
+        }
+    }
+}
+
+/// Fills a frame with zeroes, so its old contents can't leak into its next use
+///
+/// # Arguments
+/// ```frame```: the frame to zero
+/// ```physical_memory_offset```: the virtual address at which the physical memory is mapped
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the complete physical memory
+/// is mapped at `physical_memory_offset`, and that the frame isn't in use.
+pub unsafe fn zero_frame(frame: PhysFrame, physical_memory_offset: VirtAddr) {
+    let address = physical_memory_offset + frame.start_address().as_u64();
+    core::ptr::write_bytes(address.as_mut_ptr::<u8>(), 0, frame.size() as usize);
+}
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
+///
+/// Deallocated frames are reused first. They are kept in a list stored in the frames
+/// themselves, with the first 8 bytes of every frame holding the address of the next frame.
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    // The most recently deallocated frame, the start of the list of deallocated frames
+    free_list: Option<PhysFrame>,
+    free_list_length: usize,
 }
 
 impl BootInfoFrameAllocator {
@@ -52,9 +657,34 @@ impl BootInfoFrameAllocator {
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            free_list: None,
+            free_list_length: 0,
         }
     }
 
+    /// Returns the number of usable frames in the memory map, including allocated ones
+    pub fn total_frame_count(&self) -> usize {
+        self.usable_frames().count()
+    }
+
+    /// Returns the number of usable frames that haven't been allocated yet, or have been
+    /// deallocated since
+    pub fn free_frame_count(&self) -> usize {
+        self.total_frame_count().saturating_sub(self.next) + self.free_list_length
+    }
+
+    /// Returns the address a frame in the list of deallocated frames is accessed at
+    ///
+    /// # Arguments
+    /// ```frame```: the deallocated frame
+    ///
+    /// # Panics
+    /// If the physical memory hasn't been mapped by `init` yet
+    fn free_list_link(frame: PhysFrame) -> *mut u64 {
+        let offset = physical_memory_offset().expect("Memory not initialized");
+        (offset + frame.start_address().as_u64()).as_mut_ptr()
+    }
+
     /// Returns an iterator over the usable frames specified in the memory map
     fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
         // Get usable regions from memory map
@@ -76,8 +706,255 @@ impl BootInfoFrameAllocator {
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        if let Some(frame) = self.free_list.take() {
+            // Safe, as deallocated frames are only used for the list
+            unsafe {
+                let link = Self::free_list_link(frame);
+                let next = link.read();
+                self.free_list =
+                    (next != 0).then(|| PhysFrame::containing_address(PhysAddr::new(next)));
+
+                // Clear the link too, so the whole frame is zeroed
+                #[cfg(feature = "zero-on-free")]
+                link.write(0);
+            }
+            self.free_list_length -= 1;
+            return Some(frame);
+        }
+
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
 }
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        #[cfg(feature = "zero-on-free")]
+        zero_frame(
+            frame,
+            physical_memory_offset().expect("Memory not initialized"),
+        );
+
+        // Frame 0 isn't usable memory, so 0 marks the end of the list
+        let next = self
+            .free_list
+            .map_or(0, |next| next.start_address().as_u64());
+        Self::free_list_link(frame).write(next);
+        self.free_list = Some(frame);
+        self.free_list_length += 1;
+    }
+}
+
+/// A FrameAllocator which keeps a bit per frame, set while the frame is in use.
+///
+/// Unlike `BootInfoFrameAllocator`, allocating doesn't walk the memory map, and deallocated frames
+/// don't have to be accessed. The bitmap is stored on the heap, so it can only be created once
+/// the heap is initialized.
+pub struct BitmapFrameAllocator {
+    // The first usable frame, which the first bit represents
+    start: PhysFrame,
+    // A bit per frame from the first to the last usable frame, set for allocated and unusable
+    // frames
+    bitmap: Vec<u64>,
+    // The first word of the bitmap that may contain a free frame
+    next_word: usize,
+    free_frames: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Creates a FrameAllocator from the passed memory map, with every usable frame free
+    ///
+    /// # Safety
+    /// This function is unsafe because the caller must guarantee that the passed memory map is
+    /// valid. The main requirement is that all frames that are marked as `USABLE` in it are
+    /// really unused.
+    pub unsafe fn init(memory_map: &MemoryMap) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+        let start_address = usable_regions()
+            .map(|r| r.range.start_addr())
+            .min()
+            .unwrap_or(0);
+        let end_address = usable_regions()
+            .map(|r| r.range.end_addr())
+            .max()
+            .unwrap_or(0);
+
+        // Every frame starts out as unusable, only the usable regions are freed
+        let frame_count = ((end_address.max(start_address) - start_address) / 4096) as usize;
+        let mut allocator = BitmapFrameAllocator {
+            start: PhysFrame::containing_address(PhysAddr::new(start_address)),
+            bitmap: vec![u64::MAX; frame_count.div_ceil(64)],
+            next_word: 0,
+            free_frames: 0,
+        };
+        for region in usable_regions() {
+            for address in (region.range.start_addr()..region.range.end_addr()).step_by(4096) {
+                allocator.set_free(PhysFrame::containing_address(PhysAddr::new(address)));
+            }
+        }
+        allocator
+    }
+
+    /// Creates a FrameAllocator which takes over the frames of a `BootInfoFrameAllocator`, so
+    /// the frames it allocated stay allocated
+    ///
+    /// # Arguments
+    /// ```frame_allocator```: the allocator to take over, which can't be used anymore
+    pub fn from_boot_info(frame_allocator: BootInfoFrameAllocator) -> Self {
+        // Safe, as the memory map was already checked when the boot info allocator was created
+        let mut allocator = unsafe { Self::init(frame_allocator.memory_map) };
+        for frame in frame_allocator.usable_frames().take(frame_allocator.next) {
+            allocator.set_allocated(frame);
+        }
+
+        // The deallocated frames are free again
+        let mut free_list = frame_allocator.free_list;
+        while let Some(frame) = free_list {
+            // Safe, as deallocated frames are only used for the list
+            let next = unsafe { BootInfoFrameAllocator::free_list_link(frame).read() };
+            allocator.set_free(frame);
+            free_list = (next != 0).then(|| PhysFrame::containing_address(PhysAddr::new(next)));
+        }
+        allocator
+    }
+
+    /// Returns the number of usable frames that haven't been allocated yet, or have been
+    /// deallocated since
+    pub fn free_frame_count(&self) -> usize {
+        self.free_frames
+    }
+
+    /// Returns the word and bit of a frame in the bitmap
+    ///
+    /// # Arguments
+    /// ```frame```: the frame to look up
+    ///
+    /// # Panics
+    /// If the frame is outside of the usable memory
+    fn position(&self, frame: PhysFrame) -> (usize, u64) {
+        let index = (frame >= self.start).then(|| (frame - self.start) as usize);
+        let index = index
+            .filter(|&index| index < self.bitmap.len() * 64)
+            .expect("The frame isn't usable memory");
+        (index / 64, 1 << (index % 64))
+    }
+
+    /// Marks a frame as free
+    fn set_free(&mut self, frame: PhysFrame) {
+        let (word, bit) = self.position(frame);
+        if self.bitmap[word] & bit != 0 {
+            self.bitmap[word] &= !bit;
+            self.free_frames += 1;
+            self.next_word = self.next_word.min(word);
+        }
+    }
+
+    /// Marks a frame as allocated
+    fn set_allocated(&mut self, frame: PhysFrame) {
+        let (word, bit) = self.position(frame);
+        if self.bitmap[word] & bit == 0 {
+            self.bitmap[word] |= bit;
+            self.free_frames -= 1;
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        // Every word before next_word is full, so start searching there
+        while let Some(&word) = self.bitmap.get(self.next_word) {
+            if word != u64::MAX {
+                let index = self.next_word * 64 + word.trailing_ones() as usize;
+                let frame = self.start + index as u64;
+                self.set_allocated(frame);
+                return Some(frame);
+            }
+            self.next_word += 1;
+        }
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let (word, bit) = self.position(frame);
+        assert!(self.bitmap[word] & bit != 0, "The frame isn't allocated");
+
+        #[cfg(feature = "zero-on-free")]
+        zero_frame(
+            frame,
+            physical_memory_offset().expect("Memory not initialized"),
+        );
+
+        self.set_free(frame);
+    }
+}
+
+/// Checks whether the VGA buffer is reported as present and writable
+#[test_case]
+fn test_describe_mapping_vga_buffer() {
+    let physical_memory_offset = physical_memory_offset().expect("Memory not initialized");
+    let flags = walk_mapping(VirtAddr::new(0xb8000), physical_memory_offset)
+        .expect("VGA buffer not mapped");
+    assert!(flags.contains(PageTableFlags::PRESENT | PageTableFlags::WRITABLE));
+}
+
+/// Checks whether the memory map contains enough usable memory for the heap
+#[test_case]
+fn test_usable_memory() {
+    let usable = usable_memory(&crate::test_boot_info().memory_map);
+    assert_ne!(usable, 0);
+    assert!(usable >= crate::allocator::heap_size() as u64);
+}
+
+/// Checks whether the page table statistics include at least the heap and the physical memory
+/// mapping
+#[test_case]
+fn test_page_table_stats() {
+    let heap_size = crate::allocator::heap_size();
+    let physical_memory_offset = physical_memory_offset().expect("Memory not initialized");
+    let stats = page_table_stats(physical_memory_offset);
+    let usable = usable_memory(&crate::test_boot_info().memory_map);
+
+    assert!(stats.mapped_pages >= heap_size / 4096);
+    assert!(stats.mapped_bytes >= heap_size as u64 + usable);
+    assert!(stats.huge_pages <= stats.mapped_pages);
+    assert_eq!(
+        stats.present_entries[0] + stats.huge_pages,
+        stats.mapped_pages
+    );
+    assert!(stats.present_entries[3] > 0);
+}
+
+/// Checks whether sizes are shown in the largest unit that fits, and padded to the width
+#[test_case]
+fn test_byte_size_display() {
+    use alloc::format;
+
+    assert_eq!(format!("{}", ByteSize(512)), "512 B");
+    assert_eq!(format!("{}", ByteSize(1024)), "1 KiB");
+    assert_eq!(format!("{}", ByteSize(639 * 1024 + 1)), "639 KiB");
+    assert_eq!(format!("{}", ByteSize(128 * 1024 * 1024)), "128 MiB");
+    assert_eq!(format!("{:>8}", ByteSize(4096)), "   4 KiB");
+}
+
+/// Checks whether addresses in the physical memory mapping, which uses huge pages, are translated
+#[test_case]
+fn test_translate_addr_physical_memory_mapping() {
+    let physical_memory_offset = physical_memory_offset().expect("Memory not initialized");
+
+    // An address inside of a 2 MiB page, with a non-zero offset in the page
+    let physical_address = 0x0012_3456;
+    let translated = unsafe {
+        translate_addr(
+            physical_memory_offset + physical_address,
+            physical_memory_offset,
+        )
+    };
+    assert_eq!(translated, Some(PhysAddr::new(physical_address)));
+}
@@ -0,0 +1,129 @@
+//! The I/O ports used by the kernel, and thin helpers to access them.
+
+use x86_64::instructions::port::Port;
+
+/// The counter of PIT channel 0, which generates the timer interrupt
+pub const PIT_CHANNEL0: u16 = 0x40;
+/// The counter of PIT channel 2, which is connected to the PC speaker
+pub const PIT_CHANNEL2: u16 = 0x42;
+/// The mode/command register of the PIT
+pub const PIT_COMMAND: u16 = 0x43;
+/// The data port of the PS/2 controller, which contains the keyboard scancodes
+pub const PS2_DATA: u16 = 0x60;
+/// Port B of the keyboard controller, which controls the gate of PIT channel 2
+pub const PS2_PORT_B: u16 = 0x61;
+/// The status register of the PS/2 controller
+pub const PS2_STATUS: u16 = 0x64;
+/// The index register of the CMOS RAM and real-time clock, bit 7 disables NMIs
+pub const CMOS_INDEX: u16 = 0x70;
+/// The data register of the CMOS RAM byte selected with `CMOS_INDEX`
+pub const CMOS_DATA: u16 = 0x71;
+/// The index register of the VGA CRT controller
+pub const VGA_CRTC_INDEX: u16 = 0x3d4;
+/// The data register of the VGA CRT controller
+pub const VGA_CRTC_DATA: u16 = 0x3d5;
+/// The data port of the primary 8259 PIC, which contains the interrupt masks of IRQ 0-7
+pub const PIC1_DATA: u16 = 0x21;
+/// The data register of the first serial port
+pub const COM1: u16 = 0x3f8;
+/// The interrupt enable register of the first serial port
+pub const COM1_INTERRUPT_ENABLE: u16 = COM1 + 1;
+/// The modem control register of the first serial port
+pub const COM1_MODEM_CONTROL: u16 = COM1 + 4;
+/// The line status register of the first serial port
+pub const COM1_LINE_STATUS: u16 = COM1 + 5;
+/// The data register of the second serial port
+pub const COM2: u16 = 0x2f8;
+/// The interrupt enable register of the second serial port
+pub const COM2_INTERRUPT_ENABLE: u16 = COM2 + 1;
+/// The modem control register of the second serial port
+pub const COM2_MODEM_CONTROL: u16 = COM2 + 4;
+/// The line status register of the second serial port
+pub const COM2_LINE_STATUS: u16 = COM2 + 5;
+/// The isa-debug-exit device of QEMU, configured in Cargo.toml
+pub const QEMU_EXIT: u16 = 0xf4;
+/// The ACPI power management control port of QEMU
+pub const QEMU_ACPI_SHUTDOWN: u16 = 0x604;
+/// The ACPI power management control port of Bochs and older versions of QEMU
+pub const BOCHS_ACPI_SHUTDOWN: u16 = 0xb004;
+
+/// Reads a byte from an I/O port
+///
+/// # Arguments
+/// ```port```: the port to read from
+///
+/// # Safety
+/// This function is unsafe because reading a port can have side effects, like acknowledging
+/// a device or removing a byte from its buffer.
+pub unsafe fn read_u8(port: u16) -> u8 {
+    Port::new(port).read()
+}
+
+/// Writes a byte to an I/O port
+///
+/// # Arguments
+/// ```port```: the port to write to
+/// ```value```: the byte to write
+///
+/// # Safety
+/// This function is unsafe because writing a port can reconfigure hardware in ways that break
+/// memory safety, like changing which memory a device writes to.
+pub unsafe fn write_u8(port: u16, value: u8) {
+    Port::new(port).write(value);
+}
+
+/// Writes a 16-bit value to an I/O port
+///
+/// # Arguments
+/// ```port```: the port to write to
+/// ```value```: the value to write
+///
+/// # Safety
+/// This function is unsafe for the same reasons as `write_u8`.
+pub unsafe fn write_u16(port: u16, value: u16) {
+    Port::new(port).write(value);
+}
+
+/// Writes a 32-bit value to an I/O port
+///
+/// # Arguments
+/// ```port```: the port to write to
+/// ```value```: the value to write
+///
+/// # Safety
+/// This function is unsafe for the same reasons as `write_u8`.
+pub unsafe fn write_u32(port: u16, value: u32) {
+    Port::new(port).write(value);
+}
+
+/// Checks whether every known port has its own address
+#[test_case]
+fn test_ports_are_distinct() {
+    let ports = [
+        PIT_CHANNEL0,
+        PIT_CHANNEL2,
+        PIT_COMMAND,
+        PS2_DATA,
+        PS2_PORT_B,
+        PS2_STATUS,
+        CMOS_INDEX,
+        CMOS_DATA,
+        VGA_CRTC_INDEX,
+        VGA_CRTC_DATA,
+        PIC1_DATA,
+        COM1,
+        COM1_INTERRUPT_ENABLE,
+        COM1_MODEM_CONTROL,
+        COM1_LINE_STATUS,
+        COM2,
+        COM2_INTERRUPT_ENABLE,
+        COM2_MODEM_CONTROL,
+        COM2_LINE_STATUS,
+        QEMU_EXIT,
+        QEMU_ACPI_SHUTDOWN,
+        BOCHS_ACPI_SHUTDOWN,
+    ];
+    for (i, port) in ports.iter().enumerate() {
+        assert!(!ports[i + 1..].contains(port));
+    }
+}
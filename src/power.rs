@@ -0,0 +1,294 @@
+//! Powering off and rebooting the machine, also as the action taken after a panic.
+//! Only the shutdown ports of QEMU and Bochs are supported, as real hardware needs the ACPI
+//! tables to find its shutdown port. Use `exit_qemu` to end tests instead.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use x86_64::{
+    instructions::{interrupts, tables::lidt},
+    structures::DescriptorTablePointer,
+    VirtAddr,
+};
+
+use crate::{
+    hlt_loop,
+    port::{self, BOCHS_ACPI_SHUTDOWN, CMOS_DATA, CMOS_INDEX, PS2_STATUS, QEMU_ACPI_SHUTDOWN},
+};
+
+/// The value to write to the ACPI shutdown ports to power off
+const ACPI_SHUTDOWN_VALUE: u16 = 0x2000;
+
+/// The keyboard controller command that pulses the CPU reset line
+const RESET_COMMAND: u8 = 0xfe;
+
+/// The CMOS RAM byte the panic count is kept in. It isn't used by the BIOS of QEMU, but may be
+/// used by the firmware of real hardware.
+const PANIC_COUNT_CMOS_INDEX: u8 = 0x48;
+
+// The action to take after a panic, stored as a PanicAction
+static PANIC_ACTION: AtomicU8 = AtomicU8::new(PanicAction::Halt as u8);
+
+/// What the kernel does after a panic has been reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PanicAction {
+    /// Halt for ever, so the panic message stays on the screen
+    Halt,
+    /// Reset the machine, so unattended machines recover by themselves
+    Reboot,
+}
+
+/// Writes values to I/O ports, so the shutdown and reset sequences can be checked without
+/// powering off
+trait PortWriter {
+    /// Writes an 8-bit value to an I/O port
+    ///
+    /// # Safety
+    /// This function is unsafe for the same reasons as `port::write_u8`.
+    unsafe fn write_u8(&mut self, port: u16, value: u8);
+
+    /// Writes a 16-bit value to an I/O port
+    ///
+    /// # Safety
+    /// This function is unsafe for the same reasons as `port::write_u16`.
+    unsafe fn write_u16(&mut self, port: u16, value: u16);
+}
+
+/// Writes to the real I/O ports
+struct HardwarePorts;
+
+impl PortWriter for HardwarePorts {
+    unsafe fn write_u8(&mut self, port: u16, value: u8) {
+        port::write_u8(port, value);
+    }
+
+    unsafe fn write_u16(&mut self, port: u16, value: u16) {
+        port::write_u16(port, value);
+    }
+}
+
+/// Tries every known shutdown port, newest QEMU first
+///
+/// # Arguments
+/// ```ports```: the writer to issue the port writes with
+///
+/// # Safety
+/// This function is unsafe because the machine may power off, or the ports may belong to
+/// another device on real hardware.
+unsafe fn write_shutdown_ports(ports: &mut impl PortWriter) {
+    for port in [QEMU_ACPI_SHUTDOWN, BOCHS_ACPI_SHUTDOWN] {
+        ports.write_u16(port, ACPI_SHUTDOWN_VALUE);
+    }
+}
+
+/// Powers off the virtual machine through its ACPI shutdown port.
+/// Halts for ever if none of the shutdown ports worked, like on real hardware.
+///
+/// # Returns
+/// Never
+pub fn shutdown() -> ! {
+    println!("Shutting down");
+
+    // Safe, as nothing runs after this anyway
+    unsafe { write_shutdown_ports(&mut HardwarePorts) };
+
+    println!("Shutdown failed, halting");
+    hlt_loop();
+}
+
+/// Resets the CPU through the keyboard controller, which works on real hardware too.
+/// Falls back to a triple fault if the keyboard controller didn't reset the CPU.
+///
+/// # Returns
+/// Never
+pub fn reboot() -> ! {
+    // Safe, as nothing runs after this anyway
+    unsafe { write_reset_port(&mut HardwarePorts) };
+    triple_fault();
+}
+
+/// Resets the CPU by causing a triple fault
+///
+/// # Returns
+/// Never
+fn triple_fault() -> ! {
+    // Without an IDT every interrupt causes a double fault, and then a triple fault, which
+    // resets the CPU. Safe, as nothing runs after this anyway.
+    unsafe {
+        lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::new(0),
+        });
+        core::arch::asm!("int3");
+    }
+    hlt_loop();
+}
+
+/// Asks the keyboard controller to pulse the CPU reset line
+///
+/// # Arguments
+/// ```ports```: the writer to issue the port write with
+///
+/// # Safety
+/// This function is unsafe because the machine resets.
+unsafe fn write_reset_port(ports: &mut impl PortWriter) {
+    // Commands are written to the status port
+    ports.write_u8(PS2_STATUS, RESET_COMMAND);
+}
+
+/// Sets what the kernel does after a panic, halting by default
+///
+/// # Arguments
+/// ```action```: the action to take after the next panics
+pub fn set_panic_action(action: PanicAction) {
+    PANIC_ACTION.store(action as u8, Ordering::SeqCst);
+}
+
+/// Returns what the kernel does after a panic
+pub fn panic_action() -> PanicAction {
+    match PANIC_ACTION.load(Ordering::SeqCst) {
+        action if action == PanicAction::Reboot as u8 => PanicAction::Reboot,
+        _ => PanicAction::Halt,
+    }
+}
+
+/// Adds a panic to the panic count in CMOS RAM. Unlike a static, the count survives a reboot,
+/// so panics in the boots before are counted as well.
+///
+/// # Returns
+/// The number of panics, including this one, since the count was reset
+pub fn count_panic() -> u8 {
+    // Run without interrupts, so nothing selects another CMOS byte in between.
+    // Safe, as the byte isn't used by anything else.
+    interrupts::without_interrupts(|| unsafe {
+        let count = read_cmos(PANIC_COUNT_CMOS_INDEX).saturating_add(1);
+        write_cmos(PANIC_COUNT_CMOS_INDEX, count);
+        count
+    })
+}
+
+/// Resets the panic count in CMOS RAM, e.g. once the kernel stops rebooting after panics
+pub fn reset_panic_count() {
+    // Safe, as the byte isn't used by anything else
+    interrupts::without_interrupts(|| unsafe { write_cmos(PANIC_COUNT_CMOS_INDEX, 0) });
+}
+
+/// Reads a byte of the CMOS RAM
+///
+/// # Arguments
+/// ```index```: the index of the byte, below 0x80
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that no other CMOS access runs at
+/// the same time.
+unsafe fn read_cmos(index: u8) -> u8 {
+    port::write_u8(CMOS_INDEX, index);
+    port::read_u8(CMOS_DATA)
+}
+
+/// Writes a byte of the CMOS RAM
+///
+/// # Arguments
+/// ```index```: the index of the byte, below 0x80
+/// ```value```: the value to write
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that no other CMOS access runs at
+/// the same time, and that the byte isn't used by the firmware.
+unsafe fn write_cmos(index: u8, value: u8) {
+    port::write_u8(CMOS_INDEX, index);
+    port::write_u8(CMOS_DATA, value);
+}
+
+/// Takes the panic action, after the panic has been reported
+///
+/// # Returns
+/// Never
+pub fn after_panic() -> ! {
+    // Safe, as nothing runs after this anyway
+    unsafe { take_panic_action(panic_action(), &mut HardwarePorts) };
+    triple_fault();
+}
+
+/// Takes a panic action, returning only if the machine should be reset but the keyboard
+/// controller didn't reset it
+///
+/// # Arguments
+/// ```action```: the action to take
+/// ```ports```: the writer to issue the port writes with
+///
+/// # Safety
+/// This function is unsafe because the machine may reset.
+unsafe fn take_panic_action(action: PanicAction, ports: &mut impl PortWriter) {
+    match action {
+        PanicAction::Halt => hlt_loop(),
+        PanicAction::Reboot => write_reset_port(ports),
+    }
+}
+
+/// Records the port writes instead of issuing them
+#[cfg(test)]
+struct RecordingPorts {
+    writes: [(u16, u16); 2],
+    count: usize,
+}
+
+#[cfg(test)]
+impl RecordingPorts {
+    fn new() -> Self {
+        RecordingPorts {
+            writes: [(0, 0); 2],
+            count: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl PortWriter for RecordingPorts {
+    unsafe fn write_u8(&mut self, port: u16, value: u8) {
+        self.write_u16(port, u16::from(value));
+    }
+
+    unsafe fn write_u16(&mut self, port: u16, value: u16) {
+        self.writes[self.count] = (port, value);
+        self.count += 1;
+    }
+}
+
+/// Checks whether the shutdown ports are written in order, with the shutdown value
+#[test_case]
+fn test_shutdown_port_order() {
+    let mut ports = RecordingPorts::new();
+    unsafe { write_shutdown_ports(&mut ports) };
+    assert_eq!(ports.count, 2);
+    assert_eq!(
+        ports.writes,
+        [(0x604, ACPI_SHUTDOWN_VALUE), (0xb004, ACPI_SHUTDOWN_VALUE)]
+    );
+}
+
+/// Checks whether the reboot panic action writes the reset command to the keyboard controller
+#[test_case]
+fn test_reboot_panic_action() {
+    set_panic_action(PanicAction::Reboot);
+    assert_eq!(panic_action(), PanicAction::Reboot);
+
+    let mut ports = RecordingPorts::new();
+    unsafe { take_panic_action(panic_action(), &mut ports) };
+    assert_eq!(ports.count, 1);
+    assert_eq!(ports.writes[0], (0x64, 0xfe));
+
+    set_panic_action(PanicAction::Halt);
+    assert_eq!(panic_action(), PanicAction::Halt);
+}
+
+/// Checks whether the panic count in CMOS RAM counts up, and can be reset
+#[test_case]
+fn test_panic_count() {
+    reset_panic_count();
+    assert_eq!(count_panic(), 1);
+    assert_eq!(count_panic(), 2);
+    reset_panic_count();
+    assert_eq!(count_panic(), 1);
+    reset_panic_count();
+}
@@ -0,0 +1,133 @@
+//! Kernel randomness.
+//! Uses the `rdrand` instruction when the CPU supports it, and falls back to a xorshift64
+//! pseudo random number generator seeded from the time stamp counter otherwise.
+//! The fallback generator isn't cryptographically secure, but can be seeded to be reproducible.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use lazy_static::lazy_static;
+
+use crate::cpu::CpuFeatures;
+
+/// The number of times to retry `rdrand` before giving up, as recommended by Intel
+const RDRAND_RETRIES: usize = 10;
+
+/// The seed used when the fallback generator is seeded with 0, as xorshift can't leave state 0
+const ZERO_SEED_REPLACEMENT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+lazy_static! {
+    // Only query cpuid once, as it's a relatively slow instruction
+    static ref HAS_RDRAND: bool = CpuFeatures::detect().rdrand;
+}
+
+// The state of the fallback generator, 0 means it hasn't been seeded yet
+static FALLBACK_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Generates a random number
+///
+/// # Returns
+/// A random u64, from `rdrand` if available and successful, from the fallback generator otherwise
+pub fn random_u64() -> u64 {
+    if *HAS_RDRAND {
+        // rdrand can fail under heavy load, retry a few times before falling back
+        for _ in 0..RDRAND_RETRIES {
+            // Safe, as the CPU supports rdrand
+            if let Some(value) = unsafe { rdrand_u64() } {
+                return value;
+            }
+        }
+    }
+    fallback_u64()
+}
+
+/// Runs the `rdrand` instruction once
+///
+/// # Returns
+/// A random u64, or None if the CPU had no random number available
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the CPU supports `rdrand`,
+/// otherwise it causes an invalid opcode exception.
+unsafe fn rdrand_u64() -> Option<u64> {
+    let value: u64;
+    let success: u8;
+    // The carry flag is set if a random number was available
+    core::arch::asm!(
+        "rdrand {value}",
+        "setc {success}",
+        value = out(reg) value,
+        success = out(reg_byte) success,
+        options(nomem, nostack),
+    );
+    (success == 1).then_some(value)
+}
+
+/// Fills a buffer with random bytes
+///
+/// # Arguments
+/// ```buffer```: the buffer to fill
+pub fn fill_bytes(buffer: &mut [u8]) {
+    for chunk in buffer.chunks_mut(8) {
+        let bytes = random_u64().to_ne_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// Seeds the fallback generator, making its output reproducible
+///
+/// # Arguments
+/// ```seed```: the seed to use, 0 is replaced by a fixed non-zero seed
+pub fn seed_fallback(seed: u64) {
+    let seed = if seed == 0 {
+        ZERO_SEED_REPLACEMENT
+    } else {
+        seed
+    };
+    FALLBACK_STATE.store(seed, Ordering::Relaxed);
+}
+
+/// Generates the next number of the fallback generator, seeding it from the TSC if needed
+fn fallback_u64() -> u64 {
+    let mut next = 0;
+    FALLBACK_STATE
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+            let state = if state == 0 { tsc_seed() } else { state };
+            next = xorshift64(state);
+            Some(next)
+        })
+        .expect("The closure always returns Some");
+    next
+}
+
+/// Reads the time stamp counter to use as seed, making sure it isn't 0
+fn tsc_seed() -> u64 {
+    // Safe, as rdtsc is available on every x86_64 CPU
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+    if tsc == 0 {
+        ZERO_SEED_REPLACEMENT
+    } else {
+        tsc
+    }
+}
+
+/// Calculates the next state of a xorshift64 generator
+fn xorshift64(mut state: u64) -> u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+#[test_case]
+fn test_random_u64_differs() {
+    assert_ne!(random_u64(), random_u64());
+}
+
+#[test_case]
+fn test_seeded_fallback_reproducible() {
+    seed_fallback(42);
+    let first = [fallback_u64(), fallback_u64(), fallback_u64()];
+    seed_fallback(42);
+    let second = [fallback_u64(), fallback_u64(), fallback_u64()];
+    assert_eq!(first, second);
+}
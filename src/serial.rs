@@ -1,38 +1,410 @@
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::{task::AtomicWaker, Stream};
 use lazy_static::lazy_static;
 use spin::Mutex;
 use uart_16550::SerialPort;
 
+use crate::port::{
+    self, COM1, COM1_INTERRUPT_ENABLE, COM1_LINE_STATUS, COM1_MODEM_CONTROL, COM2,
+    COM2_INTERRUPT_ENABLE, COM2_LINE_STATUS, COM2_MODEM_CONTROL,
+};
+
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        // create, and initialize a new default port, return it inside a mutex
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
-        Mutex::new(serial_port)
-    };
+    pub static ref SERIAL1: Mutex<SerialPort> = init_port(&COM1_REGISTERS, true);
+    /// A separate channel for logging, so it doesn't mix with the output on the first port.
+    /// Its interrupt stays disabled, as IRQ 3 has no handler.
+    pub static ref SERIAL2: Mutex<SerialPort> = init_port(&COM2_REGISTERS, false);
+}
+
+/// The registers of a uart which are accessed directly, next to its `SerialPort`
+struct UartRegisters {
+    data: u16,
+    interrupt_enable: u16,
+    modem_control: u16,
+    line_status: u16,
 }
 
-/// Sends formatted text over the uart
+/// The registers of the first serial port
+const COM1_REGISTERS: UartRegisters = UartRegisters {
+    data: COM1,
+    interrupt_enable: COM1_INTERRUPT_ENABLE,
+    modem_control: COM1_MODEM_CONTROL,
+    line_status: COM1_LINE_STATUS,
+};
+
+/// The registers of the second serial port
+const COM2_REGISTERS: UartRegisters = UartRegisters {
+    data: COM2,
+    interrupt_enable: COM2_INTERRUPT_ENABLE,
+    modem_control: COM2_MODEM_CONTROL,
+    line_status: COM2_LINE_STATUS,
+};
+
+/// Creates and initializes a serial port. Initializing enables the interrupt for received data,
+/// which is disabled again for ports without an interrupt handler.
 ///
 /// # Arguments
-/// ```args```: the arguments to parse and send
+/// ```registers```: the registers of the port
+/// ```receive_interrupt```: whether to keep the interrupt for received data enabled
 ///
-/// # Panics
-/// If the serial interface can't be written to
+/// # Returns
+/// The port inside a mutex
+fn init_port(registers: &UartRegisters, receive_interrupt: bool) -> Mutex<SerialPort> {
+    // Safe, as the base is the register of a standard serial port
+    let mut serial_port = unsafe { SerialPort::new(registers.data) };
+    serial_port.init();
+    if !receive_interrupt {
+        // Safe, as disabling interrupts has no side effects
+        unsafe { port::write_u8(registers.interrupt_enable, 0) };
+    }
+    Mutex::new(serial_port)
+}
+
+// Whether new lines are sent as \r\n, for terminals that don't return the carriage on \n
+static CRLF: AtomicBool = AtomicBool::new(false);
+
+// Whether a failed write panics, instead of dropping the output
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+static RECEIVED_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static RECEIVED_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Called by the serial interrupt handler
+///
+/// Must not block on allocate.
+pub(crate) fn add_received_byte(byte: u8) {
+    if let Ok(queue) = RECEIVED_QUEUE.try_get() {
+        if queue.push(byte).is_err() {
+            println!("WARNING: Serial queue full; dropping serial input");
+        } else {
+            RECEIVED_WAKER.wake();
+        }
+    }
+    // Without a stream nobody reads the input, so it's dropped silently
+}
+
+/// The bytes received over the first serial port
+pub struct SerialByteStream {
+    _private: (),
+}
+
+impl SerialByteStream {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        RECEIVED_QUEUE
+            .try_init_once(|| ArrayQueue::new(100))
+            .expect("SerialByteStream::new should only be called once");
+
+        // Make sure the port is initialized, as that enables its interrupt
+        lazy_static::initialize(&SERIAL1);
+        SerialByteStream { _private: () }
+    }
+}
+
+impl Stream for SerialByteStream {
+    type Item = u8;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let queue = RECEIVED_QUEUE
+            .try_get()
+            .expect("Serial queue not initialized");
+
+        if let Some(byte) = queue.pop() {
+            return Poll::Ready(Some(byte));
+        }
+
+        RECEIVED_WAKER.register(cx.waker());
+
+        match queue.pop() {
+            Some(byte) => {
+                RECEIVED_WAKER.take();
+                Poll::Ready(Some(byte))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Checks whether the uart works, by sending a byte to itself in loopback mode.
+/// Nothing is sent to the host, and the interrupt enable and modem control registers are restored
+/// afterwards.
+///
+/// # Returns
+/// Whether the byte was received back unchanged
+pub fn loopback_test() -> bool {
+    // The byte to send, with alternating bits to catch stuck lines
+    const TEST_BYTE: u8 = 0xae;
+
+    loopback(&SERIAL1, &COM1_REGISTERS, &[TEST_BYTE])
+}
+
+/// Sends bytes through the driver of a uart in loopback mode, and reads them back from its
+/// receiver. Nothing is sent to the host, and the interrupt enable and modem control registers
+/// are restored afterwards.
+///
+/// # Arguments
+/// ```uart```: the port to send the bytes through
+/// ```registers```: the registers of the port
+/// ```bytes```: the bytes to send, at most 14 to fit in the receive FIFO, without the backspace
+/// and delete characters the driver replaces
+///
+/// # Returns
+/// Whether every byte was received back unchanged, in order
+fn loopback(uart: &Mutex<SerialPort>, registers: &UartRegisters, bytes: &[u8]) -> bool {
+    use x86_64::instructions::interrupts;
+
+    // Connects the transmitter to the receiver in the modem control register
+    const LOOPBACK: u8 = 1 << 4;
+    // The line status bit telling whether a byte was received
+    const DATA_READY: u8 = 1;
+    // The number of times to poll the line status, before giving up
+    const TIMEOUT: usize = 100_000;
+
+    assert!(bytes.len() <= 14, "Too many bytes for the receive FIFO");
+
+    // Hold the lock, so nothing else sends while in loopback mode.
+    // Run without interrupts to prevent deadlocks.
+    interrupts::without_interrupts(|| {
+        let mut serial = uart.lock();
+
+        // Safe, as the registers are restored before the lock is released
+        unsafe {
+            let interrupt_enable = port::read_u8(registers.interrupt_enable);
+            let modem_control = port::read_u8(registers.modem_control);
+
+            // Disable the interrupts, so the handler doesn't take the bytes
+            port::write_u8(registers.interrupt_enable, 0);
+            port::write_u8(registers.modem_control, modem_control | LOOPBACK);
+
+            // Drop bytes received before, they would be mistaken for the sent bytes
+            while port::read_u8(registers.line_status) & DATA_READY != 0 {
+                port::read_u8(registers.data);
+            }
+
+            for &byte in bytes {
+                serial.send(byte);
+            }
+            let received = bytes.iter().all(|&byte| {
+                let ready =
+                    (0..TIMEOUT).any(|_| port::read_u8(registers.line_status) & DATA_READY != 0);
+                ready && port::read_u8(registers.data) == byte
+            });
+
+            port::write_u8(registers.modem_control, modem_control);
+            port::write_u8(registers.interrupt_enable, interrupt_enable);
+            received
+        }
+    })
+}
+
+/// Releases `SERIAL1` and `SERIAL2` by force, also when they're locked by code that will never
+/// release them
+///
+/// # Safety
+/// This function is unsafe because the holder of the lock could still use the port at the same
+/// time. It's only sound once normal execution has stopped, like in a panic handler.
+pub unsafe fn force_unlock() {
+    for port in [&*SERIAL1, &*SERIAL2] {
+        if port.is_locked() {
+            port.force_unlock();
+        }
+    }
+}
+
+/// Sends formatted text over the uart. The output is dropped silently if it can't be written,
+/// like when there's no uart, unless strict mode is enabled with `set_strict`.
+///
+/// # Arguments
+/// ```args```: the arguments to parse and send
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
-    // enable Write functionality
-    use core::fmt::Write;
+    print_to(&SERIAL1, args);
+}
+
+/// Sends formatted text over the second uart, like `_print`
+///
+/// # Arguments
+/// ```args```: the arguments to parse and send
+#[doc(hidden)]
+pub fn _print2(args: core::fmt::Arguments) {
+    print_to(&SERIAL2, args);
+}
+
+/// Writes to the host over the first uart, like `serial_print!`, for functions writing to any
+/// `fmt::Write`
+pub(crate) struct SerialWriter;
+
+impl core::fmt::Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        _print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// Sends formatted text over a uart, handling a failed write with `write_failed`
+///
+/// # Arguments
+/// ```port```: the port to send the text over
+/// ```args```: the arguments to parse and send
+///
+/// # Returns
+/// Whether the text was written
+fn print_to(port: &Mutex<impl core::fmt::Write>, args: core::fmt::Arguments) -> bool {
     use x86_64::instructions::interrupts;
 
     // wait for access to the serial port, write the message over the serial interface.
-    // Exit with an error message if it fails.
     // Run without interrupts to prevent deadlocks
-    interrupts::without_interrupts(|| {
-        SERIAL1
-            .lock()
-            .write_fmt(args)
-            .expect("Printing to serial failed")
+    let written = interrupts::without_interrupts(|| {
+        let mut serial = port.lock();
+        write_checked(
+            &mut CrlfWriter {
+                inner: &mut *serial,
+                crlf: CRLF.load(Ordering::Relaxed),
+            },
+            args,
+        )
     });
+    if !written {
+        write_failed();
+    }
+    written
+}
+
+/// Handles a failed write, by panicking in strict mode and doing nothing otherwise
+///
+/// # Panics
+/// In strict mode. Strict mode is disabled first, so printing the panic over serial can't panic
+/// again.
+fn write_failed() {
+    if STRICT.swap(false, Ordering::Relaxed) {
+        panic!("Printing to serial failed");
+    }
+}
+
+/// Sets whether a failed write panics, off by default. Enabled by the test runner, as the test
+/// results are only reported over serial.
+///
+/// # Arguments
+/// ```strict```: whether to panic when output can't be written, instead of dropping it
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Writes formatted text
+///
+/// # Arguments
+/// ```output```: where to write the text to
+/// ```args```: the arguments to parse and write
+///
+/// # Returns
+/// Whether the text was written, a failed write is ignored otherwise
+fn write_checked(output: &mut impl core::fmt::Write, args: core::fmt::Arguments) -> bool {
+    output.write_fmt(args).is_ok()
+}
+
+/// Sets whether new lines are sent as `\r\n` instead of `\n`, off by default
+///
+/// # Arguments
+/// ```crlf```: whether to send a carriage return before every new line
+pub fn set_crlf(crlf: bool) {
+    CRLF.store(crlf, Ordering::Relaxed);
+}
+
+/// Writes to another writer, optionally sending every `\n` as `\r\n`
+struct CrlfWriter<'a, W: core::fmt::Write> {
+    inner: &'a mut W,
+    crlf: bool,
+}
+
+impl<W: core::fmt::Write> core::fmt::Write for CrlfWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if !self.crlf {
+            return self.inner.write_str(s);
+        }
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.inner.write_str("\r\n")?;
+            }
+            self.inner.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Mirrors formatted VGA text over the uart, prefixing every line with `[kernel] `
+///
+/// # Arguments
+/// ```args```: the arguments to parse and send
+#[cfg(feature = "mirror-serial")]
+pub(crate) fn _mirror(args: core::fmt::Arguments) {
+    // Whether the next mirrored text starts a new line, shared by all calls
+    static AT_LINE_START: AtomicBool = AtomicBool::new(true);
+
+    mirror_to(&SERIAL1, &AT_LINE_START, args);
+}
+
+/// Mirrors formatted text over a uart, prefixing every line with `[kernel] `
+///
+/// # Arguments
+/// ```port```: the port to send the text over
+/// ```at_line_start```: whether the next mirrored text starts a new line, updated after writing
+/// ```args```: the arguments to parse and send
+#[cfg(feature = "mirror-serial")]
+fn mirror_to(
+    port: &Mutex<impl core::fmt::Write>,
+    at_line_start: &AtomicBool,
+    args: core::fmt::Arguments,
+) {
+    use core::fmt::Write;
+
+    // Skip mirroring instead of deadlocking, if the serial port is already in use.
+    // Called with interrupts disabled by `console::_print` and `vga_buffer::print_to_screen`.
+    if let Some(mut serial) = port.try_lock() {
+        let mut line_start = at_line_start.load(Ordering::Relaxed);
+        let mut serial = CrlfWriter {
+            inner: &mut *serial,
+            crlf: CRLF.load(Ordering::Relaxed),
+        };
+        let mut writer = PrefixedWriter {
+            inner: &mut serial,
+            prefix: "[kernel] ",
+            at_line_start: &mut line_start,
+        };
+        // Mirroring is best-effort, so a failed write is ignored
+        let _ = writer.write_fmt(args);
+        at_line_start.store(line_start, Ordering::Relaxed);
+    }
+}
+
+/// Writes to another writer, inserting a prefix at the start of every line
+#[cfg(feature = "mirror-serial")]
+struct PrefixedWriter<'a, W: core::fmt::Write> {
+    inner: &'a mut W,
+    prefix: &'static str,
+    at_line_start: &'a mut bool,
+}
+
+#[cfg(feature = "mirror-serial")]
+impl<W: core::fmt::Write> core::fmt::Write for PrefixedWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if *self.at_line_start {
+                self.inner.write_str(self.prefix)?;
+            }
+            self.inner.write_str(line)?;
+            *self.at_line_start = line.ends_with('\n');
+        }
+        Ok(())
+    }
 }
 
 /// Prints to the host through the serial interface
@@ -50,3 +422,155 @@ macro_rules! serial_println {
     ($fmt:expr) => ($crate::serial_print!(concat!($fmt, "\n")));
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(concat!($fmt, "\n"), $($arg)*));
 }
+
+/// Prints to the host through the second serial interface
+#[macro_export]
+macro_rules! serial2_print {
+    ($($arg:tt)*) => {
+        $crate::serial::_print2(format_args!($($arg)*));
+    };
+}
+
+/// Prints to the host through the second serial interface, appending a new line
+#[macro_export]
+macro_rules! serial2_println {
+    () => ($crate::serial2_print!("\n"));
+    ($fmt:expr) => ($crate::serial2_print!(concat!($fmt, "\n")));
+    ($fmt:expr, $($arg:tt)*) => ($crate::serial2_print!(concat!($fmt, "\n"), $($arg)*));
+}
+
+/// Checks whether every mirrored line gets the prefix, also when written in pieces
+#[cfg(feature = "mirror-serial")]
+#[test_case]
+fn test_mirror_prefix() {
+    use alloc::string::String;
+
+    let port = Mutex::new(String::new());
+    let at_line_start = AtomicBool::new(true);
+    mirror_to(&port, &at_line_start, format_args!("first\nsec"));
+    mirror_to(&port, &at_line_start, format_args!("ond\n"));
+    assert_eq!(*port.lock(), "[kernel] first\n[kernel] second\n");
+    assert!(at_line_start.load(Ordering::Relaxed));
+
+    // The text is skipped while the port is in use
+    let guard = port.lock();
+    mirror_to(&port, &at_line_start, format_args!("skipped\n"));
+    drop(guard);
+    assert_eq!(*port.lock(), "[kernel] first\n[kernel] second\n");
+}
+
+/// Checks whether a new line is written as \r\n only when CRLF mode is enabled
+#[test_case]
+fn test_crlf_writer() {
+    use alloc::string::String;
+    use core::fmt::Write;
+
+    let mut output = String::new();
+    write!(
+        CrlfWriter {
+            inner: &mut output,
+            crlf: true
+        },
+        "a\nb\n"
+    )
+    .expect("Write failed");
+    assert_eq!(output.as_bytes(), b"a\r\nb\r\n");
+
+    let mut output = String::new();
+    write!(
+        CrlfWriter {
+            inner: &mut output,
+            crlf: false
+        },
+        "a\n"
+    )
+    .expect("Write failed");
+    assert_eq!(output, "a\n");
+}
+
+/// Checks whether a failing write is reported instead of panicking
+#[test_case]
+fn test_write_error_reported() {
+    use alloc::string::String;
+
+    // Outside of strict mode, a failed write is dropped without panicking
+    let strict = STRICT.swap(false, Ordering::Relaxed);
+    let failed = !print_to(&Mutex::new(crate::FailingWriter), format_args!("lost"));
+    set_strict(strict);
+    assert!(failed);
+
+    let port = Mutex::new(String::new());
+    assert!(print_to(&port, format_args!("kept")));
+    assert_eq!(*port.lock(), "kept");
+}
+
+/// Checks whether interrupts are disabled while the port is locked, so an interrupt handler
+/// printing over serial can't wait for the lock held by the code it interrupted
+#[test_case]
+fn test_print_masks_interrupts() {
+    use x86_64::instructions::interrupts;
+
+    // Records whether interrupts were enabled during any write
+    struct InterruptCheckingWriter {
+        interrupts_enabled: bool,
+    }
+
+    impl core::fmt::Write for InterruptCheckingWriter {
+        fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+            self.interrupts_enabled |= interrupts::are_enabled();
+            Ok(())
+        }
+    }
+
+    let port = Mutex::new(InterruptCheckingWriter {
+        interrupts_enabled: false,
+    });
+    assert!(interrupts::are_enabled());
+    print_to(&port, format_args!("test_print_masks_interrupts {}", 42));
+
+    assert!(!port.is_locked());
+    assert!(!port.lock().interrupts_enabled);
+    assert!(interrupts::are_enabled());
+}
+
+/// Checks whether printing completes after the port is unlocked by force, like the panic handler
+/// does when it interrupted code holding the lock
+#[test_case]
+fn test_print_after_force_unlock() {
+    use x86_64::instructions::interrupts;
+
+    // Disable interrupts, so the timer doesn't run while the lock is leaked
+    interrupts::without_interrupts(|| {
+        // leak a lock, as if the panic happened in the middle of printing
+        core::mem::forget(SERIAL1.lock());
+        assert!(SERIAL1.is_locked());
+
+        // Safe, as the leaked lock isn't used anymore
+        unsafe { force_unlock() };
+        serial_println!("test_print_after_force_unlock output");
+        assert!(!SERIAL1.is_locked());
+    });
+}
+
+/// Checks whether the second port exists and echoes what its driver sends in loopback mode,
+/// while the first one is in use, and the first one still works afterwards
+#[test_case]
+fn test_serial2_independent() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        let _serial1 = SERIAL1.lock();
+        assert!(loopback(&SERIAL2, &COM2_REGISTERS, b"serial2"));
+        assert!(!SERIAL2.is_locked());
+    });
+
+    // COM2 has no interrupt handler, so its interrupt must stay disabled
+    assert_eq!(unsafe { port::read_u8(COM2_INTERRUPT_ENABLE) }, 0);
+    assert!(loopback_test());
+}
+
+/// Checks whether a byte sent in loopback mode is received back
+#[test_case]
+fn test_loopback() {
+    assert!(loopback_test());
+}
@@ -0,0 +1,187 @@
+//! A line based command protocol over the first serial port, to script the kernel from the host.
+//! Every command is a single line, answered with a single line:
+//! - `PING` is answered with `PONG`
+//! - `MEM` is answered with the heap statistics
+//! - `EXIT <code>` is answered with `OK`, then exits QEMU with success for 0 and failure otherwise
+//!
+//! Unknown commands and lines that are too long are answered with `ERR <reason>`.
+
+use core::fmt::{self, Write};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::{
+    allocator::{self, HEAP_START},
+    exit_qemu,
+    serial::{SerialByteStream, SerialWriter},
+    QemuExitCode,
+};
+
+/// The maximum length of a command, without the new line
+const MAX_LINE_LENGTH: usize = 64;
+
+/// A command sent by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Ping,
+    Mem,
+    Exit(QemuExitCode),
+}
+
+/// Parses a command line
+///
+/// # Arguments
+/// ```line```: the line, without the new line
+///
+/// # Returns
+/// The command, or the reason it isn't a valid command
+fn parse_command(line: &str) -> Result<Command, &'static str> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some("PING") => Command::Ping,
+        Some("MEM") => Command::Mem,
+        Some("EXIT") => match words.next().map(str::parse::<u32>) {
+            Some(Ok(0)) => Command::Exit(QemuExitCode::Success),
+            Some(Ok(_)) => Command::Exit(QemuExitCode::Failed),
+            _ => return Err("invalid exit code"),
+        },
+        Some(_) => return Err("unknown command"),
+        None => return Err("empty command"),
+    };
+
+    if words.next().is_some() {
+        return Err("too many arguments");
+    }
+    Ok(command)
+}
+
+/// Collects received bytes into lines
+struct LineBuffer {
+    bytes: [u8; MAX_LINE_LENGTH],
+    len: usize,
+    // Set when the current line didn't fit, so the rest of it is skipped
+    overflowed: bool,
+}
+
+impl LineBuffer {
+    /// Creates an empty line buffer
+    fn new() -> Self {
+        LineBuffer {
+            bytes: [0; MAX_LINE_LENGTH],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Adds a received byte to the current line
+    ///
+    /// # Arguments
+    /// ```byte```: the received byte
+    ///
+    /// # Returns
+    /// The line once a new line is received, or an error if the line was too long or not UTF-8
+    fn push(&mut self, byte: u8) -> Option<Result<&str, &'static str>> {
+        match byte {
+            b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflowed) {
+                    return Some(Err("line too long"));
+                }
+                Some(core::str::from_utf8(&self.bytes[..len]).map_err(|_| "invalid UTF-8"))
+            }
+            // Ignore carriage returns, so both \n and \r\n end a line
+            b'\r' => None,
+            _ if self.len == MAX_LINE_LENGTH => {
+                self.overflowed = true;
+                None
+            }
+            _ => {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+/// Executes a command line, writing the reply
+///
+/// # Arguments
+/// ```line```: the command line, or the reason it couldn't be read
+/// ```output```: where to write the reply to
+fn execute(line: Result<&str, &'static str>, output: &mut impl Write) -> fmt::Result {
+    match line.and_then(parse_command) {
+        Ok(Command::Ping) => writeln!(output, "PONG"),
+        Ok(Command::Mem) => writeln!(
+            output,
+            "MEM heap_start={:#x} heap_size={} heap_initialized={}",
+            HEAP_START,
+            allocator::heap_size(),
+            allocator::is_heap_initialized()
+        ),
+        Ok(Command::Exit(exit_code)) => {
+            writeln!(output, "OK")?;
+            exit_qemu(exit_code);
+            // Still running, if the exit device isn't there
+            writeln!(output, "ERR exit failed")
+        }
+        Err(reason) => writeln!(output, "ERR {}", reason),
+    }
+}
+
+/// Reads commands from a byte stream until it ends, writing the replies
+///
+/// # Arguments
+/// ```bytes```: the received bytes
+/// ```output```: where to write the replies to
+pub async fn run_commands(mut bytes: impl Stream<Item = u8> + Unpin, output: &mut impl Write) {
+    let mut line = LineBuffer::new();
+    while let Some(byte) = bytes.next().await {
+        if let Some(result) = line.push(byte) {
+            // Replying is best-effort, the host may not be listening
+            let _ = execute(result, output);
+        }
+    }
+}
+
+/// Answers the commands the host sends over the first serial port
+pub async fn serial_commands() {
+    run_commands(SerialByteStream::new(), &mut SerialWriter).await;
+}
+
+/// Checks whether commands are parsed, and invalid commands are rejected
+#[test_case]
+fn test_parse_command() {
+    assert_eq!(parse_command("PING"), Ok(Command::Ping));
+    assert_eq!(parse_command(" MEM "), Ok(Command::Mem));
+    assert_eq!(
+        parse_command("EXIT 0"),
+        Ok(Command::Exit(QemuExitCode::Success))
+    );
+    assert_eq!(
+        parse_command("EXIT 3"),
+        Ok(Command::Exit(QemuExitCode::Failed))
+    );
+    assert_eq!(parse_command("EXIT"), Err("invalid exit code"));
+    assert_eq!(parse_command("PING PONG"), Err("too many arguments"));
+    assert_eq!(parse_command("REBOOT"), Err("unknown command"));
+}
+
+/// Checks whether lines split over several pushes are joined, and long lines are rejected
+#[test_case]
+fn test_line_buffer() {
+    let mut line = LineBuffer::new();
+    for &byte in b"PI" {
+        assert_eq!(line.push(byte), None);
+    }
+    for &byte in b"NG\r" {
+        assert_eq!(line.push(byte), None);
+    }
+    assert_eq!(line.push(b'\n'), Some(Ok("PING")));
+
+    for _ in 0..MAX_LINE_LENGTH + 1 {
+        assert_eq!(line.push(b'x'), None);
+    }
+    assert_eq!(line.push(b'\n'), Some(Err("line too long")));
+    assert_eq!(line.push(b'\n'), Some(Ok("")));
+}
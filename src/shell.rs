@@ -0,0 +1,187 @@
+//! A tiny interactive shell on the keyboard and the screen, running one command per line:
+//! - `help` lists the commands
+//! - `clear` clears the screen
+//! - `mem` shows the usable memory and the heap usage
+//! - `uptime` shows the time since boot
+//! - `echo <text>` prints the text
+//!
+//! Unknown commands print an error line. Ctrl-C discards the typed line, while Ctrl-Q, and Ctrl-D
+//! on an empty line, end the shell.
+
+use core::fmt::{self, Write};
+
+use crate::{
+    allocator, console,
+    memory::ByteSize,
+    task::line_reader::{read_line, Line},
+    time,
+    vga_buffer::{Role, WRITER},
+};
+
+/// The prompt shown before every command
+const PROMPT: &str = "> ";
+
+/// The list of commands shown by `help`
+const HELP: &str = "Commands: help, clear, mem, uptime, echo <text>";
+
+/// A built-in command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command<'a> {
+    Help,
+    Clear,
+    Mem,
+    Uptime,
+    Echo(&'a str),
+}
+
+/// Parses a command line
+///
+/// # Arguments
+/// ```line```: the line, without the new line
+///
+/// # Returns
+/// The command, None for an empty line, or the error to show for an invalid command
+fn parse_command(line: &str) -> Result<Option<Command<'_>>, &'static str> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    // Keep the spacing of the arguments, so echo prints the text as typed
+    let (name, args) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(name, args)| (name, args.trim_start()));
+    let command = match (name, args) {
+        ("help", "") => Command::Help,
+        ("clear", "") => Command::Clear,
+        ("mem", "") => Command::Mem,
+        ("uptime", "") => Command::Uptime,
+        ("echo", text) => Command::Echo(text),
+        ("help" | "clear" | "mem" | "uptime", _) => return Err("too many arguments"),
+        _ => return Err("unknown command"),
+    };
+    Ok(Some(command))
+}
+
+/// Runs a command line, writing its output
+///
+/// # Arguments
+/// ```line```: the command line, without the new line
+/// ```usable_memory```: the amount of usable memory in bytes, shown by `mem`
+/// ```output```: where to write the output to
+///
+/// # Returns
+/// Whether writing the output succeeded, or the reason the line isn't a valid command, in which
+/// case nothing is written
+fn run_line(
+    line: &str,
+    usable_memory: u64,
+    output: &mut impl Write,
+) -> Result<fmt::Result, &'static str> {
+    Ok(match parse_command(line)? {
+        None => Ok(()),
+        Some(command) => run_command(command, usable_memory, output),
+    })
+}
+
+/// Runs a command, writing its output
+///
+/// # Arguments
+/// ```command```: the command to run
+/// ```usable_memory```: the amount of usable memory in bytes, shown by `mem`
+/// ```output```: where to write the output to
+fn run_command(command: Command, usable_memory: u64, output: &mut impl Write) -> fmt::Result {
+    match command {
+        Command::Help => writeln!(output, "{}", HELP),
+        Command::Clear => {
+            use x86_64::instructions::interrupts;
+
+            // Run without interrupts to prevent deadlocks
+            interrupts::without_interrupts(|| WRITER.write().clear_screen());
+            Ok(())
+        }
+        Command::Mem => writeln!(
+            output,
+            "Usable memory: {}, heap: {} of {} in use",
+            ByteSize(usable_memory),
+            ByteSize(allocator::heap_bytes_in_use() as u64),
+            ByteSize(allocator::heap_size() as u64)
+        ),
+        Command::Uptime => writeln!(output, "Up for {} ms", time::uptime_ms()),
+        Command::Echo(text) => writeln!(output, "{}", text),
+    }
+}
+
+/// Writes output to the screen, in the color of a role
+struct ScreenWriter(Role);
+
+impl Write for ScreenWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        console::_print_as(self.0, format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// Reads commands from the keyboard, running every command once Enter is pressed.
+/// Takes the scancodes from the same queue as `keyboard::print_keypresses`, so only one of them
+/// should run.
+///
+/// # Arguments
+/// ```usable_memory```: the amount of usable memory in bytes, shown by `mem`
+///
+/// # Returns
+/// How the shell was ended, either `Line::PowerOff` for Ctrl-Q or `Line::EndOfInput` for Ctrl-D
+pub async fn shell(usable_memory: u64) -> Line {
+    loop {
+        console::_print_as(Role::Prompt, format_args!("{}", PROMPT));
+        let line = match read_line().await {
+            Line::Entered(line) => line,
+            Line::Cancelled => continue,
+            end @ (Line::EndOfInput | Line::PowerOff) => return end,
+        };
+
+        // Writing to the screen can't fail
+        if let Err(reason) = run_line(&line, usable_memory, &mut ScreenWriter(Role::Normal)) {
+            // Show invalid commands in the error color
+            let _ = writeln!(
+                ScreenWriter(Role::Error),
+                "Error: {}: {}",
+                reason,
+                line.trim()
+            );
+        }
+    }
+}
+
+/// Checks whether commands are parsed, and invalid commands are rejected
+#[test_case]
+fn test_parse_command() {
+    assert_eq!(parse_command("help"), Ok(Some(Command::Help)));
+    assert_eq!(parse_command(" mem "), Ok(Some(Command::Mem)));
+    assert_eq!(parse_command(""), Ok(None));
+    assert_eq!(
+        parse_command("echo hello  world"),
+        Ok(Some(Command::Echo("hello  world")))
+    );
+    assert_eq!(parse_command("echo"), Ok(Some(Command::Echo(""))));
+    assert_eq!(parse_command("uptime now"), Err("too many arguments"));
+    assert_eq!(parse_command("reboot"), Err("unknown command"));
+}
+
+/// Checks whether echo prints its text, and unknown commands are reported without output
+#[test_case]
+fn test_run_line() {
+    use alloc::string::String;
+
+    let mut output = String::new();
+    assert_eq!(run_line("echo hello", 0, &mut output), Ok(Ok(())));
+    assert_eq!(output, "hello\n");
+
+    let mut output = String::new();
+    assert_eq!(run_line("reboot", 0, &mut output), Err("unknown command"));
+    assert!(output.is_empty());
+
+    let mut output = String::new();
+    assert_eq!(run_line("mem", 1024 * 1024, &mut output), Ok(Ok(())));
+    assert!(output.starts_with("Usable memory: 1 MiB, heap: "));
+}
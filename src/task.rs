@@ -1,31 +1,222 @@
 use core::{
+    any::Any,
+    fmt,
     future::Future,
     pin::Pin,
-    sync::atomic::{AtomicU64, Ordering},
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicU64, Ordering},
     task::{Context, Poll},
 };
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, sync::Arc};
+use futures_util::task::AtomicWaker;
 
+pub mod channel;
 pub mod executor;
 pub mod keyboard;
+pub mod line_reader;
 pub mod simple_executor;
+pub mod sync;
+
+/// The error returned by a failed task.
+/// Panics can't be caught with `panic = "abort"`, so tasks report failures by returning this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskError(pub &'static str);
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// The output types a task future can have, either `()` or `Result<(), TaskError>`
+pub trait TaskOutput {
+    fn into_result(self) -> Result<(), TaskError>;
+}
+
+impl TaskOutput for () {
+    fn into_result(self) -> Result<(), TaskError> {
+        Ok(())
+    }
+}
+
+impl TaskOutput for Result<(), TaskError> {
+    fn into_result(self) -> Result<(), TaskError> {
+        self
+    }
+}
+
+/// The typed context a task can carry, see `Task::new_with_context`
+type TaskContext = Option<Box<dyn Any>>;
+
+// The context of the task being polled, or null outside of a poll and while it's borrowed by
+// `with_context`
+static CURRENT_CONTEXT: AtomicPtr<TaskContext> = AtomicPtr::new(ptr::null_mut());
 
 pub struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    name: &'static str,
+    future: Pin<Box<dyn Future<Output = Result<(), TaskError>>>>,
+    context: TaskContext,
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+    pub fn new(future: impl Future<Output = impl TaskOutput> + 'static) -> Task {
+        Task::new_named("unnamed", future)
+    }
+
+    /// Creates a task with a name, shown in executor diagnostics
+    ///
+    /// # Arguments
+    /// ```name```: the name of the task
+    /// ```future```: the future to run as task
+    pub fn new_named(
+        name: &'static str,
+        future: impl Future<Output = impl TaskOutput> + 'static,
+    ) -> Task {
         Task {
             id: TaskId::new(),
-            future: Box::pin(future),
+            name,
+            future: Box::pin(async move { future.await.into_result() }),
+            context: None,
         }
     }
 
-    fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+    /// Creates a task carrying a typed context, which its future can access with `with_context`
+    /// instead of using globals
+    ///
+    /// # Arguments
+    /// ```context```: the value the task can access while it runs
+    /// ```future```: the future to run as task
+    pub fn new_with_context(
+        context: impl Any,
+        future: impl Future<Output = impl TaskOutput> + 'static,
+    ) -> Task {
+        Task {
+            context: Some(Box::new(context)),
+            ..Task::new(future)
+        }
+    }
+
+    /// Creates a task together with a handle to await its output
+    ///
+    /// # Arguments
+    /// ```future```: the future to run as task
+    ///
+    /// # Returns
+    /// The task, and a handle resolving to the output of the future once the task completes.
+    /// Dropping the handle doesn't stop the task.
+    pub fn new_joinable<T: 'static>(
+        future: impl Future<Output = T> + 'static,
+    ) -> (Task, JoinHandle<T>) {
+        let slot = Arc::new(JoinSlot {
+            output: spin::Mutex::new(None),
+            waker: AtomicWaker::new(),
+        });
+        let handle = JoinHandle { slot: slot.clone() };
+        let task = Task::new(async move {
+            let output = future.await;
+            *slot.output.lock() = Some(output);
+            slot.waker.wake();
+        });
+        (task, handle)
+    }
+
+    /// Returns the name of the task
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<Result<(), TaskError>> {
+        // Make the context of this task available to `with_context` while it's polled, restoring
+        // the previous one afterwards in case a task polls another task
+        let previous = CURRENT_CONTEXT.swap(&mut self.context, Ordering::Relaxed);
+        let result = self.future.as_mut().poll(context);
+        CURRENT_CONTEXT.store(previous, Ordering::Relaxed);
+        result
+    }
+}
+
+/// Accesses the context of the running task, as set by `Task::new_with_context`
+///
+/// # Arguments
+/// ```f```: called with the context, or None outside of a task, for a task without context, for
+/// a context of another type, or when called from within `f` itself
+///
+/// # Returns
+/// The value returned by `f`
+pub fn with_context<T: 'static, R>(f: impl FnOnce(Option<&mut T>) -> R) -> R {
+    // Take the pointer while the context is borrowed, so a nested call can't borrow it again
+    let current = CURRENT_CONTEXT.swap(ptr::null_mut(), Ordering::Relaxed);
+
+    // Safe, as the pointer is only set while the task owning the context is being polled
+    let context = unsafe { current.as_mut() }
+        .and_then(|context| context.as_mut())
+        .and_then(|context| context.downcast_mut::<T>());
+    let result = f(context);
+
+    CURRENT_CONTEXT.store(current, Ordering::Relaxed);
+    result
+}
+
+/// Gives other tasks a turn, by returning `Pending` once before completing
+///
+/// # Returns
+/// A future that completes the second time it's polled
+pub fn yield_now() -> impl Future<Output = ()> {
+    YieldNow { yielded: false }
+}
+
+/// The future returned by `yield_now`
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+
+        // Reschedule this task immediately, as nothing else will wake it
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// The output of a joinable task, shared between the task and its handle
+struct JoinSlot<T> {
+    output: spin::Mutex<Option<T>>,
+    waker: AtomicWaker,
+}
+
+/// A handle to await the output of a task created by `Task::new_joinable`
+pub struct JoinHandle<T> {
+    slot: Arc<JoinSlot<T>>,
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<T> {
+        // The task may have completed before the handle is awaited
+        if let Some(output) = self.slot.output.lock().take() {
+            return Poll::Ready(output);
+        }
+
+        self.slot.waker.register(context.waker());
+
+        // Try again, the task may have completed before the waker was registered
+        match self.slot.output.lock().take() {
+            Some(output) => {
+                self.slot.waker.take();
+                Poll::Ready(output)
+            }
+            None => Poll::Pending,
+        }
     }
 }
 
@@ -38,3 +229,158 @@ impl TaskId {
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
 }
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Describes a task as "{id} ({name})", as used in executor diagnostics
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.id, self.name)
+    }
+}
+
+/// Checks whether yielding tasks take turns instead of running to completion one by one
+#[test_case]
+fn test_yield_now_interleaves() {
+    use alloc::{sync::Arc, vec::Vec};
+    use spin::Mutex;
+
+    async fn record(id: u8, order: Arc<Mutex<Vec<u8>>>) {
+        for _ in 0..3 {
+            order.lock().push(id);
+            yield_now().await;
+        }
+    }
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = executor::Executor::new();
+    executor.spawn(Task::new(record(0, order.clone())));
+    executor.spawn(Task::new(record(1, order.clone())));
+    executor.run_until_idle();
+
+    assert_eq!(*order.lock(), [0, 1, 0, 1, 0, 1]);
+}
+
+/// Checks whether task names show up in the lines the executor logs with debug-executor
+#[test_case]
+fn test_named_task_descriptions() {
+    use alloc::{format, string::String};
+
+    let first = Task::new_named("first", async {});
+    let second = Task::new_named("second", async {});
+    assert_eq!(Task::new(async {}).name(), "unnamed");
+
+    let mut log = String::new();
+    for task in [&first, &second] {
+        executor::log_spawned(&mut log, task).expect("Writing failed");
+        executor::log_completed(&mut log, task).expect("Writing failed");
+    }
+    assert_eq!(
+        log,
+        format!(
+            "spawned task {0} (first)\ntask {0} (first) completed\n\
+             spawned task {1} (second)\ntask {1} (second) completed\n",
+            first.id, second.id
+        )
+    );
+
+    let mut executor = executor::Executor::new();
+    executor.spawn(first);
+    executor.spawn(second);
+    executor.run_until_idle();
+}
+
+/// Checks whether a failing task is removed without stopping the other tasks
+#[test_case]
+fn test_failed_task_isolated() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::AtomicUsize;
+
+    async fn count(counter: Arc<AtomicUsize>) {
+        for _ in 0..3 {
+            yield_now().await;
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut executor = executor::Executor::new();
+    executor.spawn(Task::new(count(counter.clone())));
+    executor.spawn(Task::new_named("failing", async {
+        yield_now().await;
+        Err::<(), _>(TaskError("intentional failure"))
+    }));
+    executor.run_until_idle();
+
+    assert_eq!(counter.load(Ordering::Relaxed), 3);
+}
+
+/// Checks whether the output of a task can be awaited from another task
+#[test_case]
+fn test_join_handle() {
+    use core::sync::atomic::AtomicUsize;
+
+    let result = Arc::new(AtomicUsize::new(0));
+    let mut executor = executor::Executor::new();
+
+    // Spawn the awaiting task first, so it has to wait for the output
+    let (task, handle) = Task::new_joinable(async {
+        yield_now().await;
+        42
+    });
+    let awaited = result.clone();
+    executor.spawn(Task::new(async move {
+        awaited.store(handle.await, Ordering::Relaxed);
+    }));
+    executor.spawn(task);
+
+    // A dropped handle shouldn't stop the task
+    let dropped = executor.spawn_joinable(async { 1 });
+    drop(dropped);
+
+    executor.run_until_idle();
+    assert_eq!(result.load(Ordering::Relaxed), 42);
+
+    // A handle awaited after its task completed should still resolve
+    let finished = executor.spawn_joinable(async { 7 });
+    executor.run_until_idle();
+    let awaited = result.clone();
+    executor.spawn(Task::new(async move {
+        awaited.store(finished.await, Ordering::Relaxed);
+    }));
+    executor.run_until_idle();
+    assert_eq!(result.load(Ordering::Relaxed), 7);
+}
+
+/// Checks whether a task can access its own context, and tasks without one get None
+#[test_case]
+fn test_task_context() {
+    use core::sync::atomic::AtomicUsize;
+
+    let result = Arc::new(AtomicUsize::new(0));
+    let mut executor = executor::Executor::new();
+
+    let stored = result.clone();
+    executor.spawn(Task::new_with_context(40usize, async move {
+        with_context(|counter: Option<&mut usize>| *counter.unwrap() += 1);
+        yield_now().await;
+        with_context(|counter: Option<&mut usize>| *counter.unwrap() += 1);
+
+        let value = with_context(|counter: Option<&mut usize>| *counter.unwrap());
+        stored.store(value, Ordering::Relaxed);
+
+        // A context of another type isn't returned
+        assert!(with_context(|context: Option<&mut u8>| context.is_none()));
+    }));
+    executor.spawn(Task::new(async {
+        assert!(with_context(|context: Option<&mut usize>| context.is_none()));
+    }));
+    executor.run_until_idle();
+
+    assert_eq!(result.load(Ordering::Relaxed), 42);
+    assert!(with_context(|context: Option<&mut usize>| context.is_none()));
+}
@@ -0,0 +1,217 @@
+//! A bounded multi-producer, single-consumer channel for communication between async tasks.
+//! Sending suspends the task while the channel is full, receiving suspends it while the channel
+//! is empty. Both sides wake each other through the executor when progress can be made.
+
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Poll, Waker},
+};
+
+use alloc::{collections::VecDeque, sync::Arc};
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+
+/// The error returned when sending to a channel without receiver, containing the unsent value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// The error returned when receiving from an empty channel without senders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// The state shared by the senders and the receiver of a channel
+struct Channel<T> {
+    queue: ArrayQueue<T>,
+    receiver_waker: AtomicWaker,
+    sender_wakers: spin::Mutex<VecDeque<Waker>>,
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+}
+
+impl<T> Channel<T> {
+    /// Wakes every sender waiting for space in the channel
+    fn wake_senders(&self) {
+        let wakers = core::mem::take(&mut *self.sender_wakers.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates a bounded channel
+///
+/// # Arguments
+/// ```capacity```: the maximum number of values in the channel, must be larger than 0
+///
+/// # Returns
+/// The sending and receiving side of the channel
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        queue: ArrayQueue::new(capacity),
+        receiver_waker: AtomicWaker::new(),
+        sender_wakers: spin::Mutex::new(VecDeque::new()),
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+/// The sending side of a channel, can be cloned to create multiple producers
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends a value, suspending the current task while the channel is full
+    ///
+    /// # Arguments
+    /// ```value```: the value to send
+    ///
+    /// # Returns
+    /// An error containing the value, if the receiver has been dropped
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let channel = &self.channel;
+        let mut value = Some(value);
+        poll_fn(|context| {
+            let item = value.take().expect("Send polled after completion");
+            if !channel.receiver_alive.load(Ordering::Acquire) {
+                return Poll::Ready(Err(SendError(item)));
+            }
+
+            let item = match channel.queue.push(item) {
+                Ok(()) => {
+                    channel.receiver_waker.wake();
+                    return Poll::Ready(Ok(()));
+                }
+                Err(item) => item,
+            };
+
+            channel
+                .sender_wakers
+                .lock()
+                .push_back(context.waker().clone());
+
+            // Try again, the receiver may have made space before the waker was registered
+            match channel.queue.push(item) {
+                Ok(()) => {
+                    channel.receiver_waker.wake();
+                    Poll::Ready(Ok(()))
+                }
+                Err(item) => {
+                    value = Some(item);
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Wake the receiver if this was the last sender, so it can notice the channel is closed
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.receiver_waker.wake();
+        }
+    }
+}
+
+/// The receiving side of a channel
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    /// Receives a value, suspending the current task while the channel is empty
+    ///
+    /// # Returns
+    /// The received value, or an error if the channel is empty and all senders have been dropped
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        poll_fn(|context| {
+            if let Some(result) = self.try_recv() {
+                return Poll::Ready(result);
+            }
+
+            self.channel.receiver_waker.register(context.waker());
+
+            // Try again, a sender may have sent a value before the waker was registered
+            match self.try_recv() {
+                Some(result) => {
+                    self.channel.receiver_waker.take();
+                    Poll::Ready(result)
+                }
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Receives a value without waiting
+    ///
+    /// # Returns
+    /// The received value, an error if the channel is closed, or None if the channel is empty
+    fn try_recv(&self) -> Option<Result<T, RecvError>> {
+        if let Some(value) = self.channel.queue.pop() {
+            self.channel.wake_senders();
+            return Some(Ok(value));
+        }
+
+        if self.channel.senders.load(Ordering::Acquire) == 0 {
+            // Values are pushed before the sender count is decremented, so check once more
+            return Some(self.channel.queue.pop().ok_or(RecvError));
+        }
+        None
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Wake the waiting senders, so they can notice the channel is closed
+        self.channel.receiver_alive.store(false, Ordering::Release);
+        self.channel.wake_senders();
+    }
+}
+
+/// Checks whether a producer and a consumer can exchange more values than fit in the channel
+#[test_case]
+fn test_channel_producer_consumer() {
+    use super::{executor::Executor, Task};
+
+    const ITEMS: usize = 50;
+
+    async fn produce(sender: Sender<usize>) {
+        for i in 0..ITEMS {
+            sender.send(i).await.expect("Receiver dropped");
+        }
+    }
+
+    async fn consume(mut receiver: Receiver<usize>, sum: Arc<AtomicUsize>) {
+        while let Ok(value) = receiver.recv().await {
+            sum.fetch_add(value, Ordering::Relaxed);
+        }
+    }
+
+    let (sender, receiver) = channel(4);
+    let sum = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(produce(sender)));
+    executor.spawn(Task::new(consume(receiver, sum.clone())));
+    executor.run_until_idle();
+
+    assert_eq!(sum.load(Ordering::Relaxed), (ITEMS - 1) * ITEMS / 2);
+}
@@ -6,13 +6,38 @@
 //! If threads were used, tasks should also be distributed to the right threads, a common way to do
 //! this is work stealing.
 
-use core::task::{Context, Poll, Waker};
+#[cfg(any(feature = "debug-executor", test))]
+use core::fmt;
+use core::{
+    future::Future,
+    task::{Context, Poll, Waker},
+};
 
 use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
 use crossbeam_queue::ArrayQueue;
 use x86_64::instructions::interrupts::{self, enable_and_hlt};
 
-use super::{Task, TaskId};
+use super::{JoinHandle, Task, TaskId};
+
+/// Writes the line logged over serial when a task is spawned, with the debug-executor feature
+///
+/// # Arguments
+/// ```output```: where to write the line to
+/// ```task```: the spawned task
+#[cfg(any(feature = "debug-executor", test))]
+pub(super) fn log_spawned(output: &mut impl fmt::Write, task: &Task) -> fmt::Result {
+    writeln!(output, "spawned task {}", task)
+}
+
+/// Writes the line logged over serial when a task completes, with the debug-executor feature
+///
+/// # Arguments
+/// ```output```: where to write the line to
+/// ```task```: the completed task
+#[cfg(any(feature = "debug-executor", test))]
+pub(super) fn log_completed(output: &mut impl fmt::Write, task: &Task) -> fmt::Result {
+    writeln!(output, "task {} completed", task)
+}
 
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
@@ -36,6 +61,9 @@ impl Executor {
     }
 
     pub fn spawn(&mut self, task: Task) {
+        #[cfg(feature = "debug-executor")]
+        let _ = log_spawned(&mut crate::serial::SerialWriter, &task);
+
         let task_id = task.id;
         if self.tasks.insert(task_id, task).is_some() {
             panic!("Task with same ID already in tasks");
@@ -43,6 +71,22 @@ impl Executor {
         self.task_queue.push(task_id).expect("queue full");
     }
 
+    /// Spawns a future as task, returning a handle to await its output
+    ///
+    /// # Arguments
+    /// ```future```: the future to run as task
+    ///
+    /// # Returns
+    /// A handle resolving to the output of the future once the task completes
+    pub fn spawn_joinable<T: 'static>(
+        &mut self,
+        future: impl Future<Output = T> + 'static,
+    ) -> JoinHandle<T> {
+        let (task, handle) = Task::new_joinable(future);
+        self.spawn(task);
+        handle
+    }
+
     fn run_ready_tasks(&mut self) {
         // Destructure `self` to avoid borrow checker errors
         let Self {
@@ -64,8 +108,16 @@ impl Executor {
             let mut context = Context::from_waker(waker);
 
             match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    // Task done -> remove it and its cached waker
+                Poll::Ready(result) => {
+                    match result {
+                        Ok(()) => {
+                            #[cfg(feature = "debug-executor")]
+                            let _ = log_completed(&mut crate::serial::SerialWriter, task);
+                        }
+                        Err(error) => println!("task {} failed: {}", task, error),
+                    }
+
+                    // Task done or failed -> remove it and its cached waker
                     tasks.remove(&task_id);
                     waker_cache.remove(&task_id);
                 }
@@ -74,6 +126,11 @@ impl Executor {
         }
     }
 
+    /// Runs tasks until none of them are ready, without waiting for interrupts
+    pub fn run_until_idle(&mut self) {
+        self.run_ready_tasks();
+    }
+
     pub fn run(&mut self) -> ! {
         loop {
             self.run_ready_tasks();
@@ -81,7 +138,12 @@ impl Executor {
         }
     }
 
-    /// Waits for an interrupt, if there are no tasks left to execute
+    /// Halts the CPU until the next interrupt, if there are no tasks left to execute.
+    ///
+    /// Checking the queue and halting has to be atomic. Otherwise an interrupt could wake a task
+    /// after the queue was found empty but before `hlt`, and that task would only run after the
+    /// next interrupt. So interrupts are disabled during the check, and `enable_and_hlt` enables
+    /// them and halts as a single step, as `sti` only takes effect after the next instruction.
     fn sleep_if_idle(&self) {
         interrupts::disable();
         if self.task_queue.is_empty() {
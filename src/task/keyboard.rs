@@ -1,17 +1,73 @@
-use core::task::Poll;
+use core::{
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    task::Poll,
+};
 
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
 use futures_util::{task::AtomicWaker, Stream, StreamExt};
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
+use lazy_static::lazy_static;
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, KeyCode as RawKeyCode, KeyEvent, KeyState, Keyboard,
+    KeyboardLayout, Modifiers, ScancodeSet1,
+};
+
+use crate::{
+    cmdline::{self, CommandLine},
+    port::{self, PS2_DATA, PS2_STATUS},
+};
 
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
 
+/// The number of scancodes the queue holds before dropping keyboard input
+const SCANCODE_QUEUE_SIZE: usize = 100;
+
+lazy_static! {
+    // The decoder shared by every reader of the keyboard, so the state of the modifier and lock
+    // keys carries over from one reader to the next
+    static ref DECODER: spin::Mutex<KeyDecoder> = {
+        let decoder = KeyDecoder::new();
+        decoder.locks.show();
+        spin::Mutex::new(decoder)
+    };
+}
+
+/// Returns the scancode queue, creating it on first use. Every reader takes the scancodes from
+/// this one queue, so only one of them should read at the same time.
+fn scancode_queue() -> &'static ArrayQueue<u8> {
+    SCANCODE_QUEUE.get_or_init(|| ArrayQueue::new(SCANCODE_QUEUE_SIZE))
+}
+
+/// The character Ctrl-C is decoded to, to interrupt what's running
+pub const CTRL_C: char = '\u{3}';
+/// The character Ctrl-D is decoded to, to signal the end of input
+pub const CTRL_D: char = '\u{4}';
+/// The character Ctrl-Q is decoded to, which powers off the machine
+pub const CTRL_Q: char = '\u{11}';
+/// The character Backspace is decoded to
+pub(crate) const BACKSPACE: char = '\u{8}';
+
+// The modifiers of the magic key combination, tracked in the interrupt handler, so it also
+// works when no task reads the keyboard
+static MAGIC_KEYS: spin::Mutex<MagicKeys> = spin::Mutex::new(MagicKeys::new());
+
+// The number of state dumps triggered by the magic key combination
+static MAGIC_DUMPS: AtomicUsize = AtomicUsize::new(0);
+
 /// Called by the keyboard interrupt handler
 ///
 /// Must not block on allocate.
 pub(crate) fn add_scancode(scancode: u8) {
+    // The scancode is also queued, so tasks still see Ctrl-D
+    if MAGIC_KEYS
+        .try_lock()
+        .is_some_and(|mut keys| keys.update(scancode))
+    {
+        MAGIC_DUMPS.fetch_add(1, Ordering::Relaxed);
+        crate::debug::dump_state();
+    }
+
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if queue.push(scancode).is_err() {
             println!("WARNING: Scancode queue full; dropping keyboard input");
@@ -23,16 +79,28 @@ pub(crate) fn add_scancode(scancode: u8) {
     }
 }
 
+/// Adds a scancode to the queue as if the keyboard interrupt fired, to test the keyboard
+/// pipeline without hardware
+///
+/// # Arguments
+/// ```scancode```: the scancode to add
+pub fn inject_scancode(scancode: u8) {
+    // Unlike the interrupt handler, this may allocate, so scancodes injected before any reader
+    // exists aren't dropped
+    scancode_queue();
+    add_scancode(scancode);
+}
+
 pub struct ScanCodeStream {
     _private: (),
 }
 
 impl ScanCodeStream {
+    /// Creates a stream taking scancodes from the queue shared with every other reader, see
+    /// `scancode_queue`
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
+        scancode_queue();
         ScanCodeStream { _private: () }
     }
 }
@@ -44,9 +112,7 @@ impl Stream for ScanCodeStream {
         self: core::pin::Pin<&mut Self>,
         cx: &mut core::task::Context<'_>,
     ) -> core::task::Poll<Option<Self::Item>> {
-        let queue = SCANCODE_QUEUE
-            .try_get()
-            .expect("Scancode queue not initialized");
+        let queue = scancode_queue();
 
         if let Some(scancode) = queue.pop() {
             return Poll::Ready(Some(scancode));
@@ -64,18 +130,678 @@ impl Stream for ScanCodeStream {
     }
 }
 
-pub async fn print_keypresses() {
-    let mut scancodes = ScanCodeStream::new();
-    let mut keyboard = Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore);
-
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => print!("{character}"),
-                    DecodedKey::RawKey(key) => print!("{key:?}"),
+/// The PS/2 keyboard command to set the LEDs, followed by the LED bitmask
+const SET_LEDS_COMMAND: u8 = 0xed;
+/// The response of the keyboard to an accepted command or data byte
+const ACK: u8 = 0xfa;
+/// The response of the keyboard asking to send the last byte again
+const RESEND: u8 = 0xfe;
+/// The number of times a byte is sent, before giving up
+const MAX_ATTEMPTS: usize = 3;
+/// The number of status reads before a wait for the PS/2 controller times out
+const TIMEOUT: usize = 100_000;
+
+/// The error returned when the keyboard LEDs couldn't be set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedError {
+    /// The PS/2 controller didn't accept or answer a byte in time
+    Timeout,
+    /// The keyboard didn't acknowledge a byte, with its last response
+    NotAcknowledged(u8),
+}
+
+/// Sets the Num Lock, Caps Lock, and Scroll Lock LEDs of the keyboard
+///
+/// # Arguments
+/// ```num```: whether the Num Lock LED should be on
+/// ```caps```: whether the Caps Lock LED should be on
+/// ```scroll```: whether the Scroll Lock LED should be on
+///
+/// # Returns
+/// An error if the keyboard didn't acknowledge the command
+pub fn set_leds(num: bool, caps: bool, scroll: bool) -> Result<(), LedError> {
+    use x86_64::instructions::interrupts;
+
+    let leds = u8::from(scroll) | (u8::from(num) << 1) | (u8::from(caps) << 2);
+
+    // Run without interrupts, as the keyboard interrupt handler would read the ACK otherwise
+    interrupts::without_interrupts(|| {
+        send_keyboard_byte(SET_LEDS_COMMAND)?;
+        send_keyboard_byte(leds)
+    })
+}
+
+/// Sends a byte to the keyboard, waiting until it's acknowledged
+///
+/// # Arguments
+/// ```byte```: the command or data byte to send
+///
+/// # Returns
+/// An error if the keyboard didn't acknowledge the byte
+fn send_keyboard_byte(byte: u8) -> Result<(), LedError> {
+    let mut response = RESEND;
+    for _ in 0..MAX_ATTEMPTS {
+        // Wait until the input buffer of the controller is empty (status bit 1)
+        wait_for_status(|status| status & 0x02 == 0)?;
+        unsafe { port::write_u8(PS2_DATA, byte) };
+
+        // Wait until the response is in the output buffer (status bit 0)
+        wait_for_status(|status| status & 0x01 != 0)?;
+        response = unsafe { port::read_u8(PS2_DATA) };
+        if response != RESEND {
+            break;
+        }
+    }
+
+    match response {
+        ACK => Ok(()),
+        response => Err(LedError::NotAcknowledged(response)),
+    }
+}
+
+/// Waits until the status register of the PS/2 controller satisfies a condition
+///
+/// # Arguments
+/// ```condition```: the condition the status register should satisfy
+///
+/// # Returns
+/// An error if the condition wasn't satisfied in time
+fn wait_for_status(condition: impl Fn(u8) -> bool) -> Result<(), LedError> {
+    for _ in 0..TIMEOUT {
+        if condition(unsafe { port::read_u8(PS2_STATUS) }) {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(LedError::Timeout)
+}
+
+/// The state of the lock keys, shown by the keyboard LEDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LockKeys {
+    num: bool,
+    caps: bool,
+    scroll: bool,
+}
+
+impl LockKeys {
+    /// Creates the lock state of a new keyboard, where only Num Lock is on
+    const fn new() -> Self {
+        LockKeys {
+            num: true,
+            caps: false,
+            scroll: false,
+        }
+    }
+
+    /// Toggles the lock of a pressed lock key
+    ///
+    /// # Arguments
+    /// ```event```: the key event to process
+    ///
+    /// # Returns
+    /// Whether the state of a lock changed
+    fn update(&mut self, event: &KeyEvent) -> bool {
+        if event.state != KeyState::Down {
+            return false;
+        }
+        let lock = match event.code {
+            RawKeyCode::NumpadLock => &mut self.num,
+            RawKeyCode::CapsLock => &mut self.caps,
+            RawKeyCode::ScrollLock => &mut self.scroll,
+            _ => return false,
+        };
+        *lock = !*lock;
+        true
+    }
+
+    /// Shows the state of the locks on the keyboard LEDs
+    fn show(&self) {
+        if let Err(error) = set_leds(self.num, self.caps, self.scroll) {
+            println!("WARNING: Setting the keyboard LEDs failed: {error:?}");
+        }
+    }
+}
+
+/// Recognizes Ctrl+Alt+D in raw scancodes, which dumps the kernel state over serial
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MagicKeys {
+    ctrl: bool,
+    alt: bool,
+    d: bool,
+    // Whether the previous scancode was the 0xe0 prefix of an extended key
+    extended: bool,
+}
+
+impl MagicKeys {
+    /// Creates the state of a keyboard without any held keys
+    const fn new() -> Self {
+        MagicKeys {
+            ctrl: false,
+            alt: false,
+            d: false,
+            extended: false,
+        }
+    }
+
+    /// Tracks whether Ctrl, Alt, and D are held. The right Ctrl and Alt keys send the scancodes
+    /// of the left keys with the extended prefix, so both count.
+    ///
+    /// # Arguments
+    /// ```scancode```: the scancode to process, in scancode set 1
+    ///
+    /// # Returns
+    /// Whether D was pressed while Ctrl and Alt were held. Holding D repeats its press
+    /// scancode, but that only counts once, until D is released.
+    fn update(&mut self, scancode: u8) -> bool {
+        const EXTENDED_PREFIX: u8 = 0xe0;
+        const RELEASED: u8 = 0x80;
+
+        if scancode == EXTENDED_PREFIX {
+            self.extended = true;
+            return false;
+        }
+        let extended = core::mem::replace(&mut self.extended, false);
+        let pressed = scancode & RELEASED == 0;
+
+        match scancode & !RELEASED {
+            0x1d => self.ctrl = pressed,
+            0x38 => self.alt = pressed,
+            // An extended 0x20 is the Mute key
+            0x20 if !extended => {
+                let repeated = core::mem::replace(&mut self.d, pressed);
+                return pressed && !repeated && self.ctrl && self.alt;
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// The navigation, editing, and function keys, which don't produce a character
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+}
+
+impl KeyCode {
+    /// Converts a key code of the keyboard decoder
+    ///
+    /// # Arguments
+    /// ```code```: the key code to convert
+    ///
+    /// # Returns
+    /// The key code, or None if the key isn't a navigation, editing, or function key
+    fn from_raw(code: RawKeyCode) -> Option<KeyCode> {
+        Some(match code {
+            RawKeyCode::ArrowUp => KeyCode::Up,
+            RawKeyCode::ArrowDown => KeyCode::Down,
+            RawKeyCode::ArrowLeft => KeyCode::Left,
+            RawKeyCode::ArrowRight => KeyCode::Right,
+            RawKeyCode::Home => KeyCode::Home,
+            RawKeyCode::End => KeyCode::End,
+            RawKeyCode::PageUp => KeyCode::PageUp,
+            RawKeyCode::PageDown => KeyCode::PageDown,
+            RawKeyCode::Delete => KeyCode::Delete,
+            RawKeyCode::Insert => KeyCode::Insert,
+            RawKeyCode::F1 => KeyCode::F1,
+            RawKeyCode::F2 => KeyCode::F2,
+            RawKeyCode::F3 => KeyCode::F3,
+            RawKeyCode::F4 => KeyCode::F4,
+            RawKeyCode::F5 => KeyCode::F5,
+            RawKeyCode::F6 => KeyCode::F6,
+            RawKeyCode::F7 => KeyCode::F7,
+            RawKeyCode::F8 => KeyCode::F8,
+            RawKeyCode::F9 => KeyCode::F9,
+            RawKeyCode::F10 => KeyCode::F10,
+            RawKeyCode::F11 => KeyCode::F11,
+            RawKeyCode::F12 => KeyCode::F12,
+            _ => return None,
+        })
+    }
+}
+
+/// A pressed key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A key producing a character
+    Unicode(char),
+    /// A navigation, editing, or function key
+    Special(KeyCode),
+    /// Any other key without a character
+    Other(RawKeyCode),
+}
+
+/// The keyboard layouts that can be selected with `keymap=` on the kernel command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    Azerty,
+    Dvorak104,
+}
+
+impl Layout {
+    /// Looks up a layout by its name on the command line
+    ///
+    /// # Arguments
+    /// ```name```: the name of the layout, like `azerty`
+    ///
+    /// # Returns
+    /// The layout, or None if there is no layout with the name
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "us" => Some(Layout::Us104),
+            "uk" => Some(Layout::Uk105),
+            "azerty" => Some(Layout::Azerty),
+            "dvorak" => Some(Layout::Dvorak104),
+            _ => None,
+        }
+    }
+}
+
+// The layout key presses are decoded with
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us104 as u8);
+
+/// Selects the layout key presses are decoded with, also by decoders that already exist
+///
+/// # Arguments
+/// ```layout```: the layout to decode with
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+/// Returns the layout key presses are decoded with
+pub fn layout() -> Layout {
+    match LAYOUT.load(Ordering::Relaxed) {
+        1 => Layout::Uk105,
+        2 => Layout::Azerty,
+        3 => Layout::Dvorak104,
+        _ => Layout::Us104,
+    }
+}
+
+/// Selects the layout from `keymap=` on the kernel command line, if it's there
+pub fn init_layout() {
+    init_layout_from(cmdline::command_line());
+}
+
+/// Selects the layout from `keymap=` on a command line, if it's there
+///
+/// # Arguments
+/// ```command_line```: the command line with the `keymap=` argument
+fn init_layout_from(command_line: &CommandLine) {
+    if let Some(name) = command_line.get("keymap") {
+        match Layout::from_name(name) {
+            Some(layout) => set_layout(layout),
+            None => crate::warning!("Unknown keyboard layout {:?}, keeping the default", name),
+        }
+    }
+}
+
+/// Maps key codes with the layout selected by `set_layout`, as pc-keyboard fixes the layout in
+/// the type of the keyboard
+pub struct SelectedLayout;
+
+impl KeyboardLayout for SelectedLayout {
+    fn map_keycode(
+        keycode: RawKeyCode,
+        modifiers: &Modifiers,
+        handle_ctrl: HandleControl,
+    ) -> DecodedKey {
+        match layout() {
+            Layout::Us104 => layouts::Us104Key::map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Uk105 => layouts::Uk105Key::map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Azerty => layouts::Azerty::map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Dvorak104 => {
+                layouts::Dvorak104Key::map_keycode(keycode, modifiers, handle_ctrl)
+            }
+        }
+    }
+}
+
+/// Decodes scancodes into key presses, including the 0xE0-prefixed extended scancodes
+pub struct KeyDecoder {
+    keyboard: Keyboard<SelectedLayout, ScancodeSet1>,
+    locks: LockKeys,
+}
+
+impl KeyDecoder {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        KeyDecoder {
+            // Map Ctrl + letter to the control characters, to recognize shortcuts
+            keyboard: Keyboard::new(
+                SelectedLayout,
+                ScancodeSet1,
+                HandleControl::MapLettersToUnicode,
+            ),
+            locks: LockKeys::new(),
+        }
+    }
+
+    /// Adds a scancode, updating the keyboard LEDs when a lock key is pressed
+    ///
+    /// # Arguments
+    /// ```scancode```: the scancode to add
+    ///
+    /// # Returns
+    /// The pressed key, or None if the scancode doesn't complete a key press
+    pub fn add_scancode(&mut self, scancode: u8) -> Option<Key> {
+        let event = self.keyboard.add_byte(scancode).ok().flatten()?;
+        if self.locks.update(&event) {
+            self.locks.show();
+        }
+
+        // Special keys are recognized before the layout, which maps some of them to characters
+        if event.state == KeyState::Down {
+            if let Some(code) = KeyCode::from_raw(event.code) {
+                return Some(Key::Special(code));
+            }
+        }
+
+        match self.keyboard.process_keyevent(event)? {
+            DecodedKey::Unicode(character) => Some(Key::Unicode(character)),
+            DecodedKey::RawKey(code) => Some(Key::Other(code)),
+        }
+    }
+}
+
+/// The keys pressed on the keyboard, decoded with the decoder shared by every reader
+pub struct KeyStream {
+    scancodes: ScanCodeStream,
+}
+
+impl KeyStream {
+    /// Creates a stream of the keys pressed from now on. Streams can come and go, like the one
+    /// of `line_reader::read_line`, but only one should read at the same time.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        KeyStream {
+            scancodes: ScanCodeStream::new(),
+        }
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = Key;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        // Keep decoding the available scancodes, until one completes a key press
+        while let Poll::Ready(scancode) = this.scancodes.poll_next_unpin(cx) {
+            match scancode {
+                Some(scancode) => {
+                    if let Some(key) = DECODER.lock().add_scancode(scancode) {
+                        return Poll::Ready(Some(key));
+                    }
                 }
+                None => return Poll::Ready(None),
             }
         }
+        Poll::Pending
     }
 }
+
+/// Reads a line from the keyboard, blocking until Enter is pressed. Doesn't need the executor,
+/// but takes the scancodes from the same queue, and decodes them with the same decoder, as the
+/// keyboard task, so they shouldn't be used at the same time.
+///
+/// Interrupts are enabled while waiting for keystrokes, and restored before returning.
+///
+/// # Arguments
+/// ```buf```: the buffer to write the UTF-8 encoded line to, without the new line. Once it's full,
+/// further characters are ignored until Enter is pressed.
+///
+/// # Returns
+/// The number of bytes written to the buffer
+pub fn read_line(buf: &mut [u8]) -> usize {
+    use x86_64::instructions::interrupts;
+
+    let queue = scancode_queue();
+    let were_enabled = interrupts::are_enabled();
+    let mut len = 0;
+
+    loop {
+        // Check the queue with interrupts disabled, so a scancode can't arrive between the check
+        // and the hlt, which would leave it waiting for the next interrupt
+        interrupts::disable();
+        let Some(scancode) = queue.pop() else {
+            interrupts::enable_and_hlt();
+            continue;
+        };
+        interrupts::enable();
+
+        // Release the decoder before handling the key
+        let key = DECODER.lock().add_scancode(scancode);
+        match key {
+            Some(Key::Unicode('\n')) => break,
+            Some(Key::Unicode(BACKSPACE)) => {
+                // Remove the last character, including all of its continuation bytes
+                while len > 0 {
+                    len -= 1;
+                    if buf[len] & 0xc0 != 0x80 {
+                        break;
+                    }
+                }
+            }
+            Some(Key::Unicode(character)) if character.len_utf8() <= buf.len() - len => {
+                len += character.encode_utf8(&mut buf[len..]).len();
+            }
+            // The buffer is full, or the key doesn't produce a character
+            _ => {}
+        }
+    }
+
+    if !were_enabled {
+        interrupts::disable();
+    }
+    len
+}
+
+pub async fn print_keypresses() {
+    let mut keys = KeyStream::new();
+
+    while let Some(key) = keys.next().await {
+        match key {
+            Key::Unicode(CTRL_Q) => crate::power::shutdown(),
+            // Show the other Ctrl-letter combinations in caret notation, like ^C. Enter, Tab, and
+            // Backspace are decoded to control characters as well, so they're left as they are.
+            Key::Unicode(character @ '\u{1}'..='\u{1a}')
+                if !character.is_ascii_whitespace() && character != BACKSPACE =>
+            {
+                print!("^{}", char::from(b'@' + character as u8))
+            }
+            Key::Unicode(character) => print!("{character}"),
+            Key::Special(code) => print!("{code:?}"),
+            Key::Other(code) => print!("{code:?}"),
+        }
+    }
+}
+
+/// Checks whether pressing a lock key toggles its lock, and releasing it doesn't
+#[test_case]
+fn test_lock_keys_toggle() {
+    let mut locks = LockKeys::new();
+    assert!(locks.update(&KeyEvent::new(RawKeyCode::CapsLock, KeyState::Down)));
+    assert!(locks.caps);
+    assert!(!locks.update(&KeyEvent::new(RawKeyCode::CapsLock, KeyState::Up)));
+    assert!(!locks.update(&KeyEvent::new(RawKeyCode::A, KeyState::Down)));
+    assert!(locks.update(&KeyEvent::new(RawKeyCode::CapsLock, KeyState::Down)));
+    assert_eq!(locks, LockKeys::new());
+}
+
+/// Toggles every keyboard LED on and back off
+#[test_case]
+fn test_set_leds() {
+    set_leds(true, true, true).expect("Setting the keyboard LEDs failed");
+    set_leds(true, false, false).expect("Setting the keyboard LEDs failed");
+}
+
+/// Checks whether the extended scancodes of the up arrow decode to KeyCode::Up
+#[test_case]
+fn test_decode_up_arrow() {
+    let mut decoder = KeyDecoder::new();
+
+    // Press
+    assert_eq!(decoder.add_scancode(0xe0), None);
+    assert_eq!(decoder.add_scancode(0x48), Some(Key::Special(KeyCode::Up)));
+
+    // Release
+    assert_eq!(decoder.add_scancode(0xe0), None);
+    assert_eq!(decoder.add_scancode(0xc8), None);
+
+    // A character still decodes to its character
+    assert_eq!(decoder.add_scancode(0x23), Some(Key::Unicode('h')));
+}
+
+/// Checks whether the extended scancodes of every navigation and editing key are decoded
+#[test_case]
+fn test_decode_extended_keys() {
+    let mut decoder = KeyDecoder::new();
+    for (scancode, code) in [
+        (0x48, KeyCode::Up),
+        (0x50, KeyCode::Down),
+        (0x4b, KeyCode::Left),
+        (0x4d, KeyCode::Right),
+        (0x47, KeyCode::Home),
+        (0x4f, KeyCode::End),
+        (0x49, KeyCode::PageUp),
+        (0x51, KeyCode::PageDown),
+        (0x52, KeyCode::Insert),
+        (0x53, KeyCode::Delete),
+    ] {
+        assert_eq!(decoder.add_scancode(0xe0), None);
+        assert_eq!(decoder.add_scancode(scancode), Some(Key::Special(code)));
+        assert_eq!(decoder.add_scancode(0xe0), None);
+        assert_eq!(decoder.add_scancode(scancode | 0x80), None);
+    }
+}
+
+/// Checks whether Ctrl+Alt+D triggers the state dump once, also when D repeats
+#[test_case]
+fn test_magic_keys() {
+    let mut keys = MagicKeys::new();
+
+    // D without modifiers, and with only Ctrl, doesn't trigger the dump
+    assert!(!keys.update(0x20));
+    assert!(!keys.update(0xa0));
+    assert!(!keys.update(0x1d));
+    assert!(!keys.update(0x20));
+    assert!(!keys.update(0xa0));
+
+    // Right Alt with left Ctrl triggers it once, while D repeats
+    assert!(!keys.update(0xe0));
+    assert!(!keys.update(0x38));
+    assert!(keys.update(0x20));
+    assert!(!keys.update(0x20));
+    assert!(!keys.update(0x20));
+
+    // Releasing and pressing D again triggers it again
+    assert!(!keys.update(0xa0));
+    assert!(keys.update(0x20));
+    assert!(!keys.update(0xa0));
+
+    // The Mute key shares the scancode of D, with the extended prefix
+    assert!(!keys.update(0xe0));
+    assert!(!keys.update(0x20));
+
+    // After releasing Alt, D doesn't trigger it anymore
+    assert!(!keys.update(0xe0));
+    assert!(!keys.update(0xb8));
+    assert!(!keys.update(0x20));
+}
+
+/// Checks whether scancodes from the interrupt handler trigger the state dump once for Ctrl+Alt+D
+#[test_case]
+fn test_magic_keys_dump_from_interrupt() {
+    let queue = scancode_queue();
+    while queue.pop().is_some() {}
+    let dumps = MAGIC_DUMPS.load(Ordering::Relaxed);
+
+    // Press left Ctrl, right Alt, and D, holding D for a repeat
+    for scancode in [0x1d, 0xe0, 0x38, 0x20, 0x20] {
+        add_scancode(scancode);
+    }
+    assert_eq!(MAGIC_DUMPS.load(Ordering::Relaxed), dumps + 1);
+
+    // Release everything, so the held keys don't leak into other tests
+    for scancode in [0xa0, 0xe0, 0xb8, 0x9d] {
+        add_scancode(scancode);
+    }
+    assert_eq!(MAGIC_DUMPS.load(Ordering::Relaxed), dumps + 1);
+
+    // The scancodes are still queued for tasks
+    let mut queued = 0;
+    while queue.pop().is_some() {
+        queued += 1;
+    }
+    assert_eq!(queued, 9);
+}
+
+/// Checks whether Ctrl-C and Ctrl-D decode to their control characters
+#[test_case]
+fn test_decode_ctrl_keys() {
+    let mut decoder = KeyDecoder::new();
+
+    // Press left Ctrl, press and release C and D, then release left Ctrl
+    assert_eq!(decoder.add_scancode(0x1d), None);
+    assert_eq!(decoder.add_scancode(0x2e), Some(Key::Unicode(CTRL_C)));
+    assert_eq!(decoder.add_scancode(0xae), None);
+    assert_eq!(decoder.add_scancode(0x20), Some(Key::Unicode(CTRL_D)));
+    assert_eq!(decoder.add_scancode(0xa0), None);
+    assert_eq!(decoder.add_scancode(0x9d), None);
+
+    // Without Ctrl, C is decoded to its character again
+    assert_eq!(decoder.add_scancode(0x2e), Some(Key::Unicode('c')));
+}
+
+/// Checks whether the layout from the command line is used to decode key presses
+#[test_case]
+fn test_layout_from_command_line() {
+    // Presses and releases the key right of Tab
+    fn press_q_key(decoder: &mut KeyDecoder) -> Option<Key> {
+        let key = decoder.add_scancode(0x10);
+        decoder.add_scancode(0x90);
+        key
+    }
+
+    let mut decoder = KeyDecoder::new();
+    assert_eq!(press_q_key(&mut decoder), Some(Key::Unicode('q')));
+
+    init_layout_from(&CommandLine::new("keymap=azerty"));
+    assert_eq!(layout(), Layout::Azerty);
+    assert_eq!(press_q_key(&mut decoder), Some(Key::Unicode('a')));
+
+    // An unknown layout keeps the current one
+    init_layout_from(&CommandLine::new("keymap=qwertz"));
+    assert_eq!(layout(), Layout::Azerty);
+
+    set_layout(Layout::Us104);
+}
@@ -0,0 +1,82 @@
+//! Reads whole lines from the keyboard, echoing them to the screen as they're typed.
+
+use alloc::string::String;
+use futures_util::StreamExt;
+
+use crate::{
+    print,
+    task::keyboard::{Key, KeyStream, BACKSPACE, CTRL_C, CTRL_D, CTRL_Q},
+    vga_buffer::WRITER,
+};
+
+/// How reading a line from the keyboard ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// Enter was pressed, with the typed line without the new line
+    Entered(String),
+    /// Ctrl-C was pressed, discarding the typed line
+    Cancelled,
+    /// Ctrl-D was pressed on an empty line, so no more input follows
+    EndOfInput,
+    /// Ctrl-Q was pressed, asking to power off the machine
+    PowerOff,
+}
+
+/// Reads a line from the keyboard, echoing the typed characters to the screen.
+/// Backspace erases the last character, also on the screen. Ctrl-C, Ctrl-D on an empty line,
+/// and Ctrl-Q end the line as well, see `Line`.
+///
+/// Takes the scancodes from the same queue as the keyboard task, so they shouldn't read at the
+/// same time.
+///
+/// # Returns
+/// How the line ended, with the typed line once Enter is pressed
+pub async fn read_line() -> Line {
+    let mut keys = KeyStream::new();
+    let mut line = String::new();
+
+    while let Some(key) = keys.next().await {
+        match key {
+            Key::Unicode('\n') => {
+                print!("\n");
+                return Line::Entered(line);
+            }
+            Key::Unicode(CTRL_C) => {
+                print!("^C\n");
+                return Line::Cancelled;
+            }
+            // Like in a terminal, Ctrl-D only ends the input on an empty line
+            Key::Unicode(CTRL_D) if line.is_empty() => {
+                print!("\n");
+                return Line::EndOfInput;
+            }
+            Key::Unicode(CTRL_Q) => {
+                print!("\n");
+                return Line::PowerOff;
+            }
+            Key::Unicode(BACKSPACE) => {
+                // Only erase characters of this line, not the prompt before it
+                if line.pop().is_some() {
+                    erase_last_character();
+                }
+            }
+            // Other control characters aren't part of the line, except for Tab
+            Key::Unicode(character) if character.is_ascii_control() && character != '\t' => {}
+            Key::Unicode(character) => {
+                line.push(character);
+                print!("{character}");
+            }
+            // Keys without a character aren't part of the line
+            Key::Special(_) | Key::Other(_) => {}
+        }
+    }
+    Line::EndOfInput
+}
+
+/// Erases the last character on the screen
+fn erase_last_character() {
+    use x86_64::instructions::interrupts;
+
+    // Run without interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| WRITER.write().backspace());
+}
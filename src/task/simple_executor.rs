@@ -21,15 +21,42 @@ impl SimpleExecutor {
     }
 
     pub fn run(&mut self) {
-        while let Some(mut task) = self.task_queue.pop_front() {
-            let waker = dummy_waker();
-            let mut context = Context::from_waker(&waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {} // Task done
-                Poll::Pending => self.task_queue.push_back(task),
+        while let Some(task) = self.task_queue.pop_front() {
+            self.poll_task(task);
+        }
+    }
+
+    /// Polls every queued task once, in the order they were queued, then returns so other work
+    /// can be done in between. Tasks that aren't done are queued again for the next call.
+    ///
+    /// This is still a busy-poll model, as wakers are ignored: every pending task is polled
+    /// again, whether it can make progress or not.
+    pub fn run_until_idle(&mut self) {
+        for _ in 0..self.task_queue.len() {
+            if let Some(task) = self.task_queue.pop_front() {
+                self.poll_task(task);
             }
         }
     }
+
+    /// Returns whether every task is done
+    pub fn is_empty(&self) -> bool {
+        self.task_queue.is_empty()
+    }
+
+    /// Polls a task once, queueing it again if it isn't done
+    ///
+    /// # Arguments
+    /// ```task```: the task to poll
+    fn poll_task(&mut self, mut task: Task) {
+        let waker = dummy_waker();
+        let mut context = Context::from_waker(&waker);
+        match task.poll(&mut context) {
+            Poll::Ready(Ok(())) => {} // Task done
+            Poll::Ready(Err(error)) => println!("task {} failed: {}", task, error),
+            Poll::Pending => self.task_queue.push_back(task),
+        }
+    }
 }
 
 fn dummy_raw_waker() -> RawWaker {
@@ -45,3 +72,35 @@ fn dummy_raw_waker() -> RawWaker {
 fn dummy_waker() -> Waker {
     unsafe { Waker::from_raw(dummy_raw_waker()) }
 }
+
+/// Checks whether every call polls each task once, in round robin
+#[test_case]
+fn test_run_until_idle_round_robin() {
+    use alloc::{sync::Arc, vec::Vec};
+    use spin::Mutex;
+
+    use super::yield_now;
+
+    async fn record(id: u8, order: Arc<Mutex<Vec<u8>>>) {
+        for _ in 0..2 {
+            order.lock().push(id);
+            yield_now().await;
+        }
+    }
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(record(0, order.clone())));
+    executor.spawn(Task::new(record(1, order.clone())));
+
+    executor.run_until_idle();
+    assert_eq!(*order.lock(), [0, 1]);
+    executor.run_until_idle();
+    assert_eq!(*order.lock(), [0, 1, 0, 1]);
+    assert!(!executor.is_empty());
+
+    // The last call completes both tasks
+    executor.run_until_idle();
+    assert!(executor.is_empty());
+    assert_eq!(*order.lock(), [0, 1, 0, 1]);
+}
@@ -0,0 +1,148 @@
+//! Synchronization primitives for async tasks.
+//! Unlike `spin::Mutex`, these don't busy-wait when contended. Instead, the waiting task is
+//! suspended and woken through the executor once the lock is released.
+//!
+//! Critical sections shared with interrupt handlers can't suspend, those use `InterruptGuard`.
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use alloc::collections::VecDeque;
+
+pub use crate::interrupts::InterruptGuard;
+
+/// A mutex which suspends the locking task while contended, instead of spinning
+pub struct AsyncMutex<T> {
+    locked: AtomicBool,
+    waiters: spin::Mutex<VecDeque<Waker>>,
+    value: UnsafeCell<T>,
+}
+
+// Safe, as access to the value is serialized by the `locked` flag
+unsafe impl<T: Send> Send for AsyncMutex<T> {}
+unsafe impl<T: Send> Sync for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    /// Creates an unlocked mutex
+    ///
+    /// # Arguments
+    /// ```value```: the value to protect
+    pub const fn new(value: T) -> Self {
+        AsyncMutex {
+            locked: AtomicBool::new(false),
+            waiters: spin::Mutex::new(VecDeque::new()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Locks the mutex, suspending the current task until it's available
+    ///
+    /// # Returns
+    /// A future resolving to a guard, which unlocks the mutex when dropped
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock { mutex: self }
+    }
+
+    /// Locks the mutex, if it isn't locked already
+    ///
+    /// # Returns
+    /// A guard, which unlocks the mutex when dropped, or None if the mutex is locked
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+}
+
+/// The future returned by `AsyncMutex::lock`
+pub struct Lock<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        if let Some(guard) = self.mutex.try_lock() {
+            return Poll::Ready(guard);
+        }
+
+        self.mutex.waiters.lock().push_back(context.waker().clone());
+
+        // Try again, the mutex may have been unlocked before the waker was registered
+        match self.mutex.try_lock() {
+            Some(guard) => Poll::Ready(guard),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Gives access to the value of a locked `AsyncMutex`, unlocking it when dropped
+pub struct MutexGuard<'a, T> {
+    mutex: &'a AsyncMutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safe, as the guard has exclusive access while the mutex is locked
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safe, as the guard has exclusive access while the mutex is locked
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+
+        // Wake every waiting task, as some wakers may belong to tasks that already got the lock.
+        // Waking only one of those would leave the others waiting forever.
+        let waiters = core::mem::take(&mut *self.mutex.waiters.lock());
+        for waiter in waiters {
+            waiter.wake();
+        }
+    }
+}
+
+/// Checks whether two tasks contending for a mutex never hold it at the same time
+#[test_case]
+fn test_async_mutex_contention() {
+    use super::{executor::Executor, yield_now, Task};
+    use alloc::{sync::Arc, vec::Vec};
+
+    async fn hold_twice(id: u8, mutex: Arc<AsyncMutex<Vec<u8>>>) {
+        for _ in 0..2 {
+            let mut guard = mutex.lock().await;
+            guard.push(id);
+            // Give the other task a chance to take the lock while it's held
+            yield_now().await;
+            guard.push(id);
+        }
+    }
+
+    let mutex = Arc::new(AsyncMutex::new(Vec::new()));
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(hold_twice(0, mutex.clone())));
+    executor.spawn(Task::new(hold_twice(1, mutex.clone())));
+    executor.run_until_idle();
+
+    let order = mutex.try_lock().expect("Mutex still locked");
+    assert_eq!(order.len(), 8);
+    for pair in order.chunks(2) {
+        assert_eq!(pair[0], pair[1]);
+    }
+}
@@ -0,0 +1,385 @@
+//! High resolution time measurement using the time stamp counter (TSC).
+//! The TSC counts CPU cycles, so its frequency differs per CPU and may even change with the CPU
+//! frequency on older CPUs. `calibrate_tsc` measures it against the PIT, which has a fixed
+//! frequency. Under QEMU the measurement is approximate, as the host can preempt the guest
+//! during calibration.
+//!
+//! Coarser delays are measured in timer interrupts, using `Duration` with `busy_wait`, or with
+//! `sleep` in async tasks.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use x86_64::instructions::interrupts;
+
+use crate::port::{self, PIT_CHANNEL2, PIT_COMMAND, PS2_PORT_B};
+
+/// The frequency of the PIT input clock in Hz
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+/// The divisor of PIT channel 0, which generates the timer interrupt.
+/// The kernel keeps the BIOS default of 65536, giving about 18.2 interrupts per second.
+const PIT_CHANNEL0_DIVISOR: u64 = 65536;
+
+/// The duration of the calibration in milliseconds
+const CALIBRATION_MS: u64 = 10;
+
+// The measured TSC frequency in Hz, 0 if not calibrated yet
+static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// The maximum number of tasks that can sleep without busy-waking
+const MAX_SLEEPERS: usize = 32;
+
+/// A sleeping task, woken by the timer interrupt once its deadline has passed
+struct SleeperSlot {
+    // Whether a `Sleep` future owns this slot
+    in_use: AtomicBool,
+    // The tick count at which the task should be woken
+    deadline: AtomicU64,
+    // The waker of the task, only replaced and dropped outside of the timer interrupt, as
+    // dropping the last reference to a finished task frees its memory
+    waker: spin::Mutex<Option<Waker>>,
+}
+
+impl SleeperSlot {
+    const fn new() -> Self {
+        SleeperSlot {
+            in_use: AtomicBool::new(false),
+            deadline: AtomicU64::new(u64::MAX),
+            waker: spin::Mutex::new(None),
+        }
+    }
+}
+
+// The sleeping tasks, a slot per `Sleep` future
+static SLEEPERS: [SleeperSlot; MAX_SLEEPERS] = [const { SleeperSlot::new() }; MAX_SLEEPERS];
+
+/// Reads the time stamp counter
+///
+/// # Returns
+/// The number of ticks since the CPU was reset
+pub fn rdtsc() -> u64 {
+    // Safe, as rdtsc is available on every x86_64 CPU
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Measures the TSC frequency against PIT channel 2, and stores it for `tsc_to_ns`.
+/// Channel 2 counts down at the fixed PIT input frequency, independent of the timer interrupt,
+/// so this works with interrupts disabled and before or after the timer is set up.
+/// Busy-waits for about 10 milliseconds.
+///
+/// # Returns
+/// The number of TSC ticks per second
+pub fn calibrate_tsc() -> u64 {
+    let pit_ticks = PIT_FREQUENCY * CALIBRATION_MS / 1000;
+
+    // Run without interrupts, as they would make the measurement longer
+    let tsc_ticks = interrupts::without_interrupts(|| unsafe {
+        // Enable the gate of channel 2 in keyboard controller port B, with the speaker disabled
+        let value = port::read_u8(PS2_PORT_B);
+        port::write_u8(PS2_PORT_B, (value & !0x02) | 0x01);
+
+        // Channel 2, low byte then high byte, mode 0 (interrupt on terminal count), binary
+        port::write_u8(PIT_COMMAND, 0b1011_0000);
+        port::write_u8(PIT_CHANNEL2, pit_ticks as u8);
+        port::write_u8(PIT_CHANNEL2, (pit_ticks >> 8) as u8);
+
+        // The output of channel 2 goes high once the count reaches 0, shown in port B
+        let start = rdtsc();
+        while port::read_u8(PS2_PORT_B) & 0x20 == 0 {}
+        let end = rdtsc();
+
+        // Restore the gate and speaker bits
+        port::write_u8(PS2_PORT_B, value);
+        end - start
+    });
+
+    // Scale by the programmed count, as the PIT can't count exactly 10 milliseconds
+    let frequency = (u128::from(tsc_ticks) * u128::from(PIT_FREQUENCY) / u128::from(pit_ticks))
+        .clamp(1, u128::from(u64::MAX)) as u64;
+    TSC_FREQUENCY.store(frequency, Ordering::Relaxed);
+    frequency
+}
+
+/// Returns the frequency of the TSC measured by `calibrate_tsc`
+///
+/// # Returns
+/// The number of TSC ticks per second, or None if the TSC hasn't been calibrated yet
+pub fn tsc_frequency() -> Option<u64> {
+    match TSC_FREQUENCY.load(Ordering::Relaxed) {
+        0 => None,
+        frequency => Some(frequency),
+    }
+}
+
+/// Converts a number of TSC ticks to nanoseconds
+///
+/// # Arguments
+/// ```ticks```: the number of ticks, e.g. the difference between two `rdtsc` calls
+///
+/// # Returns
+/// The number of nanoseconds, or None if the TSC hasn't been calibrated yet
+pub fn tsc_to_ns(ticks: u64) -> Option<u64> {
+    let frequency = tsc_frequency()?;
+    let nanoseconds = u128::from(ticks) * 1_000_000_000 / u128::from(frequency);
+    Some(nanoseconds.min(u128::from(u64::MAX)) as u64)
+}
+
+/// Returns the time since boot in milliseconds. Measured with the TSC once `calibrate_tsc` has
+/// been called, as the local APIC timer doesn't interrupt at a known rate. Before that it's
+/// estimated from the number of timer interrupts, as if the PIT generated them.
+pub fn uptime_ms() -> u64 {
+    if let Some(frequency) = tsc_frequency() {
+        // The TSC starts counting when the CPU is reset, shortly before the kernel boots
+        return (u128::from(rdtsc()) * 1000 / u128::from(frequency)) as u64;
+    }
+    let milliseconds =
+        u128::from(crate::interrupts::ticks()) * u128::from(PIT_CHANNEL0_DIVISOR) * 1000
+            / u128::from(PIT_FREQUENCY);
+    milliseconds as u64
+}
+
+/// A length of time, counted in timer interrupts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration {
+    ticks: u64,
+}
+
+impl Duration {
+    /// Creates a duration of a number of timer interrupts
+    ///
+    /// # Arguments
+    /// ```ticks```: the number of timer interrupts
+    pub const fn from_ticks(ticks: u64) -> Duration {
+        Duration { ticks }
+    }
+
+    /// Creates a duration of at least a number of milliseconds, rounded up to whole timer
+    /// interrupts
+    ///
+    /// # Arguments
+    /// ```milliseconds```: the number of milliseconds
+    pub const fn from_millis(milliseconds: u64) -> Duration {
+        let ticks = (milliseconds as u128 * PIT_FREQUENCY as u128)
+            .div_ceil(PIT_CHANNEL0_DIVISOR as u128 * 1000);
+        Duration {
+            ticks: ticks as u64,
+        }
+    }
+
+    /// Returns the number of timer interrupts
+    pub const fn ticks(self) -> u64 {
+        self.ticks
+    }
+}
+
+/// Spins until a number of timer interrupts has passed, to wait without the executor, like
+/// during initialization.
+///
+/// Interrupts must be enabled, and the PIT has to generate the timer interrupts, so the local
+/// APIC timer can't be used. Otherwise the tick counter doesn't advance at the expected rate, or
+/// doesn't advance at all and this never returns.
+///
+/// # Arguments
+/// ```duration```: the time to wait
+pub fn busy_wait(duration: Duration) {
+    let start = crate::interrupts::ticks();
+    while crate::interrupts::ticks() - start < duration.ticks {
+        core::hint::spin_loop();
+    }
+}
+
+/// Waits until a number of timer interrupts has passed, without keeping the CPU busy
+///
+/// # Arguments
+/// ```duration```: the time to wait
+///
+/// # Returns
+/// A future that completes once the time has passed
+pub fn sleep(duration: Duration) -> impl Future<Output = ()> {
+    Sleep {
+        deadline: crate::interrupts::ticks() + duration.ticks,
+        slot: None,
+    }
+}
+
+/// The future returned by `sleep`
+struct Sleep {
+    // The tick count at which the future completes
+    deadline: u64,
+    // The index of the slot in `SLEEPERS` holding the waker, None if no slot was claimed (yet)
+    slot: Option<usize>,
+}
+
+impl Sleep {
+    /// Claims a free slot in `SLEEPERS`
+    ///
+    /// # Returns
+    /// The index of the slot, or None if every slot is in use
+    fn claim_slot(&self) -> Option<usize> {
+        SLEEPERS.iter().position(|slot| {
+            slot.in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })
+    }
+
+    /// Gives the slot back, dropping the waker outside of the timer interrupt
+    fn release_slot(&mut self) {
+        if let Some(index) = self.slot.take() {
+            let slot = &SLEEPERS[index];
+            slot.deadline.store(u64::MAX, Ordering::Relaxed);
+            // Take the waker with interrupts disabled, so the timer interrupt never finds the
+            // lock taken, and drop it afterwards
+            let waker = interrupts::without_interrupts(|| slot.waker.lock().take());
+            drop(waker);
+            slot.in_use.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if crate::interrupts::ticks() >= self.deadline {
+            self.release_slot();
+            return Poll::Ready(());
+        }
+
+        if self.slot.is_none() {
+            self.slot = self.claim_slot();
+        }
+        match self.slot {
+            Some(index) => {
+                let slot = &SLEEPERS[index];
+                // Only replace the waker if it changed, the previous waker is dropped here and
+                // not in the timer interrupt
+                let previous = interrupts::without_interrupts(|| {
+                    let mut waker = slot.waker.lock();
+                    match waker.as_ref() {
+                        Some(waker) if waker.will_wake(context.waker()) => None,
+                        _ => waker.replace(context.waker().clone()),
+                    }
+                });
+                drop(previous);
+                slot.deadline.store(self.deadline, Ordering::Release);
+            }
+            // Too many sleeping tasks, so check again as soon as possible
+            None => context.waker().wake_by_ref(),
+        }
+
+        // The timer interrupt may have fired before the waker was stored
+        if crate::interrupts::ticks() >= self.deadline {
+            self.release_slot();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.release_slot();
+    }
+}
+
+/// Called by the timer interrupt handler, wakes every sleeping task whose deadline has passed
+///
+/// Must not block or allocate, and must not drop wakers, as dropping the last reference to a
+/// task frees it.
+pub(crate) fn wake_sleepers() {
+    let now = crate::interrupts::ticks();
+    for slot in SLEEPERS.iter() {
+        if !slot.in_use.load(Ordering::Acquire) || slot.deadline.load(Ordering::Acquire) > now {
+            continue;
+        }
+        // The lock is only taken with interrupts disabled, so it's never held here
+        if let Some(waker) = slot.waker.try_lock() {
+            if let Some(waker) = waker.as_ref() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+/// Checks whether a sleeping task keeps a single slot however often it's polled, and gives it
+/// back when the sleep is dropped
+#[test_case]
+fn test_sleep_uses_one_slot() {
+    use alloc::boxed::Box;
+    use futures_util::task::noop_waker;
+
+    fn slots_in_use() -> usize {
+        SLEEPERS
+            .iter()
+            .filter(|slot| slot.in_use.load(Ordering::Relaxed))
+            .count()
+    }
+
+    let before = slots_in_use();
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut sleep = Box::pin(sleep(Duration::from_ticks(1000)));
+    for _ in 0..MAX_SLEEPERS * 2 {
+        assert_eq!(sleep.as_mut().poll(&mut context), Poll::Pending);
+    }
+    assert_eq!(slots_in_use(), before + 1);
+
+    drop(sleep);
+    assert_eq!(slots_in_use(), before);
+}
+
+/// Checks whether the uptime doesn't go backwards
+#[test_case]
+fn test_uptime_ms_increases() {
+    let first = uptime_ms();
+    busy_wait(Duration::from_ticks(2));
+    assert!(uptime_ms() >= first);
+}
+
+/// Checks whether the calibrated TSC frequency is plausible for a CPU QEMU runs on, and whether
+/// conversions use it
+#[test_case]
+fn test_calibrate_tsc() {
+    let frequency = calibrate_tsc();
+    assert_eq!(tsc_frequency(), Some(frequency));
+    assert!(
+        frequency > 100_000_000,
+        "TSC frequency {frequency} Hz is too low"
+    );
+    assert_eq!(tsc_to_ns(frequency), Some(1_000_000_000));
+}
+
+/// Checks whether the time stamp counter increases
+#[test_case]
+fn test_rdtsc_increases() {
+    let first = rdtsc();
+    let second = rdtsc();
+    assert!(second > first);
+}
+
+/// Checks whether milliseconds are rounded up to whole timer interrupts
+#[test_case]
+fn test_duration_from_millis() {
+    assert_eq!(Duration::from_millis(0).ticks(), 0);
+    assert_eq!(Duration::from_millis(1).ticks(), 1);
+    assert_eq!(Duration::from_millis(110).ticks(), 3);
+    assert_eq!(Duration::from_millis(1000).ticks(), 19);
+}
+
+/// Checks whether busy waiting about 50 milliseconds takes about as many timer interrupts
+#[test_case]
+fn test_busy_wait() {
+    let duration = Duration::from_millis(50);
+    let start = crate::interrupts::ticks();
+    busy_wait(duration);
+    let elapsed = crate::interrupts::ticks() - start;
+
+    // A timer interrupt may arrive between the reads and the wait
+    assert!(elapsed >= duration.ticks() && elapsed <= duration.ticks() + 1);
+}
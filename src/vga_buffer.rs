@@ -1,7 +1,7 @@
-use core::fmt;
+use core::{fmt, panic::PanicInfo};
 
 use lazy_static::lazy_static;
-use spin::Mutex;
+use spin::RwLock;
 use volatile::Volatile;
 
 /// Represents the color options for the vga buffer
@@ -27,9 +27,68 @@ pub enum Color {
     White = 15,
 }
 
+impl Color {
+    /// Every color, ordered by their numeric value
+    pub const ALL: [Color; 16] = [
+        Color::Black,
+        Color::Blue,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Magenta,
+        Color::Brown,
+        Color::LightGray,
+        Color::DarkGray,
+        Color::LightBlue,
+        Color::LightGreen,
+        Color::LightCyan,
+        Color::LightRed,
+        Color::Pink,
+        Color::Yellow,
+        Color::White,
+    ];
+
+    /// Converts an ANSI foreground color code to a color
+    ///
+    /// # Arguments
+    /// ```code```: the ANSI code, 30-37 for normal colors or 90-97 for bright colors
+    ///
+    /// # Returns
+    /// The color, or None if the code isn't a foreground color code
+    pub fn from_ansi_fg(code: u8) -> Option<Color> {
+        // ANSI orders the colors red, green, blue, where VGA orders them blue, green, red
+        const ANSI_ORDER: [Color; 8] = [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Brown,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::LightGray,
+        ];
+        match code {
+            30..=37 => Some(ANSI_ORDER[usize::from(code - 30)]),
+            // The bright variants are 8 higher in the VGA palette
+            90..=97 => Color::try_from(ANSI_ORDER[usize::from(code - 90)] as u8 + 8).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for Color {
+    /// The value that isn't a color
+    type Error = u8;
+
+    /// Converts a numeric value (0-15) to a color
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Color::ALL.get(usize::from(value)).copied().ok_or(value)
+    }
+}
+
 /// Represents the full color byte of a character, foreground (4-bit), background (3-bit)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
     /// Creates a color code
@@ -40,27 +99,142 @@ impl ColorCode {
     ///
     /// # Returns
     /// A color code
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         Self((background as u8) << 4 | foreground as u8)
     }
+
+    /// Sets or clears the blink flag (bit 7) of a color code
+    ///
+    /// By default, VGA text mode interprets this bit as blink. When bright backgrounds are enabled
+    /// with `set_blink_enabled(false)`, it selects the bright variant of the background color
+    /// instead, so blinking and bright backgrounds can't be used at the same time.
+    ///
+    /// # Arguments
+    /// ```blink```: whether the character should blink
+    ///
+    /// # Returns
+    /// The color code with the blink flag set or cleared
+    fn with_blink(self, blink: bool) -> ColorCode {
+        if blink {
+            Self(self.0 | 0x80)
+        } else {
+            Self(self.0 & !0x80)
+        }
+    }
+}
+
+/// The purpose of text, which decides its color through the color scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Normal,
+    Error,
+    Warning,
+    Success,
+    Prompt,
+}
+
+/// The colors of the roles of text, so subsystems don't hard-code colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorScheme {
+    pub normal: ColorCode,
+    pub error: ColorCode,
+    pub warning: ColorCode,
+    pub success: ColorCode,
+    pub prompt: ColorCode,
+}
+
+/// The color scheme used until another one is set, every role is yellow on black
+const DEFAULT_SCHEME: ColorScheme = ColorScheme {
+    normal: ColorCode::new(Color::Yellow, Color::Black),
+    error: ColorCode::new(Color::Yellow, Color::Black),
+    warning: ColorCode::new(Color::Yellow, Color::Black),
+    success: ColorCode::new(Color::Yellow, Color::Black),
+    prompt: ColorCode::new(Color::Yellow, Color::Black),
+};
+
+impl ColorScheme {
+    /// Returns the color of a role
+    ///
+    /// # Arguments
+    /// ```role```: the role to get the color of
+    pub fn color(&self, role: Role) -> ColorCode {
+        match role {
+            Role::Normal => self.normal,
+            Role::Error => self.error,
+            Role::Warning => self.warning,
+            Role::Success => self.success,
+            Role::Prompt => self.prompt,
+        }
+    }
+}
+
+impl Default for ColorScheme {
+    /// The colors the kernel has always used, yellow on black for everything
+    fn default() -> Self {
+        DEFAULT_SCHEME
+    }
 }
 
+// The color scheme of the screen
+static SCHEME: RwLock<ColorScheme> = RwLock::new(DEFAULT_SCHEME);
+
 /// Represents a full VGA character
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
+pub struct ScreenChar {
     ascii_character: u8,
     color_code: ColorCode,
 }
 
-/// The dimensions of the VGA buffer
+impl ScreenChar {
+    /// Creates a VGA character
+    ///
+    /// # Arguments
+    /// ```character```: the Code Page 437 glyph to show
+    /// ```foreground```: the foreground color
+    /// ```background```: the background color
+    ///
+    /// # Returns
+    /// A VGA character
+    pub const fn new(character: u8, foreground: Color, background: Color) -> ScreenChar {
+        ScreenChar {
+            ascii_character: character,
+            color_code: ColorCode::new(foreground, background),
+        }
+    }
+}
+
+/// The error returned when the data to blit doesn't fill exactly one screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlitSizeError {
+    /// The length of the data that was passed
+    pub len: usize,
+}
+
+/// The dimensions of the default 80x25 text mode
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 
-/// The VGA buffer
+/// The height of the VGA buffer in the 80x50 mode, the largest supported height
+const MAX_BUFFER_HEIGHT: usize = 50;
+
+/// The number of cells in the VGA buffer, enough for every supported text mode
+const BUFFER_CELLS: usize = BUFFER_WIDTH * MAX_BUFFER_HEIGHT;
+
+/// The dimensions of a text mode, in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+}
+
+/// The color of the status bar, distinct from the default text color
+const STATUS_COLOR: ColorCode = ColorCode::new(Color::Black, Color::LightGray);
+
+/// The VGA buffer, with the rows of the current text mode stored one after the other
 #[repr(transparent)]
 struct Buffer {
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    chars: [Volatile<ScreenChar>; BUFFER_CELLS],
 }
 
 /// Writes text to the VGA buffer
@@ -68,6 +242,18 @@ pub struct Writer {
     column_position: usize,
     color_code: ColorCode,
     buffer: &'static mut Buffer,
+    // The number of columns and rows shown in the current text mode
+    dimensions: Dimensions,
+    // Reserved rows with their color, these aren't scrolled and ignore the writer's color
+    row_colors: [Option<ColorCode>; MAX_BUFFER_HEIGHT],
+    // The first and last row of the scroll region
+    scroll_top: usize,
+    scroll_bottom: usize,
+    // The row text is written on, moving down with every new line until the last row of the
+    // scroll region is reached
+    cursor_row: usize,
+    // Whether \r moves back to the start of the row, for text with \r\n line endings
+    crlf: bool,
 }
 
 impl fmt::Write for Writer {
@@ -76,9 +262,85 @@ impl fmt::Write for Writer {
         self.write_string(s);
         Ok(())
     }
+
+    /// Writes a character, translated to its Code Page 437 glyph.
+    /// Characters without a glyph are written as `0xfe`.
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        match c {
+            '\n' => self.new_line(),
+            c => self.write_byte(to_cp437(c).unwrap_or(0xfe)),
+        }
+        Ok(())
+    }
 }
 
 impl Writer {
+    /// Creates a writer for the default 80x25 text mode, clearing the screen and starting on the
+    /// top row
+    ///
+    /// # Arguments
+    /// ```buffer```: the VGA buffer to write to
+    fn new(buffer: &'static mut Buffer) -> Writer {
+        let mut writer = Writer {
+            column_position: 0,
+            color_code: DEFAULT_SCHEME.normal,
+            buffer,
+            dimensions: Dimensions {
+                width: BUFFER_WIDTH,
+                height: BUFFER_HEIGHT,
+            },
+            row_colors: [None; MAX_BUFFER_HEIGHT],
+            scroll_top: 0,
+            scroll_bottom: BUFFER_HEIGHT - 1,
+            cursor_row: 0,
+            crlf: false,
+        };
+
+        // remove what the BIOS and bootloader left on the screen
+        writer.clear_screen();
+        writer
+    }
+
+    /// Returns the cell at a position on the screen
+    ///
+    /// # Arguments
+    /// ```row```: the row of the cell
+    /// ```col```: the column of the cell
+    fn cell(&self, row: usize, col: usize) -> &Volatile<ScreenChar> {
+        &self.buffer.chars[self.cell_index(row, col)]
+    }
+
+    /// Returns the cell at a position on the screen, to write to it
+    ///
+    /// # Arguments
+    /// ```row```: the row of the cell
+    /// ```col```: the column of the cell
+    fn cell_mut(&mut self, row: usize, col: usize) -> &mut Volatile<ScreenChar> {
+        let index = self.cell_index(row, col);
+        &mut self.buffer.chars[index]
+    }
+
+    /// Calculates the offset of a cell in the VGA buffer. Every access to the buffer goes through
+    /// here, so a position outside of the screen is caught in debug builds, instead of silently
+    /// writing to the memory mapped cells past the visible rows.
+    ///
+    /// # Arguments
+    /// ```row```: the row of the cell
+    /// ```col```: the column of the cell
+    fn cell_index(&self, row: usize, col: usize) -> usize {
+        debug_assert!(
+            row < self.dimensions.height,
+            "Row {} is below the screen",
+            row
+        );
+        debug_assert!(
+            col < self.dimensions.width,
+            "Column {} is past the right edge of the screen",
+            col
+        );
+        row * self.dimensions.width + col
+    }
+
     /// Writes a single character to the screen
     ///
     /// # Arguments
@@ -88,22 +350,26 @@ impl Writer {
             // move to a new line, if a new line character is printed
             b'\n' => self.new_line(),
 
+            // move back to the start of the row, if a carriage return is printed in CRLF mode
+            b'\r' if self.crlf => self.column_position = 0,
+
             // else, print the character to the screen
             byte => {
                 // if we're at the end of the current line, first go to a new line
-                if self.column_position >= BUFFER_WIDTH {
+                if self.column_position >= self.dimensions.width {
                     self.new_line();
                 }
 
-                // set the current row to the last row, and the current column to the column position
-                let row = BUFFER_HEIGHT - 1;
+                // set the current row to the cursor row, and the current column to the column
+                // position
+                let row = self.cursor_row;
                 let col = self.column_position;
 
                 // get the color code for this writer
                 let color_code = self.color_code;
 
                 // create the character, and write it to the screen
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.cell_mut(row, col).write(ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -114,116 +380,1963 @@ impl Writer {
         }
     }
 
-    /// Moves the cursor to the next line
+    /// Moves the cursor to the next line, scrolling if the cursor is on the last row of the scroll
+    /// region
     fn new_line(&mut self) {
-        // shift every character 1 line up, replacing the first row
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
+        // move down to the next unreserved row without scrolling, if the cursor is above the
+        // last row of the scroll region
+        if let Some(row) =
+            (self.cursor_row + 1..=self.scroll_bottom).find(|&row| self.row_colors[row].is_none())
+        {
+            self.cursor_row = row;
+            self.column_position = 0;
+            return;
         }
 
-        // clear the last row, and reset the column position
-        self.clear_row(BUFFER_HEIGHT - 1);
+        self.scroll_up();
+        self.cursor_row = self.scroll_bottom;
         self.column_position = 0;
     }
 
+    /// Shifts every unreserved row in the scroll region 1 line up, replacing the first unreserved
+    /// row, and clears the last row of the scroll region. Reserved rows, like a status bar, are
+    /// left untouched.
+    fn scroll_up(&mut self) {
+        let width = self.dimensions.width;
+        let mut previous_row = None;
+        let mut row = self.scroll_top;
+        while row <= self.scroll_bottom {
+            if self.row_colors[row].is_some() {
+                row += 1;
+                continue;
+            }
+
+            // find the run of consecutive unreserved rows starting at this row
+            let start = row;
+            while row <= self.scroll_bottom && self.row_colors[row].is_none() {
+                row += 1;
+            }
+
+            // the first row of the run moves to the last unreserved row before the run, the
+            // others move up within the run, which is a single copy
+            let cells = self.buffer.chars.as_mut_ptr() as *mut ScreenChar;
+            // check the last cell of the run as well, the copies only check their first cell
+            let _ = self.cell_index(row - 1, width - 1);
+            // Safe, as every row is inside the VGA buffer, and the buffer is borrowed mutably
+            unsafe {
+                if let Some(previous_row) = previous_row {
+                    copy_cells(
+                        cells.add(self.cell_index(start, 0)),
+                        cells.add(self.cell_index(previous_row, 0)),
+                        width,
+                    );
+                }
+                copy_cells(
+                    cells.add(self.cell_index(start, 0) + width),
+                    cells.add(self.cell_index(start, 0)),
+                    (row - start - 1) * width,
+                );
+            }
+            previous_row = Some(row - 1);
+        }
+
+        self.clear_row(self.scroll_bottom);
+    }
+
+    /// Scrolls like `scroll_up`, but copies every cell separately, to check the result of the
+    /// faster copy against
+    #[cfg(test)]
+    fn scroll_up_per_cell(&mut self) {
+        let mut previous_row = None;
+        for row in self.scroll_top..=self.scroll_bottom {
+            if self.row_colors[row].is_some() {
+                continue;
+            }
+            if let Some(previous_row) = previous_row {
+                for col in 0..self.dimensions.width {
+                    let character = self.cell(row, col).read();
+                    self.cell_mut(previous_row, col).write(character);
+                }
+            }
+            previous_row = Some(row);
+        }
+        self.clear_row(self.scroll_bottom);
+    }
+
     /// Clears a row on the screen
     ///
     /// # Arguments
     /// ```row```: the row index to clear
     fn clear_row(&mut self, row: usize) {
-        // create the blank character, to fill the row with
+        // create the blank character, to fill the row with, in the color of a reserved row
         let blank = ScreenChar {
             ascii_character: b' ',
-            color_code: self.color_code,
+            color_code: self.row_colors[row].unwrap_or(self.color_code),
         };
 
         // fill the row with the blank character
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for col in 0..self.dimensions.width {
+            self.cell_mut(row, col).write(blank);
+        }
+    }
+
+    /// Writes a status line to the top row, which isn't scrolled away by new lines
+    ///
+    /// # Arguments
+    /// ```s```: the status to show, truncated to the width of the screen
+    pub fn set_status(&mut self, s: &str) {
+        // Reserve the top row, unless it has been reserved with another color already
+        let color_code = *self.row_colors[0].get_or_insert(STATUS_COLOR);
+        self.leave_reserved_row();
+
+        // fill the status bar with blanks, then write the status over it
+        self.clear_row(0);
+        for (col, byte) in s.bytes().take(self.dimensions.width).enumerate() {
+            let ascii_character = match byte {
+                // printable character
+                0x20..=0x7e => byte,
+                // not part of printable ASCII range
+                _ => 0xfe,
+            };
+            self.cell_mut(0, col).write(ScreenChar {
+                ascii_character,
+                color_code,
+            });
+        }
+    }
+
+    /// Removes the status bar, making the top row scroll again
+    pub fn disable_status(&mut self) {
+        self.release_row(0);
+    }
+
+    /// Reserves a row with its own color, which isn't scrolled by new lines
+    ///
+    /// # Arguments
+    /// ```row```: the row to reserve, can't be the bottom row as text is written there
+    /// ```fg```: the foreground color of the row
+    /// ```bg```: the background color of the row
+    pub fn reserve_status_row(&mut self, row: usize, fg: Color, bg: Color) {
+        assert!(
+            row < self.dimensions.height - 1,
+            "Can't reserve the bottom row"
+        );
+        let color_code = ColorCode::new(fg, bg);
+        self.row_colors[row] = Some(color_code);
+        self.leave_reserved_row();
+
+        // recolor the current content of the row
+        for col in 0..self.dimensions.width {
+            let mut character = self.cell(row, col).read();
+            character.color_code = color_code;
+            self.cell_mut(row, col).write(character);
+        }
+    }
+
+    /// Moves the cursor to the next line, if it's on a reserved row
+    fn leave_reserved_row(&mut self) {
+        if self.row_colors[self.cursor_row].is_some() {
+            self.new_line();
+        }
+    }
+
+    /// Releases a reserved row, clearing it and making it scroll again
+    ///
+    /// # Arguments
+    /// ```row```: the row to release
+    pub fn release_row(&mut self, row: usize) {
+        self.row_colors[row] = None;
+        self.clear_row(row);
+    }
+
+    /// Confines scrolling to the rows from top to bottom (inclusive), like the VT100 DECSTBM
+    /// sequence. Rows outside of the region are left untouched by new lines, and the cursor moves
+    /// to the bottom row of the region. The default region is the full screen.
+    ///
+    /// # Arguments
+    /// ```top```: the first row of the scroll region
+    /// ```bottom```: the last row of the scroll region, below the first row, as a single row
+    /// can't scroll
+    ///
+    /// # Panics
+    /// If the region is less than 2 rows high, or doesn't fit on the screen
+    pub fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        assert!(
+            top < bottom,
+            "The bottom of the scroll region must be below the top"
+        );
+        assert!(
+            bottom < self.dimensions.height,
+            "The scroll region doesn't fit on the screen"
+        );
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+
+        // start at the beginning of the new bottom row
+        self.cursor_row = bottom;
+        self.column_position = 0;
+    }
+
+    /// Moves where the next character is written on the current row, without clearing anything,
+    /// so the next characters overwrite the existing ones
+    ///
+    /// # Arguments
+    /// ```col```: the column to write the next character in, clamped to the width of the screen
+    pub fn set_position(&mut self, col: usize) {
+        self.column_position = col.min(self.dimensions.width - 1);
+    }
+
+    /// Selects the row the next characters are written on, keeping the column position.
+    /// New lines move down to the next row, and only scroll from the last row of the scroll
+    /// region.
+    ///
+    /// # Arguments
+    /// ```row```: the row to write on, inside the scroll region
+    pub fn set_row(&mut self, row: usize) {
+        assert!(
+            (self.scroll_top..=self.scroll_bottom).contains(&row),
+            "The row is outside of the scroll region"
+        );
+        self.cursor_row = row;
+    }
+
+    /// Erases the character before the cursor and moves the cursor back onto its cell. Stops at
+    /// the start of the row, as the previous row may have been scrolled already.
+    pub fn backspace(&mut self) {
+        if self.column_position == 0 {
+            return;
+        }
+        self.column_position = self.column_position.min(self.dimensions.width) - 1;
+
+        let (row, col) = (self.cursor_row, self.column_position);
+        let color_code = self.color_code;
+        self.cell_mut(row, col).write(ScreenChar {
+            ascii_character: b' ',
+            color_code,
+        });
+    }
+
+    /// Returns the number of columns and rows shown in the current text mode
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// Returns the row the next character is written on
+    pub fn cursor_row(&self) -> usize {
+        self.cursor_row
+    }
+
+    /// Returns the number of rows shown in the current text mode
+    pub fn height(&self) -> usize {
+        self.dimensions.height
+    }
+
+    /// Changes the number of rows, keeping the bottom rows on the bottom of the screen.
+    /// Reserved rows are released and the scroll region is reset to the full screen.
+    ///
+    /// # Arguments
+    /// ```height```: the new number of rows
+    fn resize(&mut self, height: usize) {
+        assert!(
+            height <= MAX_BUFFER_HEIGHT && self.dimensions.width * height <= BUFFER_CELLS,
+            "The screen is too high"
+        );
+        self.row_colors = [None; MAX_BUFFER_HEIGHT];
+
+        if height < self.dimensions.height {
+            // move the bottom rows up to the new bottom
+            let shift = self.dimensions.height - height;
+            for row in 0..height {
+                for col in 0..self.dimensions.width {
+                    let character = self.cell(row + shift, col).read();
+                    self.cell_mut(row, col).write(character);
+                }
+            }
+        } else {
+            // move every row down to the new bottom, and clear the new rows on top.
+            // The new height is set first, as the new bottom rows are below the old screen.
+            let old_height = self.dimensions.height;
+            let shift = height - old_height;
+            self.dimensions.height = height;
+            for row in (0..old_height).rev() {
+                for col in 0..self.dimensions.width {
+                    let character = self.cell(row, col).read();
+                    self.cell_mut(row + shift, col).write(character);
+                }
+            }
+            for row in 0..shift {
+                self.clear_row(row);
+            }
+        }
+
+        self.dimensions.height = height;
+        self.scroll_top = 0;
+        self.scroll_bottom = height - 1;
+        self.cursor_row = height - 1;
+    }
+
+    /// Writes a string at a position, without moving the cursor or scrolling
+    ///
+    /// # Arguments
+    /// ```row```: the row to write on
+    /// ```col```: the column of the first character
+    /// ```s```: the string to write, truncated at the end of the row
+    /// ```fg```: the foreground color of the string
+    /// ```bg```: the background color of the string
+    pub fn write_at(&mut self, row: usize, col: usize, s: &str, fg: Color, bg: Color) {
+        assert!(row < self.dimensions.height, "The row is below the screen");
+        let color_code = ColorCode::new(fg, bg);
+        for (col, byte) in (col..self.dimensions.width).zip(s.bytes()) {
+            let ascii_character = match byte {
+                // printable character
+                0x20..=0x7e => byte,
+                // not part of printable ASCII range
+                _ => 0xfe,
+            };
+            self.cell_mut(row, col).write(ScreenChar {
+                ascii_character,
+                color_code,
+            });
         }
     }
 
+    /// Writes a string centered on a row, without moving the cursor or scrolling
+    ///
+    /// # Arguments
+    /// ```row```: the row to write on
+    /// ```s```: the string to write, truncated to the width of the screen
+    /// ```fg```: the foreground color of the string
+    /// ```bg```: the background color of the string
+    pub fn write_centered(&mut self, row: usize, s: &str, fg: Color, bg: Color) {
+        // start at the first column, if the string is too wide
+        let col = self.dimensions.width.saturating_sub(s.len()) / 2;
+        self.write_at(row, col, s, fg, bg);
+    }
+
+    /// Writes the text written after this call in the color of a role in the color scheme
+    ///
+    /// # Arguments
+    /// ```role```: the role of the text
+    pub fn set_role(&mut self, role: Role) {
+        self.color_code = scheme().color(role);
+    }
+
+    /// Writes in the color of a role, restoring the previous color afterwards, so the color of
+    /// the text written after it doesn't change, including whether it blinks
+    ///
+    /// # Arguments
+    /// ```role```: the role of the text
+    /// ```write```: the function writing the text
+    ///
+    /// # Returns
+    /// What the function returned
+    pub fn with_role<R>(&mut self, role: Role, write: impl FnOnce(&mut Writer) -> R) -> R {
+        let previous = self.color_code;
+        self.set_role(role);
+        let result = write(self);
+        self.color_code = previous;
+        result
+    }
+
+    /// Makes the text written after this call blink, or stop blinking
+    ///
+    /// # Arguments
+    /// ```blink```: whether the text should blink
+    pub fn set_blink(&mut self, blink: bool) {
+        self.color_code = self.color_code.with_blink(blink);
+    }
+
     /// Writes a string to the screen
     ///
     /// # Arguments
     /// ```s```: the string to write to the screen
     pub fn write_string(&mut self, s: &str) {
-        // iterate through the bytes in the string
-        for byte in s.bytes() {
-            match byte {
-                // printable character
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // not part of printable ASCII range
-                _ => self.write_byte(0xfe),
+        let mut bytes = s.as_bytes();
+        while let Some(&byte) = bytes.first() {
+            // move to a new line, if a new line character is printed
+            if byte == b'\n' {
+                self.new_line();
+                bytes = &bytes[1..];
+                continue;
+            }
+
+            // move back to the start of the row, if a carriage return is printed in CRLF mode
+            if byte == b'\r' && self.crlf {
+                self.column_position = 0;
+                bytes = &bytes[1..];
+                continue;
             }
+
+            // if we're at the end of the current line, first go to a new line
+            if self.column_position >= self.dimensions.width {
+                self.new_line();
+            }
+
+            // write the run of characters up to the next new line or the end of the row at once,
+            // instead of going through write_byte for every character
+            let crlf = self.crlf;
+            let run_length = bytes
+                .iter()
+                .take(self.dimensions.width - self.column_position)
+                .take_while(|&&byte| byte != b'\n' && !(crlf && byte == b'\r'))
+                .count();
+            let row = self.cursor_row;
+            let color_code = self.color_code;
+            for (col, &byte) in (self.column_position..).zip(&bytes[..run_length]) {
+                let ascii_character = match byte {
+                    // printable character
+                    0x20..=0x7e => byte,
+                    // not part of printable ASCII range
+                    _ => 0xfe,
+                };
+                self.cell_mut(row, col).write(ScreenChar {
+                    ascii_character,
+                    color_code,
+                });
+            }
+
+            self.column_position += run_length;
+            bytes = &bytes[run_length..];
         }
     }
-}
 
-// create a writer accessible from any module using this module
-lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) }
-    });
-}
+    /// Sets whether text uses \r\n line endings, like text meant for a serial terminal. A \r
+    /// then moves back to the start of the row, instead of being shown as an unprintable
+    /// character. A \n starts a new line either way.
+    ///
+    /// # Arguments
+    /// ```crlf```: whether \r is a carriage return
+    pub fn set_crlf(&mut self, crlf: bool) {
+        self.crlf = crlf;
+    }
 
-// prints formatted text to the screen
-#[macro_export]
-macro_rules! print {
-    ($($arg: tt)*) => ($crate::vga_buffer::_print(format_args!($($arg)*)));
-}
+    /// Copies a full screen of characters to the VGA buffer, row by row
+    ///
+    /// # Arguments
+    /// ```data```: the characters to show, exactly `width * height` long
+    ///
+    /// # Returns
+    /// An error if the data doesn't fill exactly one screen, nothing is written in that case
+    pub fn blit(&mut self, data: &[ScreenChar]) -> Result<(), BlitSizeError> {
+        if data.len() != self.dimensions.width * self.dimensions.height {
+            return Err(BlitSizeError { len: data.len() });
+        }
 
-// prints formatted text to the screen, ending with a new line
-#[macro_export]
-macro_rules! println {
-    () => (print!("\n"));
-    ($($arg:tt)*) => (print!("{}\n", format_args!($($arg)*)));
-}
+        for (row, characters) in data.chunks_exact(self.dimensions.width).enumerate() {
+            for (col, &character) in characters.iter().enumerate() {
+                self.cell_mut(row, col).write(character);
+            }
+        }
+        Ok(())
+    }
 
-// print formatted text to the screen
-#[doc(hidden)]
-pub fn _print(args: fmt::Arguments) {
-    use core::fmt::Write;
-    use x86_64::instructions::interrupts;
+    /// Writes bytes to the screen without replacing non-ASCII bytes, for pre-rendered content.
+    /// Bytes are interpreted as Code Page 437 glyphs, except for `\n` which starts a new line.
+    ///
+    /// # Arguments
+    /// ```bytes```: the bytes to write to the screen
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
 
-    // Run the following code without interrupts to prevent deadlocks
-    interrupts::without_interrupts(|| {
-        WRITER.lock().write_fmt(args).unwrap();
-    });
-}
+    /// Clears every row of the screen except the reserved rows, and moves the cursor to the
+    /// start of the first unreserved row of the scroll region
+    pub fn clear_screen(&mut self) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
 
-/// test whether println panics
-#[test_case]
-fn test_println_simple() {
-    println!("test_println_simple output");
-}
+        // Fill every run of consecutive rows that aren't reserved at once
+        let mut row = 0;
+        while row < self.dimensions.height {
+            if self.row_colors[row].is_some() {
+                row += 1;
+                continue;
+            }
+            let start = row;
+            while row < self.dimensions.height && self.row_colors[row].is_none() {
+                row += 1;
+            }
 
-///test whether printing many lines fails
-#[test_case]
-fn test_println_many() {
-    for _ in 0..1000 {
-        println!("test_println_many output");
+            let len = (row - start) * self.dimensions.width;
+            let start_index = self.cell_index(start, 0);
+            // check the last cell of the run as well, the fill is only checked at its start
+            let _ = self.cell_index(row - 1, self.dimensions.width - 1);
+            // The pointer is derived from the whole buffer, as the fill writes past the first cell
+            let cells = self.buffer.chars.as_mut_ptr() as *mut ScreenChar;
+            // Safe, as the rows from start up to row are inside the VGA buffer
+            unsafe { fill_cells(cells.add(start_index), len, blank) };
+        }
+        self.cursor_row = (self.scroll_top..=self.scroll_bottom)
+            .find(|&row| self.row_colors[row].is_none())
+            .unwrap_or(self.scroll_bottom);
+        self.column_position = 0;
     }
 }
 
-/// tests whether characters are really written to the vga buffer
-#[test_case]
-fn test_println_output() {
-    use core::fmt::Write;
+/// A rectangle of the screen with its own cursor, which clips and scrolls its text within its
+/// bounds. Multiple windows can be shown at the same time, every write takes the `WRITER` lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Window {
+    top: usize,
+    left: usize,
+    height: usize,
+    width: usize,
+    // The position the next character is written at, relative to the top left of the window
+    cursor_row: usize,
+    column_position: usize,
+}
+
+impl Window {
+    /// Creates a window, with the cursor in its top left corner
+    ///
+    /// # Arguments
+    /// ```top```: the first row of the window
+    /// ```left```: the first column of the window
+    /// ```height```: the number of rows of the window
+    /// ```width```: the number of columns of the window
+    ///
+    /// # Panics
+    /// If the window is empty
+    pub fn new(top: usize, left: usize, height: usize, width: usize) -> Window {
+        assert!(height > 0 && width > 0, "The window is empty");
+        Window {
+            top,
+            left,
+            height,
+            width,
+            cursor_row: 0,
+            column_position: 0,
+        }
+    }
+
+    /// Writes a string to the window, wrapping at its right edge and scrolling its rows when the
+    /// bottom row is full
+    ///
+    /// # Arguments
+    /// ```s```: the string to write
+    pub fn print_str(&mut self, s: &str) {
+        use x86_64::instructions::interrupts;
+
+        // Run without interrupts to prevent deadlocks
+        interrupts::without_interrupts(|| self.print_str_to(&mut WRITER.write(), s));
+    }
+
+    /// Fills the window with blanks, and moves the cursor to its top left corner
+    pub fn clear(&mut self) {
+        use x86_64::instructions::interrupts;
+
+        // Run without interrupts to prevent deadlocks
+        interrupts::without_interrupts(|| self.clear_on(&mut WRITER.write()));
+    }
+
+    /// Writes a string to the window on a writer's screen
+    ///
+    /// # Arguments
+    /// ```writer```: the writer of the screen the window is on
+    /// ```s```: the string to write
+    fn print_str_to(&mut self, writer: &mut Writer, s: &str) {
+        self.check_bounds(writer);
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.new_line(writer);
+                continue;
+            }
+
+            // if we're at the right edge of the window, first go to a new line
+            if self.column_position >= self.width {
+                self.new_line(writer);
+            }
+
+            let ascii_character = match byte {
+                // printable character
+                0x20..=0x7e => byte,
+                // not part of printable ASCII range
+                _ => 0xfe,
+            };
+            let color_code = writer.color_code;
+            writer
+                .cell_mut(self.top + self.cursor_row, self.left + self.column_position)
+                .write(ScreenChar {
+                    ascii_character,
+                    color_code,
+                });
+            self.column_position += 1;
+        }
+    }
+
+    /// Fills the window with blanks on a writer's screen, and moves the cursor to its top left
+    /// corner
+    ///
+    /// # Arguments
+    /// ```writer```: the writer of the screen the window is on
+    fn clear_on(&mut self, writer: &mut Writer) {
+        self.check_bounds(writer);
+        for row in 0..self.height {
+            self.clear_row(writer, row);
+        }
+        self.cursor_row = 0;
+        self.column_position = 0;
+    }
+
+    /// Moves the cursor to the next row of the window, scrolling the rows of the window up if
+    /// the cursor is on its bottom row
+    ///
+    /// # Arguments
+    /// ```writer```: the writer of the screen the window is on
+    fn new_line(&mut self, writer: &mut Writer) {
+        self.column_position = 0;
+        if self.cursor_row + 1 < self.height {
+            self.cursor_row += 1;
+            return;
+        }
+
+        // shift the rows of the window 1 line up, leaving the cells around it untouched
+        for row in 1..self.height {
+            for col in self.left..self.left + self.width {
+                let character = writer.cell(self.top + row, col).read();
+                writer.cell_mut(self.top + row - 1, col).write(character);
+            }
+        }
+        self.clear_row(writer, self.height - 1);
+    }
+
+    /// Clears a row of the window
+    ///
+    /// # Arguments
+    /// ```writer```: the writer of the screen the window is on
+    /// ```row```: the row to clear, relative to the top of the window
+    fn clear_row(&self, writer: &mut Writer, row: usize) {
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: writer.color_code,
+        };
+        for col in self.left..self.left + self.width {
+            writer.cell_mut(self.top + row, col).write(blank);
+        }
+    }
+
+    /// Makes sure the window fits on the screen, which may have been resized since it was created
+    ///
+    /// # Arguments
+    /// ```writer```: the writer of the screen the window is on
+    fn check_bounds(&self, writer: &Writer) {
+        let Dimensions { width, height } = writer.dimensions;
+        assert!(
+            self.top + self.height <= height && self.left + self.width <= width,
+            "The window doesn't fit on the screen"
+        );
+    }
+}
+
+impl fmt::Write for Window {
+    /// Writes formatted string to the window
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.print_str(s);
+        Ok(())
+    }
+}
+
+/// The characters of the Code Page 437 glyphs 0x80 up to and including 0xff, in order
+#[rustfmt::skip]
+const CP437_UPPER_HALF: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', // 0x80
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', // 0x90
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', // 0xa0
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', // 0xb0
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', // 0xc0
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', // 0xd0
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', // 0xe0
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}', // 0xf0
+];
+
+/// Translates a character to its Code Page 437 glyph
+///
+/// # Arguments
+/// ```c```: the character to translate
+///
+/// # Returns
+/// The glyph, or None if Code Page 437 doesn't contain the character.
+/// Control characters have no glyph, even though the VGA font shows symbols for them.
+pub fn to_cp437(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' => Some(c as u8),
+        _ => CP437_UPPER_HALF
+            .iter()
+            .position(|&glyph| glyph == c)
+            .map(|index| 0x80 + index as u8),
+    }
+}
+
+/// Fills consecutive cells of the VGA buffer with the same character, writing 4 cells at a time
+/// where possible. Every write is still volatile, as the buffer is memory mapped I/O.
+///
+/// # Arguments
+/// ```cells```: the first cell to fill
+/// ```len```: the number of cells to fill
+/// ```character```: the character to fill the cells with
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the `len` cells starting at
+/// `cells` are valid to write to, and not accessed through a reference at the same time.
+unsafe fn fill_cells(cells: *mut ScreenChar, len: usize, character: ScreenChar) {
+    // A cell in memory is the character, followed by its color code
+    let cell = u16::from(character.ascii_character) | u16::from(character.color_code.0) << 8;
+    let pattern = u64::from(cell) * 0x0001_0001_0001_0001;
+
+    let cells = cells as *mut u16;
+    let mut index = 0;
+
+    // Write single cells until the next cell is aligned for a 64-bit write
+    while index < len && (cells.add(index) as usize) % 8 != 0 {
+        core::ptr::write_volatile(cells.add(index), cell);
+        index += 1;
+    }
+
+    // Write 4 cells at a time
+    while len - index >= 4 {
+        core::ptr::write_volatile(cells.add(index) as *mut u64, pattern);
+        index += 4;
+    }
+
+    // Write the remaining cells
+    while index < len {
+        core::ptr::write_volatile(cells.add(index), cell);
+        index += 1;
+    }
+}
+
+/// Copies consecutive cells of the VGA buffer to a lower address, copying 4 cells at a time where
+/// possible. Every access is still volatile, as the buffer is memory mapped I/O.
+///
+/// # Arguments
+/// ```src```: the first cell to copy
+/// ```dst```: where to copy the first cell to, at most as high as `src`
+/// ```len```: the number of cells to copy
+///
+/// # Safety
+/// This function is unsafe because the caller must guarantee that the `len` cells starting at
+/// `src` and `dst` are valid to access, and not accessed through a reference at the same time.
+unsafe fn copy_cells(src: *const ScreenChar, dst: *mut ScreenChar, len: usize) {
+    debug_assert!(dst as usize <= src as usize);
+    let (src, dst) = (src as *const u16, dst as *mut u16);
+    let mut index = 0;
+
+    // Copying forward is correct for overlapping cells, as the destination is lower. Copy 4 cells
+    // at a time only when both are aligned the same, which they are for whole rows.
+    if (src as usize) % 8 == (dst as usize) % 8 {
+        while index < len && (src.add(index) as usize) % 8 != 0 {
+            core::ptr::write_volatile(dst.add(index), core::ptr::read_volatile(src.add(index)));
+            index += 1;
+        }
+        while len - index >= 4 {
+            let cells = core::ptr::read_volatile(src.add(index) as *const u64);
+            core::ptr::write_volatile(dst.add(index) as *mut u64, cells);
+            index += 4;
+        }
+    }
+
+    // Copy the remaining cells
+    while index < len {
+        core::ptr::write_volatile(dst.add(index), core::ptr::read_volatile(src.add(index)));
+        index += 1;
+    }
+}
+
+// create a writer accessible from any module using this module
+lazy_static! {
+    pub static ref WRITER: RwLock<Writer> =
+        RwLock::new(Writer::new(unsafe { &mut *(0xb8000 as *mut Buffer) }));
+}
+
+/// Changes the color scheme, switching new text to its normal color. Text already on the screen
+/// keeps its color.
+///
+/// # Arguments
+/// ```scheme```: the new color scheme
+pub fn set_scheme(scheme: ColorScheme) {
+    use x86_64::instructions::interrupts;
+
+    // Run without interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        *SCHEME.write() = scheme;
+        WRITER.write().set_role(Role::Normal);
+    });
+}
+
+/// Returns the current color scheme
+pub fn scheme() -> ColorScheme {
+    *SCHEME.read()
+}
+
+/// Switches the meaning of bit 7 of the attribute byte between blink and bright background
+///
+/// # Arguments
+/// ```enabled```: true to make the bit blink the character, false to brighten the background
+pub fn set_blink_enabled(enabled: bool) {
+    use x86_64::instructions::{interrupts, port::Port};
+
+    // The attribute mode control register of the attribute controller
+    const MODE_CONTROL_INDEX: u8 = 0x10;
+    // Keeps the palette enabled while the register is accessed, otherwise the screen goes blank
+    const PALETTE_ADDRESS_SOURCE: u8 = 0x20;
+    // The blink enable bit of the attribute mode control register
+    const BLINK_ENABLE: u8 = 0x08;
+
+    let mut input_status: Port<u8> = Port::new(0x3da);
+    let mut address_data: Port<u8> = Port::new(0x3c0);
+    let mut data_read: Port<u8> = Port::new(0x3c1);
+
+    // Run without interrupts, as the index/data flip-flop shouldn't be touched in between
+    interrupts::without_interrupts(|| unsafe {
+        // Reading the input status register resets the flip-flop to the index state
+        input_status.read();
+        address_data.write(MODE_CONTROL_INDEX | PALETTE_ADDRESS_SOURCE);
+        let mode = data_read.read();
+        let mode = if enabled {
+            mode | BLINK_ENABLE
+        } else {
+            mode & !BLINK_ENABLE
+        };
+
+        // The flip-flop is now in the data state, so this write goes to the selected register
+        address_data.write(mode);
+    });
+}
+
+/// Switches to the 80x50 text mode, by halving the font height to 8 pixels.
+/// The original 8x16 font is saved, and an 8x8 font is derived from it by merging every pair of
+/// pixel rows.
+///
+/// # Panics
+/// If the physical memory offset isn't known yet, as the font memory is accessed through it
+pub fn set_mode_80x50() {
+    set_text_mode(MAX_BUFFER_HEIGHT);
+}
+
+/// Switches back to the default 80x25 text mode, restoring the original 8x16 font
+///
+/// # Panics
+/// If the physical memory offset isn't known yet, as the font memory is accessed through it
+pub fn set_mode_80x25() {
+    set_text_mode(BUFFER_HEIGHT);
+}
+
+/// The size of a glyph in the font memory, of which only the first `font height` bytes are used
+const GLYPH_STRIDE: usize = 32;
+
+/// The number of glyphs in a font
+const GLYPH_COUNT: usize = 256;
+
+/// The height of the font in the 80x25 mode
+const DEFAULT_FONT_HEIGHT: usize = 16;
+
+// The 8x16 font of the 80x25 mode, saved while the 80x50 mode is used
+static SAVED_FONT: spin::Mutex<Option<[[u8; DEFAULT_FONT_HEIGHT]; GLYPH_COUNT]>> =
+    spin::Mutex::new(None);
+
+/// Switches between the 80x25 and 80x50 text modes.
+/// Both modes show 400 scan lines, so only the font height and the font itself change.
+///
+/// # Arguments
+/// ```height```: the number of rows of the new mode, either 25 or 50
+fn set_text_mode(height: usize) {
+    use crate::port::{self, VGA_CRTC_DATA, VGA_CRTC_INDEX};
+    use x86_64::instructions::interrupts;
+
+    // The maximum scan line register of the CRT controller, bits 0-4 store the font height - 1
+    const MAXIMUM_SCAN_LINE_INDEX: u8 = 0x09;
+    // The cursor start and end registers of the CRT controller
+    const CURSOR_START_INDEX: u8 = 0x0a;
+    const CURSOR_END_INDEX: u8 = 0x0b;
+
+    let font_height = 400 / height;
+    let font_memory = crate::memory::physical_memory_offset()
+        .expect("Physical memory offset not initialized")
+        + 0xa0000u64;
+    let font_memory = font_memory.as_mut_ptr::<u8>();
+
+    // Run without interrupts, and hold the writer, as the text buffer isn't accessible while the
+    // font memory is
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        if writer.dimensions.height == height {
+            return;
+        }
+
+        let mut saved_font = SAVED_FONT.lock();
+        // Safe, as the font memory is only accessed between these calls, with the writer held
+        unsafe {
+            enable_font_access();
+            if font_height < DEFAULT_FONT_HEIGHT {
+                // Save the 8x16 font, and replace it by merging every pair of rows
+                let font = saved_font.get_or_insert([[0; DEFAULT_FONT_HEIGHT]; GLYPH_COUNT]);
+                for (glyph, rows) in font.iter_mut().enumerate() {
+                    let glyph_memory = font_memory.add(glyph * GLYPH_STRIDE);
+                    for (row, byte) in rows.iter_mut().enumerate() {
+                        *byte = glyph_memory.add(row).read_volatile();
+                    }
+                    for row in 0..font_height {
+                        glyph_memory
+                            .add(row)
+                            .write_volatile(rows[row * 2] | rows[row * 2 + 1]);
+                    }
+                }
+            } else if let Some(font) = saved_font.take() {
+                // Restore the saved 8x16 font
+                for (glyph, rows) in font.iter().enumerate() {
+                    let glyph_memory = font_memory.add(glyph * GLYPH_STRIDE);
+                    for (row, &byte) in rows.iter().enumerate() {
+                        glyph_memory.add(row).write_volatile(byte);
+                    }
+                }
+            }
+            disable_font_access();
+
+            // Set the font height, and move the cursor to the last 2 scan lines of a character
+            port::write_u8(VGA_CRTC_INDEX, MAXIMUM_SCAN_LINE_INDEX);
+            let maximum_scan_line = port::read_u8(VGA_CRTC_DATA);
+            port::write_u8(
+                VGA_CRTC_DATA,
+                (maximum_scan_line & 0xe0) | (font_height - 1) as u8,
+            );
+            port::write_u8(VGA_CRTC_INDEX, CURSOR_START_INDEX);
+            port::write_u8(VGA_CRTC_DATA, (font_height - 2) as u8);
+            port::write_u8(VGA_CRTC_INDEX, CURSOR_END_INDEX);
+            port::write_u8(VGA_CRTC_DATA, (font_height - 1) as u8);
+        }
+
+        writer.resize(height);
+    });
+}
+
+/// Maps plane 2, which contains the font, at 0xA0000 instead of the text buffer at 0xB8000
+///
+/// # Safety
+/// This function is unsafe because the text buffer can't be accessed until
+/// `disable_font_access` is called.
+unsafe fn enable_font_access() {
+    write_indexed(0x3c4, 0x02, 0x04); // sequencer map mask: only write to plane 2
+    write_indexed(0x3c4, 0x04, 0x07); // sequencer memory mode: sequential access
+    write_indexed(0x3ce, 0x04, 0x02); // graphics controller read map select: read plane 2
+    write_indexed(0x3ce, 0x05, 0x00); // graphics controller mode: disable odd/even
+    write_indexed(0x3ce, 0x06, 0x04); // graphics controller miscellaneous: map at 0xA0000
+}
+
+/// Maps the text buffer at 0xB8000 again, with the default text mode settings
+///
+/// # Safety
+/// This function is unsafe because it must only be called after `enable_font_access`.
+unsafe fn disable_font_access() {
+    write_indexed(0x3c4, 0x02, 0x03); // sequencer map mask: write to plane 0 and 1
+    write_indexed(0x3c4, 0x04, 0x03); // sequencer memory mode: odd/even access
+    write_indexed(0x3ce, 0x04, 0x00); // graphics controller read map select: read plane 0
+    write_indexed(0x3ce, 0x05, 0x10); // graphics controller mode: enable odd/even
+    write_indexed(0x3ce, 0x06, 0x0e); // graphics controller miscellaneous: map at 0xB8000
+}
+
+/// Writes an indexed VGA register, where the data port directly follows the index port
+///
+/// # Arguments
+/// ```index_port```: the port to write the register index to
+/// ```index```: the index of the register
+/// ```value```: the value to write to the register
+///
+/// # Safety
+/// This function is unsafe because wrong values can break the display.
+unsafe fn write_indexed(index_port: u16, index: u8, value: u8) {
+    crate::port::write_u8(index_port, index);
+    crate::port::write_u8(index_port + 1, value);
+}
+
+/// Copies the contents of the screen
+///
+/// Only takes a read lock on `WRITER`, so multiple snapshots can be taken at the same time.
+///
+/// # Returns
+/// The character and attribute byte of every cell on the screen.
+/// Rows below the height of the current text mode are `(0, 0)`.
+pub fn snapshot() -> [[(u8, u8); BUFFER_WIDTH]; MAX_BUFFER_HEIGHT] {
+    use x86_64::instructions::interrupts;
+
+    let mut cells = [[(0, 0); BUFFER_WIDTH]; MAX_BUFFER_HEIGHT];
+
+    // Run without interrupts to prevent deadlocks with interrupt handlers that print
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.read();
+        let Dimensions { width, height } = writer.dimensions;
+        for (row, row_cells) in cells.iter_mut().take(height).enumerate() {
+            for (col, cell) in row_cells.iter_mut().take(width).enumerate() {
+                // Volatile reads only need a shared reference
+                let screen_char = writer.cell(row, col).read();
+                *cell = (screen_char.ascii_character, screen_char.color_code.0);
+            }
+        }
+    });
+    cells
+}
+
+/// Prints the text on the screen over serial, so the host can compare it with a golden file.
+///
+/// The block starts with a `=== SCREENSHOT <width>x<height> ===` line, followed by one line per
+/// row of exactly `width` characters, and ends with a `=== END SCREENSHOT ===` line.
+/// Colors are left out, and characters outside of printable ASCII are written as `.`.
+pub fn screenshot() {
+    // Copy the screen first, so WRITER is only locked briefly
+    let cells = snapshot();
+    let dimensions = WRITER.read().dimensions;
+
+    let mut serial = crate::serial::SerialWriter;
+    // A screenshot is best-effort, like printing over serial
+    let _ = write_screenshot(&cells, dimensions, &mut serial);
+}
+
+/// Writes the screenshot of a copy of the screen
+///
+/// # Arguments
+/// ```cells```: the copy of the screen, as returned by `snapshot`
+/// ```dimensions```: the number of columns and rows to write
+/// ```output```: where to write the screenshot to
+fn write_screenshot(
+    cells: &[[(u8, u8); BUFFER_WIDTH]],
+    dimensions: Dimensions,
+    output: &mut impl fmt::Write,
+) -> fmt::Result {
+    writeln!(
+        output,
+        "=== SCREENSHOT {}x{} ===",
+        dimensions.width, dimensions.height
+    )?;
+    for row in cells.iter().take(dimensions.height) {
+        for &(character, _) in row.iter().take(dimensions.width) {
+            let character = match character {
+                0x20..=0x7e => character as char,
+                _ => '.',
+            };
+            output.write_char(character)?;
+        }
+        output.write_char('\n')?;
+    }
+    writeln!(output, "=== END SCREENSHOT ===")
+}
+
+/// Writes a message to the top row of the screen, without locking `WRITER`
+///
+/// Only meant for situations where the writer can't be used, like nested faults.
+/// The message is truncated to the width of the screen.
+///
+/// # Arguments
+/// ```s```: the message to write
+pub(crate) fn emergency_print(s: &str) {
+    let color_code = ColorCode::new(Color::White, Color::Red);
+    let buffer = 0xb8000 as *mut ScreenChar;
+    for (col, byte) in s.bytes().take(BUFFER_WIDTH).enumerate() {
+        // Safe, as the VGA buffer is always mapped and col is within the first row
+        unsafe {
+            buffer.add(col).write_volatile(ScreenChar {
+                ascii_character: byte,
+                color_code,
+            });
+        }
+    }
+}
+
+// prints formatted text to the selected console, the screen by default
+#[macro_export]
+macro_rules! print {
+    ($($arg: tt)*) => ($crate::console::_print(format_args!($($arg)*)));
+}
+
+// prints formatted text to the selected console, ending with a new line
+#[macro_export]
+macro_rules! println {
+    () => (print!("\n"));
+    ($($arg:tt)*) => (print!("{}\n", format_args!($($arg)*)));
+}
+
+/// Releases `WRITER` by force, also when it's locked by code that will never release it
+///
+/// # Safety
+/// This function is unsafe because the holders of the lock could still use the writer at the same
+/// time. It's only sound once normal execution has stopped, like in a panic handler.
+pub unsafe fn force_unlock() {
+    if WRITER.writer_count() > 0 {
+        WRITER.force_write_unlock();
+    }
+    for _ in 0..WRITER.reader_count() {
+        WRITER.force_read_decrement();
+    }
+}
+
+/// Prints a panic message, also when the panic happened while `WRITER` or `SERIAL1` was locked,
+/// like in the middle of printing
+///
+/// # Arguments
+/// ```args```: the panic message
+///
+/// # Safety
+/// This function is unsafe for the same reasons as `force_unlock`, so only call it from a panic
+/// handler.
+pub unsafe fn panic_print(args: fmt::Arguments) {
+    force_unlock();
+    crate::serial::force_unlock();
+    print_to_screen(args);
+}
+
+/// Paints the whole screen red and shows a panic on it in white: the location and message,
+/// followed by a backtrace. The location and message are printed like any other text, so they're
+/// still mirrored over serial with the `mirror-serial` feature.
+///
+/// # Arguments
+/// ```info```: the location and message of the panic
+///
+/// # Safety
+/// This function is unsafe for the same reasons as `force_unlock`, so only call it from a panic
+/// handler.
+pub unsafe fn panic_screen(info: &PanicInfo) {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    // The panic may have happened while printing
+    force_unlock();
+    crate::serial::force_unlock();
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.color_code = ColorCode::new(Color::White, Color::Red);
+
+        // Release the reserved rows and the scroll region, so the whole screen is painted
+        let height = writer.dimensions.height;
+        writer.row_colors = [None; MAX_BUFFER_HEIGHT];
+        writer.set_scroll_region(0, height - 1);
+        writer.clear_screen();
+    });
+
+    print_to_screen(format_args!("{}\n", info));
+
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let _ = writeln!(writer, "\nBacktrace:");
+        crate::debug::walk_stack(|address| {
+            let _ = writeln!(writer, "  {:#018x}", address.as_u64());
+        });
+    });
+}
+
+// print formatted text to the VGA text buffer, also when another console is selected, for the
+// panic messages
+fn print_to_screen(args: fmt::Arguments) {
+    use x86_64::instructions::interrupts;
+
+    // Run the following code without interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        print_to(&WRITER, args);
+
+        #[cfg(feature = "mirror-serial")]
+        crate::serial::_mirror(args);
+    });
+}
+
+/// Writes formatted text to a screen, dropping the result. Writing to the screen can't really
+/// fail, and panicking on an error would print again from the panic handler, failing in a loop.
+///
+/// # Arguments
+/// ```writer```: the screen to write the text to, locked while writing
+/// ```args```: the arguments to parse and write
+fn print_to(writer: &RwLock<impl fmt::Write>, args: fmt::Arguments) {
+    let _ = writer.write().write_fmt(args);
+}
+
+/// test whether println panics
+#[test_case]
+fn test_println_simple() {
+    println!("test_println_simple output");
+}
+
+///test whether printing many lines fails
+#[test_case]
+fn test_println_many() {
+    for _ in 0..1000 {
+        println!("test_println_many output");
+    }
+}
+
+/// tests whether characters are really written to the vga buffer
+#[test_case]
+fn test_println_output() {
+    use core::fmt::Write;
     use x86_64::instructions::interrupts;
     let s = "Some test string that fits on a single line";
     // Disable interrupts to prevent deadlocks
     interrupts::without_interrupts(|| {
-        let mut writer = WRITER.lock();
+        let mut writer = WRITER.write();
         writeln!(writer, "\n{}", s).expect("Writeln failed");
         for (i, c) in s.chars().enumerate() {
-            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = writer.cell(writer.cursor_row - 1, i).read();
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     });
 }
+
+/// tests whether the cells on the bottom and right edges are still inside the screen
+#[test_case]
+fn test_cell_edges() {
+    use x86_64::instructions::interrupts;
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let Dimensions { width, height } = writer.dimensions;
+        writer.write_at(height - 1, width - 1, "x", Color::Yellow, Color::Black);
+        assert_eq!(
+            writer.cell(height - 1, width - 1).read().ascii_character,
+            b'x'
+        );
+        assert_eq!(writer.cell_index(height - 1, width - 1), width * height - 1);
+        writer.clear_row(height - 1);
+    });
+}
+
+/// tests whether println writes to the screen, when no other console was selected
+#[test_case]
+fn test_println_reaches_screen() {
+    use x86_64::instructions::interrupts;
+    let s = "Some test string printed through the console";
+    println!("\n{}", s);
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.write();
+        for (i, c) in s.chars().enumerate() {
+            let screen_char = writer.cell(writer.cursor_row - 1, i).read();
+            assert_eq!(char::from(screen_char.ascii_character), c);
+        }
+    });
+}
+
+/// tests whether an error log is written in the error color of the color scheme
+#[test_case]
+fn test_error_log_uses_scheme() {
+    use x86_64::instructions::interrupts;
+
+    let error = ColorCode::new(Color::White, Color::Red);
+    set_scheme(ColorScheme {
+        error,
+        ..ColorScheme::default()
+    });
+    crate::error!("test_error_log_uses_scheme");
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.read();
+        let row = writer.cursor_row - 1;
+        assert_eq!(writer.cell(row, 0).read().color_code, error);
+        assert_eq!(writer.color_code, DEFAULT_SCHEME.normal);
+    });
+    set_scheme(ColorScheme::default());
+}
+
+/// tests whether printing in the color of a role keeps the color set before, like blinking
+#[test_case]
+fn test_print_as_keeps_color() {
+    use x86_64::instructions::interrupts;
+
+    let blinking = interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.set_blink(true);
+        writer.color_code
+    });
+    crate::console::_print_as(Role::Error, format_args!("test_print_as_keeps_color\n"));
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        assert_eq!(writer.color_code, blinking);
+        writer.set_blink(false);
+    });
+}
+
+/// tests whether the blink flag sets bit 7 of the attribute byte
+#[test_case]
+fn test_blink_attribute() {
+    use x86_64::instructions::interrupts;
+
+    let color_code = ColorCode::new(Color::Yellow, Color::Black);
+    assert_eq!(color_code.with_blink(true).0, 0x8e);
+    assert_eq!(color_code.with_blink(true).with_blink(false), color_code);
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.set_blink(true);
+        writer.write_byte(b'b');
+        writer.set_blink(false);
+        let screen_char = writer
+            .cell(writer.cursor_row, writer.column_position - 1)
+            .read();
+        assert_eq!(screen_char.color_code.0 & 0x80, 0x80);
+    });
+}
+
+/// tests whether the status bar survives new lines
+#[test_case]
+fn test_status_bar_survives_new_lines() {
+    use x86_64::instructions::interrupts;
+    let status = "Status bar";
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.set_status(status);
+        for _ in 0..BUFFER_HEIGHT {
+            writer.write_byte(b'\n');
+        }
+        for (i, c) in status.chars().enumerate() {
+            let screen_char = writer.cell(0, i).read();
+            assert_eq!(char::from(screen_char.ascii_character), c);
+            assert_eq!(screen_char.color_code, STATUS_COLOR);
+        }
+        writer.disable_status();
+    });
+}
+
+/// tests whether raw bytes are written to the vga buffer unchanged
+#[test_case]
+fn test_write_raw_box_drawing() {
+    use x86_64::instructions::interrupts;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.write_raw(b"\n\xc9");
+        let screen_char = writer.cell(writer.cursor_row, 0).read();
+        assert_eq!(screen_char.ascii_character, 0xc9);
+    });
+}
+
+/// tests whether a blitted screen is copied to the vga buffer
+#[test_case]
+fn test_blit() {
+    use alloc::vec::Vec;
+    use x86_64::instructions::interrupts;
+
+    let screen: Vec<ScreenChar> = (0..BUFFER_WIDTH * BUFFER_HEIGHT)
+        .map(|i| ScreenChar::new(i as u8, Color::LightGreen, Color::Blue))
+        .collect();
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        assert_eq!(
+            writer.blit(&screen[1..]),
+            Err(BlitSizeError {
+                len: screen.len() - 1
+            })
+        );
+        writer.blit(&screen).expect("Blit failed");
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let screen_char = writer.cell(row, col).read();
+                assert_eq!(screen_char, screen[row * BUFFER_WIDTH + col]);
+            }
+        }
+    });
+}
+
+/// tests whether a snapshot contains the characters and colors on the screen
+#[test_case]
+fn test_snapshot() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    let s = "Snapshot test string";
+    // Disable interrupts to prevent deadlocks
+    let (color_code, row) = interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writeln!(writer, "\n{}", s).expect("Writeln failed");
+        (writer.color_code, writer.cursor_row - 1)
+    });
+
+    let cells = snapshot();
+    for (i, c) in s.bytes().enumerate() {
+        assert_eq!(cells[row][i], (c, color_code.0));
+    }
+}
+
+/// tests whether a reserved row keeps its color and content while scrolling
+#[test_case]
+fn test_reserved_row_survives_scrolling() {
+    use x86_64::instructions::interrupts;
+    let status = "Reserved row";
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.reserve_status_row(0, Color::White, Color::Blue);
+        writer.set_status(status);
+        for _ in 0..BUFFER_HEIGHT * 2 {
+            writer.write_string("scrolling\n");
+        }
+        for col in 0..BUFFER_WIDTH {
+            let screen_char = writer.cell(0, col).read();
+            let expected = status.as_bytes().get(col).copied().unwrap_or(b' ');
+            assert_eq!(screen_char.ascii_character, expected);
+            assert_eq!(
+                screen_char.color_code,
+                ColorCode::new(Color::White, Color::Blue)
+            );
+        }
+        writer.release_row(0);
+    });
+}
+
+/// tests whether every color survives a round-trip through its numeric value
+#[test_case]
+fn test_color_round_trip() {
+    for (i, &color) in Color::ALL.iter().enumerate() {
+        assert_eq!(color as usize, i);
+        assert_eq!(Color::try_from(color as u8), Ok(color));
+    }
+    assert_eq!(Color::try_from(16), Err(16));
+}
+
+/// tests whether ANSI foreground codes map to the right colors
+#[test_case]
+fn test_color_from_ansi_fg() {
+    assert_eq!(Color::from_ansi_fg(30), Some(Color::Black));
+    assert_eq!(Color::from_ansi_fg(31), Some(Color::Red));
+    assert_eq!(Color::from_ansi_fg(33), Some(Color::Brown));
+    assert_eq!(Color::from_ansi_fg(93), Some(Color::Yellow));
+    assert_eq!(Color::from_ansi_fg(97), Some(Color::White));
+    assert_eq!(Color::from_ansi_fg(38), None);
+}
+
+/// tests whether scrolling within a scroll region leaves the other rows untouched
+#[test_case]
+fn test_scroll_region() {
+    use x86_64::instructions::interrupts;
+    const TOP: usize = 5;
+    const BOTTOM: usize = 15;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let mut before =
+            [[ScreenChar::new(0, Color::Black, Color::Black); BUFFER_WIDTH]; BUFFER_HEIGHT];
+        for (row, characters) in before.iter_mut().enumerate() {
+            for (col, character) in characters.iter_mut().enumerate() {
+                *character = writer.cell(row, col).read();
+            }
+        }
+
+        writer.set_scroll_region(TOP, BOTTOM);
+        for _ in 0..BUFFER_HEIGHT * 2 {
+            writer.write_string("scroll region\n");
+        }
+        writer.write_string("last");
+
+        for row in (0..TOP).chain(BOTTOM + 1..BUFFER_HEIGHT) {
+            for col in 0..BUFFER_WIDTH {
+                assert_eq!(writer.cell(row, col).read(), before[row][col]);
+            }
+        }
+        for (col, c) in "last".bytes().enumerate() {
+            assert_eq!(writer.cell(BOTTOM, col).read().ascii_character, c);
+        }
+        assert_eq!(writer.cell(BOTTOM - 1, 0).read().ascii_character, b's');
+
+        writer.set_scroll_region(0, BUFFER_HEIGHT - 1);
+    });
+}
+
+/// tests whether switching between 80x25 and 80x50 keeps the bottom rows on the bottom
+#[test_case]
+fn test_switch_80x50_and_back() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+    let s = "80x50 test string";
+
+    set_mode_80x50();
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        assert_eq!(writer.height(), MAX_BUFFER_HEIGHT);
+        writeln!(writer, "\n{}", s).expect("Writeln failed");
+        for (i, c) in s.bytes().enumerate() {
+            let screen_char = writer.cell(MAX_BUFFER_HEIGHT - 2, i).read();
+            assert_eq!(screen_char.ascii_character, c);
+        }
+    });
+
+    set_mode_80x25();
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.read();
+        assert_eq!(writer.height(), BUFFER_HEIGHT);
+        for (i, c) in s.bytes().enumerate() {
+            let screen_char = writer.cell(BUFFER_HEIGHT - 2, i).read();
+            assert_eq!(screen_char.ascii_character, c);
+        }
+    });
+}
+
+/// tests whether a carriage return moves back to the start of the row only in CRLF mode
+#[test_case]
+fn test_crlf_mode() {
+    let mut writer = test_writer();
+    let row_text = |writer: &Writer| -> [u8; 4] {
+        core::array::from_fn(|col| writer.cell(writer.cursor_row(), col).read().ascii_character)
+    };
+
+    writer.write_string("ab\rc");
+    assert_eq!(&row_text(&writer), b"ab\xfec");
+
+    writer.set_crlf(true);
+    writer.write_string("\nab\rc");
+    assert_eq!(&row_text(&writer), b"cb  ");
+
+    // write_byte does the same
+    writer.write_byte(b'\n');
+    for byte in "ab\rc".bytes() {
+        writer.write_byte(byte);
+    }
+    assert_eq!(&row_text(&writer), b"cb  ");
+    assert_eq!(writer.cursor_row(), 2);
+}
+
+/// tests whether growing the screen moves the written rows down to the new bottom
+#[test_case]
+fn test_resize_grow_keeps_content() {
+    let mut writer = test_writer();
+    for row in 0..BUFFER_HEIGHT {
+        if row > 0 {
+            writer.write_byte(b'\n');
+        }
+        writer.write_byte(b'a' + row as u8);
+    }
+
+    writer.resize(MAX_BUFFER_HEIGHT);
+    let shift = MAX_BUFFER_HEIGHT - BUFFER_HEIGHT;
+    assert_eq!(writer.height(), MAX_BUFFER_HEIGHT);
+    for row in 0..BUFFER_HEIGHT {
+        assert_eq!(
+            writer.cell(row + shift, 0).read().ascii_character,
+            b'a' + row as u8
+        );
+    }
+    for row in 0..shift {
+        assert_eq!(writer.cell(row, 0).read().ascii_character, b' ');
+    }
+
+    // shrinking back keeps the bottom rows
+    writer.resize(BUFFER_HEIGHT);
+    assert_eq!(writer.cell(0, 0).read().ascii_character, b'a');
+    assert_eq!(
+        writer.cell(BUFFER_HEIGHT - 1, 0).read().ascii_character,
+        b'a' + (BUFFER_HEIGHT - 1) as u8
+    );
+}
+
+/// tests whether writing a string produces the same screen as writing it byte by byte
+#[test_case]
+fn test_write_string_matches_write_byte() {
+    use alloc::vec::Vec;
+    use x86_64::instructions::interrupts;
+
+    // long enough to wrap, with new lines, unprintable characters, and a multi-byte character
+    let s = "first line\nsecond line with an unprintable \x07 character and a multi-byte \u{e9} \
+             character, long enough to wrap around the end of the row\n\nlast";
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.write_byte(b'\n');
+        let copy_screen = |writer: &Writer| -> Vec<ScreenChar> {
+            let Dimensions { width, height } = writer.dimensions;
+            (0..height)
+                .flat_map(|row| (0..width).map(move |col| (row, col)))
+                .map(|(row, col)| writer.cell(row, col).read())
+                .collect()
+        };
+        let before = copy_screen(&writer);
+        let before_row = writer.cursor_row;
+
+        // the naive path, every character through write_byte
+        for byte in s.bytes() {
+            match byte {
+                0x20..=0x7e | b'\n' => writer.write_byte(byte),
+                _ => writer.write_byte(0xfe),
+            }
+        }
+        let naive = copy_screen(&writer);
+        let naive_column = writer.column_position;
+
+        // restore the screen, then take the optimized path
+        writer.blit(&before).expect("Blit failed");
+        writer.cursor_row = before_row;
+        writer.column_position = 0;
+        writer.write_string(s);
+        assert!(copy_screen(&writer) == naive, "The screens differ");
+        assert_eq!(writer.column_position, naive_column);
+    });
+}
+
+/// tests whether cells are found at their offset from the start of the VGA buffer
+#[test_case]
+fn test_cell_offsets() {
+    use x86_64::instructions::interrupts;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let writer = WRITER.read();
+        assert_eq!(
+            writer.dimensions(),
+            Dimensions {
+                width: BUFFER_WIDTH,
+                height: BUFFER_HEIGHT
+            }
+        );
+        for (row, col) in [
+            (0, 0),
+            (0, BUFFER_WIDTH - 1),
+            (1, 0),
+            (BUFFER_HEIGHT - 1, 7),
+        ] {
+            let address = writer.cell(row, col) as *const Volatile<ScreenChar> as usize;
+            assert_eq!(address, 0xb8000 + (row * BUFFER_WIDTH + col) * 2);
+        }
+    });
+}
+
+/// tests whether a centered string is written in the middle of the row, and truncated if too long
+#[test_case]
+fn test_write_centered() {
+    use x86_64::instructions::interrupts;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.write_centered(12, "MENU", Color::White, Color::Blue);
+        for (i, c) in "MENU".bytes().enumerate() {
+            let screen_char = writer.cell(12, 38 + i).read();
+            assert_eq!(screen_char, ScreenChar::new(c, Color::White, Color::Blue));
+        }
+
+        let long = [b'x'; BUFFER_WIDTH + 10];
+        let long = core::str::from_utf8(&long).unwrap();
+        writer.write_centered(12, long, Color::White, Color::Blue);
+        assert_eq!(writer.cell(12, 0).read().ascii_character, b'x');
+        assert_eq!(
+            writer.cell(12, BUFFER_WIDTH - 1).read().ascii_character,
+            b'x'
+        );
+    });
+}
+
+/// tests whether clearing the screen blanks every row but the reserved ones, and measures it
+/// against clearing cell by cell, in TSC ticks
+#[test_case]
+fn test_clear_screen() {
+    use crate::time::rdtsc;
+    use x86_64::instructions::interrupts;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.reserve_status_row(3, Color::White, Color::Blue);
+        writer.write_at(3, 0, "kept", Color::White, Color::Blue);
+        writer.write_string("some text\nmore");
+
+        let start = rdtsc();
+        for row in 0..BUFFER_HEIGHT {
+            if writer.row_colors[row].is_none() {
+                writer.clear_row(row);
+            }
+        }
+        let middle = rdtsc();
+        writer.clear_screen();
+        let end = rdtsc();
+        crate::serial_println!(
+            "clear_row: {} ticks, clear_screen: {} ticks",
+            middle - start,
+            end - middle
+        );
+
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: writer.color_code,
+        };
+        for row in (0..BUFFER_HEIGHT).filter(|&row| row != 3) {
+            for col in 0..BUFFER_WIDTH {
+                assert_eq!(writer.cell(row, col).read(), blank);
+            }
+        }
+        assert_eq!(writer.cell(3, 0).read().ascii_character, b'k');
+        writer.release_row(3);
+    });
+}
+
+/// tests whether moving the position back overwrites a character in the middle of the line, and
+/// whether a selected row is written on
+#[test_case]
+fn test_set_position_and_row() {
+    use x86_64::instructions::interrupts;
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.write_string("\nabcdef");
+        writer.set_position(2);
+        writer.write_byte(b'X');
+
+        let line = writer.cursor_row;
+        let row: [u8; 6] =
+            core::array::from_fn(|col| writer.cell(line, col).read().ascii_character);
+        assert_eq!(&row, b"abXdef");
+        assert_eq!(writer.column_position, 3);
+
+        // the position is clamped to the last column
+        writer.set_position(BUFFER_WIDTH + 5);
+        assert_eq!(writer.column_position, BUFFER_WIDTH - 1);
+
+        // a new line on a selected row moves to the next row, without scrolling
+        let selected = if line < 10 { 20 } else { 5 };
+        writer.set_row(selected);
+        writer.set_position(0);
+        writer.write_string("up\nhere");
+        assert_eq!(writer.cell(selected, 0).read().ascii_character, b'u');
+        assert_eq!(writer.cell(selected + 1, 3).read().ascii_character, b'e');
+        assert_eq!(writer.cell(line, 2).read().ascii_character, b'X');
+
+        writer.set_row(line);
+        writer.write_byte(b'\n');
+    });
+}
+
+/// tests whether a screenshot contains the framed rows, with the text written last on its row
+#[test_case]
+fn test_screenshot() {
+    use alloc::string::String;
+    use x86_64::instructions::interrupts;
+
+    // Disable interrupts, so the timer can't print between writing and taking the snapshot
+    let (cells, row) = interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        writer.write_string("\nscreenshot \x01text");
+        let row = writer.cursor_row;
+        drop(writer);
+        (snapshot(), row)
+    });
+    let dimensions = Dimensions {
+        width: BUFFER_WIDTH,
+        height: BUFFER_HEIGHT,
+    };
+
+    let mut output = String::new();
+    write_screenshot(&cells, dimensions, &mut output).expect("Writing the screenshot failed");
+    let lines: alloc::vec::Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), BUFFER_HEIGHT + 2);
+    assert_eq!(lines[0], "=== SCREENSHOT 80x25 ===");
+    assert_eq!(lines[BUFFER_HEIGHT + 1], "=== END SCREENSHOT ===");
+    assert!(lines[1..=BUFFER_HEIGHT]
+        .iter()
+        .all(|line| line.len() == BUFFER_WIDTH));
+    assert!(lines[row + 1].starts_with("screenshot .text "));
+
+    screenshot();
+}
+
+/// Creates a writer on a buffer in normal memory, as if the kernel just booted
+#[cfg(test)]
+fn test_writer() -> Writer {
+    static mut TEST_BUFFER: [u16; BUFFER_CELLS] = [0; BUFFER_CELLS];
+
+    // Every test writer starts by clearing the buffer, so reusing it is fine
+    Writer::new(unsafe { &mut *(core::ptr::addr_of_mut!(TEST_BUFFER) as *mut Buffer) })
+}
+
+/// tests whether output after booting fills the screen from the top row downward
+#[test_case]
+fn test_fresh_writer_fills_from_top() {
+    let mut writer = test_writer();
+    assert_eq!(writer.cursor_row(), 0);
+
+    writer.write_string("first\nsecond\nthird");
+    assert_eq!(writer.cell(0, 0).read().ascii_character, b'f');
+    assert_eq!(writer.cell(1, 0).read().ascii_character, b's');
+    assert_eq!(writer.cell(2, 0).read().ascii_character, b't');
+    assert_eq!(writer.cursor_row(), 2);
+}
+
+/// tests whether the screen only scrolls once the bottom row is reached
+#[test_case]
+fn test_fresh_writer_scrolls_once() {
+    let mut writer = test_writer();
+
+    // fill every row, without scrolling
+    for row in 0..BUFFER_HEIGHT {
+        if row > 0 {
+            writer.write_byte(b'\n');
+        }
+        writer.write_byte(b'a' + row as u8);
+    }
+    assert_eq!(writer.cursor_row(), BUFFER_HEIGHT - 1);
+    assert_eq!(writer.cell(0, 0).read().ascii_character, b'a');
+
+    // the next line scrolls every row up once
+    writer.write_string("\nz");
+    assert_eq!(writer.cursor_row(), BUFFER_HEIGHT - 1);
+    assert_eq!(writer.cell(0, 0).read().ascii_character, b'b');
+    assert_eq!(
+        writer.cell(BUFFER_HEIGHT - 2, 0).read().ascii_character,
+        b'a' + (BUFFER_HEIGHT - 1) as u8
+    );
+    assert_eq!(
+        writer.cell(BUFFER_HEIGHT - 1, 0).read().ascii_character,
+        b'z'
+    );
+}
+
+/// tests whether box-drawing and other Unicode characters are translated to their Code Page 437
+/// glyphs, and unmappable ones to 0xfe
+#[test_case]
+fn test_write_char_cp437() {
+    use core::fmt::Write;
+    use x86_64::instructions::interrupts;
+
+    assert_eq!(to_cp437('─'), Some(0xc4));
+    assert_eq!(to_cp437('│'), Some(0xb3));
+    assert_eq!(to_cp437('é'), Some(0x82));
+    assert_eq!(to_cp437('A'), Some(b'A'));
+    assert_eq!(to_cp437('€'), None);
+
+    // Disable interrupts to prevent deadlocks
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        for c in "\n┌─┐ 20°C ±1 €".chars() {
+            writer.write_char(c).expect("Writing failed");
+        }
+        let row = writer.cursor_row;
+        let expected = b"\xda\xc4\xbf 20\xf8C \xf11 \xfe";
+        for (col, &byte) in expected.iter().enumerate() {
+            assert_eq!(writer.cell(row, col).read().ascii_character, byte);
+        }
+    });
+}
+
+/// tests whether a panic message is printed while WRITER is locked, instead of deadlocking
+#[test_case]
+fn test_panic_print_while_locked() {
+    use x86_64::instructions::interrupts;
+
+    let message = "panic while printing";
+    // Disable interrupts, so the timer doesn't deadlock on the leaked lock
+    interrupts::without_interrupts(|| {
+        // leak a lock, as if the panic happened in the middle of printing
+        core::mem::forget(WRITER.write());
+
+        unsafe { panic_print(format_args!("\n{}", message)) };
+
+        let writer = WRITER.read();
+        for (col, c) in message.bytes().enumerate() {
+            assert_eq!(
+                writer.cell(writer.cursor_row, col).read().ascii_character,
+                c
+            );
+        }
+    });
+}
+
+/// tests whether a failing write is ignored instead of panicking
+#[test_case]
+fn test_write_error_ignored() {
+    let screen = RwLock::new(crate::FailingWriter);
+    print_to(&screen, format_args!("lost {}", 1));
+    assert!(screen.try_write().is_some());
+    println!("write_error_ignored output");
+}
+
+/// tests whether text printed to a window scrolls within the window, without touching the cells
+/// around it
+#[test_case]
+fn test_window_scrolls_within_bounds() {
+    let mut writer = test_writer();
+
+    // fill the screen with a border character, to detect writes outside of the window
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            writer
+                .cell_mut(row, col)
+                .write(ScreenChar::new(b'#', Color::White, Color::Black));
+        }
+    }
+
+    let mut window = Window::new(2, 2, 5, 20);
+    window.clear_on(&mut writer);
+    for line in 0..7 {
+        if line > 0 {
+            window.print_str_to(&mut writer, "\n");
+        }
+        window.print_str_to(&mut writer, "line ");
+        window.print_str_to(&mut writer, core::str::from_utf8(&[b'0' + line]).unwrap());
+    }
+
+    // lines 0 and 1 were scrolled out, lines 2 up to 6 fill the window
+    for row in 0..5 {
+        let expected = [b'l', b'i', b'n', b'e', b' ', b'2' + row as u8];
+        for (col, &byte) in expected.iter().enumerate() {
+            assert_eq!(writer.cell(2 + row, 2 + col).read().ascii_character, byte);
+        }
+        assert_eq!(writer.cell(2 + row, 2 + 6).read().ascii_character, b' ');
+    }
+
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            if (2..7).contains(&row) && (2..22).contains(&col) {
+                continue;
+            }
+            assert_eq!(writer.cell(row, col).read().ascii_character, b'#');
+        }
+    }
+}
+
+/// tests whether backspace erases the last character and stops at the start of the row
+#[test_case]
+fn test_backspace() {
+    let mut writer = test_writer();
+    writer.write_string("ab");
+    writer.backspace();
+    assert_eq!(writer.cell(0, 1).read().ascii_character, b' ');
+    writer.write_byte(b'c');
+    assert_eq!(writer.cell(0, 1).read().ascii_character, b'c');
+
+    writer.backspace();
+    writer.backspace();
+    writer.backspace();
+    assert_eq!(writer.cell(0, 0).read().ascii_character, b' ');
+    assert_eq!(writer.cursor_row(), 0);
+}
+
+/// tests whether scrolling with the bulk copy gives the same screen as copying every cell, with
+/// reserved rows and a scroll region
+#[test_case]
+fn test_scroll_up_matches_per_cell() {
+    static mut OTHER_BUFFER: [u16; BUFFER_CELLS] = [0; BUFFER_CELLS];
+
+    let mut fast = test_writer();
+    let mut slow =
+        Writer::new(unsafe { &mut *(core::ptr::addr_of_mut!(OTHER_BUFFER) as *mut Buffer) });
+    for writer in [&mut fast, &mut slow] {
+        for row in 0..BUFFER_HEIGHT {
+            for col in 0..BUFFER_WIDTH {
+                let character = ScreenChar::new((row * 7 + col) as u8, Color::White, Color::Black);
+                writer.cell_mut(row, col).write(character);
+            }
+        }
+        writer.reserve_status_row(0, Color::Black, Color::LightGray);
+        writer.reserve_status_row(5, Color::Black, Color::LightGray);
+        writer.set_scroll_region(0, BUFFER_HEIGHT - 3);
+    }
+
+    for _ in 0..3 {
+        fast.scroll_up();
+        slow.scroll_up_per_cell();
+    }
+    for row in 0..BUFFER_HEIGHT {
+        for col in 0..BUFFER_WIDTH {
+            assert_eq!(fast.cell(row, col).read(), slow.cell(row, col).read());
+        }
+    }
+}
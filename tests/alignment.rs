@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    panic::PanicInfo,
+};
+
+use blog_os::{
+    allocator::{
+        bump::BumpAllocator, linked_list::LinkedListAllocator, test_allocator, ALLOCATOR,
+        TEST_HEAP_SIZE,
+    },
+    hlt_loop, serial_print,
+};
+use bootloader::{entry_point, BootInfo};
+
+extern crate alloc;
+
+/// The alignments to check, including one larger than the largest block size (2048) of the
+/// fixed-size block allocator, which is served by its fallback allocator
+const ALIGNMENTS: [usize; 4] = [64, 128, 4096, 8192];
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::try_init(boot_info).expect("Kernel initialization failed");
+
+    test_main();
+    hlt_loop();
+}
+
+/// Allocates with every alignment, and checks whether the returned pointers are aligned.
+/// Alignments the heap is too small for are skipped.
+///
+/// # Arguments
+/// ```allocator```: the allocator to check
+fn check_alignments(allocator: &impl GlobalAlloc) {
+    for align in ALIGNMENTS {
+        // Sizes both smaller than and equal to the alignment
+        for size in [8, align] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = unsafe { allocator.alloc(layout) };
+            if ptr.is_null() {
+                serial_print!("(skipped {} bytes aligned to {}) ", size, align);
+                continue;
+            }
+
+            assert_eq!(
+                ptr as usize % align,
+                0,
+                "{} bytes not aligned to {}",
+                size,
+                align
+            );
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+    }
+}
+
+/// Checks the alignment of allocations from the global allocator, the fixed-size block allocator
+/// unless another one is selected with a feature
+#[test_case]
+fn global_allocator_alignment() {
+    check_alignments(unsafe { &ALLOCATOR });
+}
+
+/// Checks the alignment of allocations from the linked list allocator
+#[test_case]
+fn linked_list_alignment() {
+    let allocator = unsafe {
+        test_allocator(
+            LinkedListAllocator::new(),
+            LinkedListAllocator::init,
+            TEST_HEAP_SIZE,
+        )
+    };
+    check_alignments(&allocator);
+}
+
+/// Checks the alignment of allocations from the bump allocator
+#[test_case]
+fn bump_alignment() {
+    let allocator =
+        unsafe { test_allocator(BumpAllocator::new(), BumpAllocator::init, TEST_HEAP_SIZE) };
+    check_alignments(&allocator);
+}
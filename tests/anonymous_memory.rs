@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    hlt_loop,
+    memory::{self, MapError, ANONYMOUS_SIZE, ANONYMOUS_START},
+    KernelResources,
+};
+use bootloader::{entry_point, BootInfo};
+use spin::Mutex;
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+// The page table and frame allocator, for the tests to map memory with
+static RESOURCES: Mutex<Option<KernelResources>> = Mutex::new(None);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let resources = blog_os::try_init(boot_info).expect("Kernel initialization failed");
+    *RESOURCES.lock() = Some(resources);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether anonymous memory is rounded up to whole pages, zeroed, writable, and gives its
+/// frames back when it's unmapped
+#[test_case]
+fn map_and_unmap_anonymous() {
+    let mut guard = RESOURCES.lock();
+    let KernelResources {
+        mapper,
+        frame_allocator,
+    } = guard.as_mut().expect("Kernel not initialized");
+    let flags = PageTableFlags::WRITABLE;
+
+    // Map once first, so the page tables of the range exist and aren't counted below
+    let start = memory::map_anonymous(1, flags, mapper, frame_allocator).expect("Mapping failed");
+    memory::unmap_anonymous(start, mapper, frame_allocator).expect("Unmapping failed");
+
+    let free = frame_allocator.free_frame_count();
+    let start = memory::map_anonymous(3 * 4096 + 1, flags, mapper, frame_allocator)
+        .expect("Mapping failed");
+    assert_eq!(start.as_u64(), ANONYMOUS_START);
+    assert_eq!(frame_allocator.free_frame_count(), free - 4);
+
+    let bytes = unsafe { core::slice::from_raw_parts_mut(start.as_mut_ptr::<u8>(), 4 * 4096) };
+    assert!(bytes.iter().all(|&byte| byte == 0));
+    bytes.fill(0xab);
+
+    // A second range doesn't overlap the first
+    let second =
+        memory::map_anonymous(4096, flags, mapper, frame_allocator).expect("Mapping failed");
+    assert_eq!(second.as_u64(), ANONYMOUS_START + 4 * 4096);
+
+    memory::unmap_anonymous(start, mapper, frame_allocator).expect("Unmapping failed");
+    memory::unmap_anonymous(second, mapper, frame_allocator).expect("Unmapping failed");
+    assert_eq!(frame_allocator.free_frame_count(), free);
+    let offset = memory::physical_memory_offset().expect("Memory not initialized");
+    assert_eq!(unsafe { memory::translate_addr(start, offset) }, None);
+}
+
+/// Checks whether invalid requests are rejected
+#[test_case]
+fn invalid_anonymous_requests() {
+    let mut guard = RESOURCES.lock();
+    let KernelResources {
+        mapper,
+        frame_allocator,
+    } = guard.as_mut().expect("Kernel not initialized");
+
+    assert!(matches!(
+        memory::map_anonymous(0, PageTableFlags::WRITABLE, mapper, frame_allocator),
+        Err(MapError::ZeroSize)
+    ));
+    for size in [ANONYMOUS_SIZE as usize + 1, usize::MAX] {
+        assert!(matches!(
+            memory::map_anonymous(size, PageTableFlags::WRITABLE, mapper, frame_allocator),
+            Err(MapError::NoVirtualSpace)
+        ));
+    }
+    assert!(matches!(
+        memory::unmap_anonymous(
+            VirtAddr::new(ANONYMOUS_START + 4096),
+            mapper,
+            frame_allocator
+        ),
+        Err(MapError::NotMapped)
+    ));
+}
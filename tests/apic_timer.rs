@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    apic::{self, TimerSource},
+    hlt_loop, interrupts,
+};
+use bootloader::{entry_point, BootInfo};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let mut resources = blog_os::init(boot_info);
+    let source = apic::init(&mut resources.mapper, &mut resources.frame_allocator)
+        .expect("APIC initialization failed");
+
+    // Every CPU QEMU emulates by default has a local APIC
+    assert_eq!(source, TimerSource::Apic);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether the local APIC timer generates timer interrupts
+#[test_case]
+fn apic_timer_ticks() {
+    assert!(apic::is_enabled());
+
+    // Wait for a few timer interrupts, halting until the next interrupt each time
+    let start = interrupts::ticks();
+    for _ in 0..1000 {
+        if interrupts::ticks() >= start + 3 {
+            return;
+        }
+        x86_64::instructions::hlt();
+    }
+    panic!("The APIC timer didn't advance the ticks");
+}
@@ -0,0 +1,177 @@
+//! Microbenchmarks, reported over serial as `name=value` lines for a CI script to parse.
+//! Every benchmark is repeated a few times and the fastest run is reported, to reduce the noise
+//! of running under QEMU. Timings are only reported, never asserted, so this can't flake.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use core::{hint::black_box, panic::PanicInfo};
+
+use alloc::{boxed::Box, string::String};
+use blog_os::{
+    exit_qemu, hlt_loop, serial_println,
+    task::{executor::Executor, simple_executor::SimpleExecutor, yield_now, Task},
+    time::{calibrate_tsc, rdtsc, tsc_to_ns},
+    vga_buffer::WRITER,
+    QemuExitCode,
+};
+use bootloader::{entry_point, BootInfo};
+use x86_64::instructions::interrupts;
+
+/// The number of times every benchmark is run
+const RUNS: usize = 5;
+
+/// The number of operations per run, the reported values are per operation
+const ALLOCATIONS: u64 = 1_000;
+const CLEARS: u64 = 100;
+const SCROLLS: u64 = 1_000;
+const WRITES: u64 = 10;
+
+/// The number of bytes printed by every write
+const WRITE_BYTES: usize = 10 * 1024;
+const YIELDS: u64 = 1_000;
+
+/// The benchmarks, each returning the number of TSC ticks of a single operation
+const BENCHMARKS: &[(&str, fn() -> u64)] = &[
+    ("alloc_box", alloc_box),
+    ("vga_clear", vga_clear),
+    ("vga_scroll", vga_scroll),
+    ("vga_write_byte", vga_write_byte),
+    ("vga_write_string", vga_write_string),
+    ("task_yield", task_yield),
+    ("simple_task_yield", simple_task_yield),
+];
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::try_init(boot_info).expect("Kernel initialization failed");
+    serial_println!("tsc_frequency_hz={}", calibrate_tsc());
+
+    for (name, benchmark) in BENCHMARKS {
+        let ticks = (0..RUNS).map(|_| benchmark()).min().unwrap_or(0);
+        serial_println!("{}_ticks={}", name, ticks);
+        if let Some(nanoseconds) = tsc_to_ns(ticks) {
+            serial_println!("{}_ns={}", name, nanoseconds);
+        }
+    }
+
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+/// Allocates and frees a small box
+fn alloc_box() -> u64 {
+    let start = rdtsc();
+    for i in 0..ALLOCATIONS {
+        black_box(Box::new(i));
+    }
+    (rdtsc() - start) / ALLOCATIONS
+}
+
+/// Clears the whole screen
+fn vga_clear() -> u64 {
+    // Disable interrupts, so the timer can't print while measuring
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let start = rdtsc();
+        for _ in 0..CLEARS {
+            writer.clear_screen();
+        }
+        (rdtsc() - start) / CLEARS
+    })
+}
+
+/// Scrolls the screen a line, by starting a new line on the last row
+fn vga_scroll() -> u64 {
+    // Disable interrupts, so the timer can't print while measuring
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        // Move the cursor to the last row first, so every new line scrolls
+        for _ in 0..writer.height() {
+            writer.write_byte(b'\n');
+        }
+        let start = rdtsc();
+        for _ in 0..SCROLLS {
+            writer.write_byte(b'\n');
+        }
+        (rdtsc() - start) / SCROLLS
+    })
+}
+
+/// Returns the text printed by the write benchmarks, lines of text filling `WRITE_BYTES`
+fn write_text() -> String {
+    "benchmark line with some text, printed to the screen\n"
+        .chars()
+        .cycle()
+        .take(WRITE_BYTES)
+        .collect()
+}
+
+/// Prints a 10 KB string byte by byte
+fn vga_write_byte() -> u64 {
+    let text = write_text();
+    // Disable interrupts, so the timer can't print while measuring
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let start = rdtsc();
+        for _ in 0..WRITES {
+            for byte in text.bytes() {
+                writer.write_byte(byte);
+            }
+        }
+        (rdtsc() - start) / WRITES
+    })
+}
+
+/// Prints a 10 KB string at once, which writes runs of characters between new lines at once
+fn vga_write_string() -> u64 {
+    let text = write_text();
+    // Disable interrupts, so the timer can't print while measuring
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.write();
+        let start = rdtsc();
+        for _ in 0..WRITES {
+            writer.write_string(&text);
+        }
+        (rdtsc() - start) / WRITES
+    })
+}
+
+/// Yields half of the yields of a task switch benchmark, so two of them ping-pong `YIELDS` times
+async fn yield_many() {
+    for _ in 0..YIELDS / 2 {
+        yield_now().await;
+    }
+}
+
+/// Switches between two tasks which yield to each other
+fn task_yield() -> u64 {
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(yield_many()));
+    executor.spawn(Task::new(yield_many()));
+
+    // Yielding tasks wake themselves, so both run to completion
+    let start = rdtsc();
+    executor.run_until_idle();
+    (rdtsc() - start) / YIELDS
+}
+
+/// Switches between two tasks which yield to each other on the `SimpleExecutor`, as a baseline
+/// for the ready queue and waker overhead of the full executor
+fn simple_task_yield() -> u64 {
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(yield_many()));
+    executor.spawn(Task::new(yield_many()));
+
+    let start = rdtsc();
+    executor.run();
+    (rdtsc() - start) / YIELDS
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
@@ -0,0 +1,202 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::{
+    arch::asm,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
+
+use blog_os::{exit_qemu, hlt_loop, serial_print, serial_println, QemuExitCode};
+use lazy_static::lazy_static;
+use x86_64::{
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+/// The exceptions triggered by this test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Exception {
+    None,
+    Breakpoint,
+    InvalidOpcode,
+    DivideError,
+    PageFault,
+}
+
+impl Exception {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Exception::Breakpoint,
+            2 => Exception::InvalidOpcode,
+            3 => Exception::DivideError,
+            4 => Exception::PageFault,
+            _ => Exception::None,
+        }
+    }
+}
+
+// The last exception whose handler ran
+static FIRED: AtomicU8 = AtomicU8::new(Exception::None as u8);
+
+// The address to continue at after a fault, as faults return to the faulting instruction
+static RESUME_ADDRESS: AtomicU64 = AtomicU64::new(0);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+// Create a separate IDT for this test, to record which handler fired
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.page_fault.set_handler_fn(page_fault_handler);
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(double_fault_handler)
+                .set_stack_index(blog_os::gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+/// Records the exception, and skips the faulting instruction if a resume address is set
+fn record(exception: Exception, stack_frame: &mut InterruptStackFrame) {
+    FIRED.store(exception as u8, Ordering::SeqCst);
+    let resume_address = RESUME_ADDRESS.swap(0, Ordering::SeqCst);
+    if resume_address != 0 {
+        // Safe, as the resume address points directly after the faulting instruction
+        unsafe {
+            stack_frame
+                .as_mut()
+                .update(|frame| frame.instruction_pointer = VirtAddr::new(resume_address));
+        }
+    }
+}
+
+extern "x86-interrupt" fn breakpoint_handler(mut stack_frame: InterruptStackFrame) {
+    record(Exception::Breakpoint, &mut stack_frame);
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(mut stack_frame: InterruptStackFrame) {
+    record(Exception::InvalidOpcode, &mut stack_frame);
+}
+
+extern "x86-interrupt" fn divide_error_handler(mut stack_frame: InterruptStackFrame) {
+    record(Exception::DivideError, &mut stack_frame);
+}
+
+extern "x86-interrupt" fn page_fault_handler(
+    mut stack_frame: InterruptStackFrame,
+    _error_code: PageFaultErrorCode,
+) {
+    record(Exception::PageFault, &mut stack_frame);
+}
+
+extern "x86-interrupt" fn double_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: u64,
+) -> ! {
+    serial_println!("[failed]");
+    serial_println!("Exception cascaded into a double fault");
+    exit_qemu(QemuExitCode::Failed);
+    hlt_loop();
+}
+
+fn trigger_breakpoint() {
+    // A breakpoint is a trap, so execution continues after it without a resume address
+    x86_64::instructions::interrupts::int3();
+}
+
+fn trigger_invalid_opcode() {
+    unsafe {
+        asm!(
+            "lea {resume}, [rip + 2f]",
+            "mov [{slot}], {resume}",
+            "ud2",
+            "2:",
+            resume = out(reg) _,
+            slot = in(reg) RESUME_ADDRESS.as_ptr(),
+        );
+    }
+}
+
+fn trigger_divide_error() {
+    unsafe {
+        asm!(
+            "lea {resume}, [rip + 2f]",
+            "mov [{slot}], {resume}",
+            "xor edx, edx",
+            "mov eax, 1",
+            "xor ecx, ecx",
+            "div ecx",
+            "2:",
+            resume = out(reg) _,
+            slot = in(reg) RESUME_ADDRESS.as_ptr(),
+            out("rax") _,
+            out("rcx") _,
+            out("rdx") _,
+        );
+    }
+}
+
+fn trigger_page_fault() {
+    unsafe {
+        asm!(
+            "lea {resume}, [rip + 2f]",
+            "mov [{slot}], {resume}",
+            "mov byte ptr [{address}], 0",
+            "2:",
+            resume = out(reg) _,
+            slot = in(reg) RESUME_ADDRESS.as_ptr(),
+            // A known unmapped address
+            address = in(reg) 0xdead_beef_000u64,
+        );
+    }
+}
+
+/// Runs a single case, exiting with a failure if the expected handler didn't run
+fn run_case(name: &str, trigger: fn(), expected: Exception) {
+    serial_print!("exceptions::{}...\t", name);
+
+    FIRED.store(Exception::None as u8, Ordering::SeqCst);
+    trigger();
+
+    let fired = Exception::from_u8(FIRED.load(Ordering::SeqCst));
+    if fired == expected {
+        serial_println!("[ok]");
+    } else {
+        serial_println!("[failed]");
+        serial_println!("Expected {:?}, but {:?} fired", expected, fired);
+        exit_qemu(QemuExitCode::Failed);
+        hlt_loop();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    blog_os::gdt::init();
+    init_test_idt();
+
+    run_case("breakpoint", trigger_breakpoint, Exception::Breakpoint);
+    run_case(
+        "invalid_opcode",
+        trigger_invalid_opcode,
+        Exception::InvalidOpcode,
+    );
+    run_case("divide_error", trigger_divide_error, Exception::DivideError);
+    run_case("page_fault", trigger_page_fault, Exception::PageFault);
+
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
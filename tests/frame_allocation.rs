@@ -0,0 +1,259 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use alloc::{boxed::Box, vec::Vec};
+use blog_os::{
+    allocator::{self, HEAP_GUARD_SIZE, HEAP_START},
+    hlt_loop,
+    memory::{self, BitmapFrameAllocator, BootInfoFrameAllocator},
+    KernelResources,
+};
+use bootloader::{
+    bootinfo::{FrameRange, MemoryMap, MemoryRegion, MemoryRegionType},
+    entry_point, BootInfo,
+};
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    VirtAddr,
+};
+
+// The page table and frame allocator, for the tests to take frames from
+static RESOURCES: Mutex<Option<KernelResources>> = Mutex::new(None);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let resources = blog_os::try_init(boot_info).expect("Kernel initialization failed");
+    *RESOURCES.lock() = Some(resources);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether flushing the TLB after remapping a page makes accesses use the new frame
+#[test_case]
+fn flush_tlb_after_remap() {
+    let mut guard = RESOURCES.lock();
+    let KernelResources {
+        mapper,
+        frame_allocator,
+    } = guard.as_mut().expect("Kernel not initialized");
+
+    let offset = memory::physical_memory_offset().expect("Memory not initialized");
+    let frames = [(); 2].map(|_| frame_allocator.allocate_frame().expect("Out of frames"));
+    for (marker, frame) in (1..).zip(frames) {
+        unsafe { *(offset + frame.start_address().as_u64()).as_mut_ptr::<u64>() = marker };
+    }
+
+    // A page past the heap guard page
+    let address = HEAP_START + allocator::heap_size() + 2 * HEAP_GUARD_SIZE;
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(address as u64));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    let value = page.start_address().as_ptr::<u64>();
+    unsafe {
+        mapper
+            .map_to(page, frames[0], flags, frame_allocator)
+            .expect("Mapping failed")
+            .flush();
+        assert_eq!(value.read_volatile(), 1);
+
+        // Remap the page without flushing, like a manual page table edit
+        mapper.unmap(page).expect("Unmapping failed").1.ignore();
+        mapper
+            .map_to(page, frames[1], flags, frame_allocator)
+            .expect("Mapping failed")
+            .ignore();
+        memory::flush_tlb(page.start_address());
+        assert_eq!(value.read_volatile(), 2);
+        assert_eq!(
+            memory::translate_addr(page.start_address(), offset),
+            Some(frames[1].start_address())
+        );
+
+        mapper.unmap(page).expect("Unmapping failed").1.ignore();
+        memory::flush_tlb_all();
+        assert_eq!(memory::translate_addr(page.start_address(), offset), None);
+
+        for frame in frames {
+            frame_allocator.deallocate_frame(frame);
+        }
+    }
+}
+
+/// Checks whether a deallocated frame is reused, and comes back zeroed with zero-on-free
+#[test_case]
+fn deallocated_frame_reused() {
+    let mut guard = RESOURCES.lock();
+    let frame_allocator = &mut guard
+        .as_mut()
+        .expect("Kernel not initialized")
+        .frame_allocator;
+
+    let frame = frame_allocator.allocate_frame().expect("Out of frames");
+    let offset = memory::physical_memory_offset().expect("Memory not initialized");
+    let bytes: *mut u8 = (offset + frame.start_address().as_u64()).as_mut_ptr();
+    unsafe { core::ptr::write_bytes(bytes, 0xab, 4096) };
+
+    let before = frame_allocator.free_frame_count();
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    assert_eq!(frame_allocator.free_frame_count(), before + 1);
+    assert_eq!(frame_allocator.allocate_frame(), Some(frame));
+    assert_eq!(frame_allocator.free_frame_count(), before);
+
+    #[cfg(feature = "zero-on-free")]
+    {
+        let contents = unsafe { core::slice::from_raw_parts(bytes, 4096) };
+        assert!(contents.iter().all(|&byte| byte == 0));
+    }
+
+    unsafe { frame_allocator.deallocate_frame(frame) };
+}
+
+/// Checks whether the bitmap allocator hands out every frame once, and reuses deallocated frames,
+/// lowest first
+#[test_case]
+fn bitmap_frame_allocator() {
+    // Use 70 frames, so the bitmap needs more than 1 word
+    const FRAMES: usize = 70;
+
+    let mut guard = RESOURCES.lock();
+    let frame_allocator = &mut guard
+        .as_mut()
+        .expect("Kernel not initialized")
+        .frame_allocator;
+
+    // Take the frames from the kernel, so the bitmap allocator owns them
+    let (taken, run_start) = take_consecutive_frames(frame_allocator, FRAMES);
+    let run = &taken[run_start..];
+    let memory_map = usable_memory_map(run);
+
+    let mut bitmap_allocator = unsafe { BitmapFrameAllocator::init(&memory_map) };
+    assert_eq!(bitmap_allocator.free_frame_count(), FRAMES);
+    let frames: Vec<PhysFrame> =
+        core::iter::from_fn(|| bitmap_allocator.allocate_frame()).collect();
+    assert_eq!(frames, run);
+    assert_eq!(bitmap_allocator.free_frame_count(), 0);
+
+    // Free in a different order than allocated
+    unsafe {
+        bitmap_allocator.deallocate_frame(frames[66]);
+        bitmap_allocator.deallocate_frame(frames[3]);
+    }
+    assert_eq!(bitmap_allocator.free_frame_count(), 2);
+    assert_eq!(bitmap_allocator.allocate_frame(), Some(frames[3]));
+    assert_eq!(bitmap_allocator.allocate_frame(), Some(frames[66]));
+    assert_eq!(bitmap_allocator.allocate_frame(), None);
+
+    for frame in taken {
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+}
+
+/// Checks whether allocating frames decreases the free frame count by the same amount
+#[test_case]
+fn free_frame_count() {
+    let mut guard = RESOURCES.lock();
+    let frame_allocator = &mut guard
+        .as_mut()
+        .expect("Kernel not initialized")
+        .frame_allocator;
+
+    let before = frame_allocator.free_frame_count();
+    assert!(before <= frame_allocator.total_frame_count());
+
+    let frames = [(); 10].map(|_| frame_allocator.allocate_frame().expect("Out of frames"));
+    assert_eq!(frame_allocator.free_frame_count(), before - 10);
+
+    for frame in frames {
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+    assert_eq!(frame_allocator.free_frame_count(), before);
+}
+
+/// Takes frames from the kernel until it handed out enough consecutive frames
+///
+/// # Arguments
+/// ```frame_allocator```: the frame allocator of the kernel
+/// ```count```: the number of consecutive frames needed
+///
+/// # Returns
+/// Every taken frame, to deallocate afterwards, and the index of the first consecutive frame
+fn take_consecutive_frames(
+    frame_allocator: &mut BootInfoFrameAllocator,
+    count: usize,
+) -> (Vec<PhysFrame>, usize) {
+    let mut taken: Vec<PhysFrame> = Vec::new();
+    let mut run_start = 0;
+    while taken.len() - run_start < count {
+        let frame = frame_allocator.allocate_frame().expect("Out of frames");
+        if taken.last().is_some_and(|&last| last + 1 != frame) {
+            run_start = taken.len();
+        }
+        taken.push(frame);
+    }
+    (taken, run_start)
+}
+
+/// Creates a memory map with a single usable region
+///
+/// # Arguments
+/// ```run```: the consecutive frames making up the region
+fn usable_memory_map(run: &[PhysFrame]) -> MemoryMap {
+    let mut memory_map = MemoryMap::new();
+    memory_map.add_region(MemoryRegion {
+        range: FrameRange::new(
+            run[0].start_address().as_u64(),
+            run[run.len() - 1].start_address().as_u64() + 4096,
+        ),
+        region_type: MemoryRegionType::Usable,
+    });
+    memory_map
+}
+
+/// Checks whether the bitmap allocator keeps the frames allocated by the boot info allocator
+#[test_case]
+fn bitmap_from_boot_info() {
+    const FRAMES: usize = 8;
+
+    let mut guard = RESOURCES.lock();
+    let frame_allocator = &mut guard
+        .as_mut()
+        .expect("Kernel not initialized")
+        .frame_allocator;
+
+    // Take the frames from the kernel, so the second boot info allocator owns them. It needs
+    // the memory map for as long as it exists, so it's leaked.
+    let (taken, run_start) = take_consecutive_frames(frame_allocator, FRAMES);
+    let run = &taken[run_start..];
+    let memory_map: &'static MemoryMap = Box::leak(Box::new(usable_memory_map(run)));
+
+    let mut boot_info_allocator = unsafe { BootInfoFrameAllocator::init(memory_map) };
+    assert_eq!(boot_info_allocator.free_frame_count(), FRAMES);
+    for &frame in &run[..3] {
+        assert_eq!(boot_info_allocator.allocate_frame(), Some(frame));
+    }
+
+    let mut bitmap_allocator = BitmapFrameAllocator::from_boot_info(boot_info_allocator);
+    assert_eq!(bitmap_allocator.free_frame_count(), FRAMES - 3);
+    assert_eq!(bitmap_allocator.allocate_frame(), Some(run[3]));
+
+    for frame in taken {
+        unsafe { frame_allocator.deallocate_frame(frame) };
+    }
+}
@@ -4,16 +4,14 @@
 #![test_runner(blog_os::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
-use core::{hint::black_box, panic::PanicInfo};
+use core::{alloc::Layout, hint::black_box, panic::PanicInfo};
 
 use alloc::{boxed::Box, vec::Vec};
 use blog_os::{
     allocator::{self, HEAP_SIZE},
     hlt_loop,
-    memory::{self, BootInfoFrameAllocator},
 };
 use bootloader::{entry_point, BootInfo};
-use x86_64::VirtAddr;
 
 extern crate alloc;
 
@@ -25,11 +23,7 @@ fn panic(info: &PanicInfo) -> ! {
 entry_point!(main);
 
 fn main(boot_info: &'static BootInfo) -> ! {
-    blog_os::init();
-    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
-    let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
-    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+    blog_os::init(boot_info);
 
     test_main();
     hlt_loop();
@@ -77,7 +71,9 @@ fn many_boxes() {
     }
 }
 
-/// Checks whether memory is reused when any piece of memory isn't freed
+/// Checks whether memory is reused when any piece of memory isn't freed.
+/// Skipped with the bump allocator, which only reuses memory once everything is freed.
+#[cfg(not(feature = "bump-allocator"))]
 #[test_case]
 fn many_boxes_long_lived() {
     // Create a box that will only be freed at the end of the function
@@ -94,3 +90,92 @@ fn many_boxes_long_lived() {
     // Check whether the long lived box is still available
     assert_eq!(*long_lived, 1);
 }
+
+/// Checks whether boxed values of over-aligned types are aligned, also when the alignment is
+/// larger than the size of the value
+#[test_case]
+fn over_aligned_box() {
+    #[repr(align(64))]
+    struct Aligned64(u8);
+
+    #[repr(align(4096))]
+    struct Aligned4096(u8);
+
+    let small = Box::new(Aligned64(1));
+    let page = Box::new(Aligned4096(2));
+    assert_eq!(&*small as *const Aligned64 as usize % 64, 0);
+    assert_eq!(&*page as *const Aligned4096 as usize % 4096, 0);
+    assert_eq!(small.0 + page.0, 3);
+}
+
+/// Checks whether allocations through the raw allocator API are aligned, for alignments both
+/// smaller and larger than their size
+#[test_case]
+fn raw_aligned_allocations() {
+    for (size, align) in [(8, 64), (64, 64), (100, 64), (8, 4096), (4096, 4096)] {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        assert!(
+            !ptr.is_null(),
+            "Allocating {size} bytes aligned to {align} failed"
+        );
+        assert_eq!(
+            ptr as usize % align,
+            0,
+            "{size} bytes not aligned to {align}"
+        );
+        unsafe { alloc::alloc::dealloc(ptr, layout) };
+    }
+}
+
+/// Checks whether freeing allocations of varying sizes out of order leaves the heap usable for
+/// a block of half its size, so freed memory is merged again instead of staying fragmented.
+///
+/// The heap is filled completely, the last block is kept, and the rest is freed out of order, so
+/// no untouched memory is left for the large block. Only the fixed-size block allocator, the
+/// default global allocator, passes: it serves these sizes from its merging fallback allocator.
+/// The bump allocator only reuses memory once everything is freed, and the linked list allocator
+/// doesn't merge adjacent free regions, so both would fail.
+#[cfg(not(any(feature = "bump-allocator", feature = "linked-list-allocator")))]
+#[test_case]
+fn fragmentation_resistance() {
+    // Sizes larger than the largest block size, as freed blocks stay in their lists unmerged
+    const SIZES: [usize; 4] = [3000, 4096, 2500, 5000];
+
+    // Reserve the list up front, so it doesn't need to grow while the heap is full
+    let mut blocks: Vec<(*mut u8, Layout)> = Vec::with_capacity(allocator::heap_size() / 2500);
+    while blocks.len() < blocks.capacity() {
+        let layout = Layout::from_size_align(SIZES[blocks.len() % SIZES.len()], 8).unwrap();
+        let ptr = unsafe { alloc::alloc::alloc(layout) };
+        if ptr.is_null() {
+            break;
+        }
+        unsafe { ptr.write_bytes(blocks.len() as u8, layout.size()) };
+        blocks.push((ptr, layout));
+    }
+    assert!(blocks.len() > SIZES.len(), "Heap too small for the test");
+
+    // The heap is full, so the large block can only come from merged freed blocks
+    let large = Layout::from_size_align(allocator::heap_size() / 2, 8).unwrap();
+    assert!(unsafe { alloc::alloc::alloc(large) }.is_null());
+
+    // Keep the last block, then free every third block, then the rest in reverse, checking
+    // nothing was overwritten
+    let (last, rest) = blocks.split_last().expect("No blocks allocated");
+    let order = (0..rest.len())
+        .step_by(3)
+        .chain((0..rest.len()).rev().filter(|i| i % 3 != 0));
+    for i in order {
+        let (ptr, layout) = rest[i];
+        let contents = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+        assert!(contents.iter().all(|&byte| byte == i as u8));
+        unsafe { alloc::alloc::dealloc(ptr, layout) };
+    }
+
+    let ptr = unsafe { alloc::alloc::alloc(large) };
+    assert!(!ptr.is_null(), "Freed blocks weren't merged");
+    unsafe {
+        alloc::alloc::dealloc(ptr, large);
+        alloc::alloc::dealloc(last.0, last.1);
+    }
+}
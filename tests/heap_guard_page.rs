@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    allocator::{self, HEAP_START},
+    exit_qemu, hlt_loop,
+    memory::{self, BootInfoFrameAllocator},
+    serial_print, serial_println, QemuExitCode,
+};
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+// Create a separate IDT for this test, to make page faults exit with a success code
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    _error_code: PageFaultErrorCode,
+) {
+    // The fault should be caused by the first byte after the heap
+    if Cr2::read() == VirtAddr::new((HEAP_START + allocator::heap_size()) as u64) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!("Unexpected page fault at {:?}", Cr2::read());
+        exit_qemu(QemuExitCode::Failed);
+    }
+    hlt_loop();
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("heap_guard_page::write_past_heap...\t");
+
+    blog_os::gdt::init();
+    init_test_idt();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("Heap initialization failed");
+
+    // Write to the guard page directly after the heap
+    let past_heap = (HEAP_START + allocator::heap_size()) as *mut u8;
+    unsafe { past_heap.write_volatile(42) };
+
+    panic!("Execution continued after writing past the heap");
+}
@@ -0,0 +1,41 @@
+//! Checks whether a task woken by the timer interrupt runs, while the executor halts the CPU
+//! between interrupts. If the wakeup were lost between checking the task queue and halting, the
+//! executor would halt for ever with a ready task, and the test would time out.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    exit_qemu, serial_print, serial_println,
+    task::{executor::Executor, Task},
+    time::{sleep, Duration},
+    QemuExitCode,
+};
+use bootloader::{entry_point, BootInfo};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::try_init(boot_info).expect("Kernel initialization failed");
+
+    serial_print!("idle_wakeup::timer_wakes_sleeping_task...\t");
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(async {
+        // Sleep several times, so some wakeups arrive while the executor is halted
+        for _ in 0..3 {
+            sleep(Duration::from_ticks(2)).await;
+        }
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }));
+    executor.run();
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{fmt, panic::PanicInfo};
+
+use blog_os::hlt_loop;
+use bootloader::{entry_point, BootInfo};
+use spin::Mutex;
+
+/// The number of bytes kept of the progress markers
+const TRACE_CAPACITY: usize = 128;
+
+/// Keeps the text written to it in a fixed buffer, as the heap doesn't exist yet while tracing
+struct TraceBuffer {
+    bytes: [u8; TRACE_CAPACITY],
+    len: usize,
+}
+
+impl TraceBuffer {
+    /// Returns the text written so far
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).expect("Trace isn't UTF-8")
+    }
+}
+
+impl fmt::Write for TraceBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+// The progress markers written by init
+static TRACE: Mutex<TraceBuffer> = Mutex::new(TraceBuffer {
+    bytes: [0; TRACE_CAPACITY],
+    len: 0,
+});
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::try_init_traced(boot_info, &mut *TRACE.lock()).expect("Kernel initialization failed");
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether init wrote every progress marker, in order
+#[test_case]
+fn init_trace() {
+    assert_eq!(
+        TRACE.lock().as_str(),
+        "idt ok\ngdt ok\npics ok\ninterrupts enabled\n"
+    );
+}
@@ -0,0 +1,267 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::{
+    future::Future,
+    panic::PanicInfo,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use alloc::string::String;
+use blog_os::{
+    hlt_loop, println, shell,
+    task::{
+        keyboard::{inject_scancode, print_keypresses, read_line},
+        line_reader::{self, Line},
+    },
+    vga_buffer::{self, WRITER},
+};
+use bootloader::{entry_point, BootInfo};
+use futures_util::task::noop_waker;
+use x86_64::instructions::interrupts;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::init(boot_info);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether the scancodes of "hi" and the extended scancodes of the up arrow are decoded
+/// and printed by the keyboard task
+#[test_case]
+fn decode_injected_scancodes() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+    let mut task = pin!(print_keypresses());
+
+    // The first poll waits for scancodes
+    assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+    // Run without interrupts, so the timer can't print between the characters
+    let bottom_row = interrupts::without_interrupts(|| {
+        // Start on an empty line, then press and release h, i, and the up arrow
+        println!();
+        for scancode in [0x23, 0xa3, 0x17, 0x97, 0xe0, 0x48, 0xe0, 0xc8] {
+            inject_scancode(scancode);
+        }
+        for _ in 0..3 {
+            assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+        }
+
+        let row = WRITER.read().cursor_row();
+        vga_buffer::snapshot()[row]
+    });
+    assert_eq!(bottom_row[0].0, b'h');
+    assert_eq!(bottom_row[1].0, b'i');
+
+    // Special keys are printed by name
+    assert_eq!(bottom_row[2].0, b'U');
+    assert_eq!(bottom_row[3].0, b'p');
+}
+
+/// Checks whether read_line returns the characters typed before Enter, applying backspace and
+/// ignoring characters that don't fit
+#[test_case]
+fn read_injected_line() {
+    // Press and release h, i, and Enter
+    for scancode in [0x23, 0xa3, 0x17, 0x97, 0x1c, 0x9c] {
+        inject_scancode(scancode);
+    }
+    let mut buf = [0; 16];
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"hi");
+
+    // Press and release h, x, Backspace, i, and Enter
+    for scancode in [0x23, 0xa3, 0x2d, 0xad, 0x0e, 0x8e, 0x17, 0x97, 0x1c, 0x9c] {
+        inject_scancode(scancode);
+    }
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"hi");
+
+    // Only h fits, so i is ignored
+    for scancode in [0x23, 0xa3, 0x17, 0x97, 0x1c, 0x9c] {
+        inject_scancode(scancode);
+    }
+    let mut buf = [0; 1];
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"h");
+}
+
+/// Checks whether Caps Lock stays on from one line to the next, as every reader shares the
+/// decoder
+#[test_case]
+fn lock_keys_carry_over_lines() {
+    // Caps Lock, h, and Enter
+    for scancode in [0x3a, 0xba, 0x23, 0xa3, 0x1c, 0x9c] {
+        inject_scancode(scancode);
+    }
+    let mut buf = [0; 16];
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"H");
+
+    // h and Enter, then Caps Lock again to turn it off, and i and Enter
+    for scancode in [0x23, 0xa3, 0x1c, 0x9c, 0x3a, 0xba, 0x17, 0x97, 0x1c, 0x9c] {
+        inject_scancode(scancode);
+    }
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"H");
+    let len = read_line(&mut buf);
+    assert_eq!(&buf[..len], b"i");
+}
+
+/// Checks whether the line reader returns the typed line once Enter is pressed, with backspace
+/// applied, and echoes it to the screen
+#[test_case]
+fn read_injected_line_async() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    // Run without interrupts, so the timer can't print between the characters
+    let (line, row) = interrupts::without_interrupts(|| {
+        println!();
+        let mut task = pin!(line_reader::read_line());
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        // Press and release h, x, Backspace, i, and Enter
+        for scancode in [0x23, 0xa3, 0x2d, 0xad, 0x0e, 0x8e, 0x17, 0x97, 0x1c, 0x9c] {
+            inject_scancode(scancode);
+        }
+        let Poll::Ready(line) = task.as_mut().poll(&mut context) else {
+            panic!("Line not read after Enter");
+        };
+
+        // The echoed line is on the row above the cursor, after the new line of Enter
+        let row = WRITER.read().cursor_row() - 1;
+        (line, vga_buffer::snapshot()[row])
+    });
+    assert_eq!(line, Line::Entered(String::from("hi")));
+    assert_eq!(row[0].0, b'h');
+    assert_eq!(row[1].0, b'i');
+    assert_eq!(row[2].0, b' ');
+}
+
+/// Checks whether the shell runs a typed echo command, printing its text
+#[test_case]
+fn shell_echo() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    // Run without interrupts, so the timer can't print between the characters
+    let row = interrupts::without_interrupts(|| {
+        println!();
+        let mut task = pin!(shell::shell(0));
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        // Press "echo hello" and Enter, releasing is optional
+        for scancode in [
+            0x12, 0x2e, 0x23, 0x18, 0x39, 0x23, 0x12, 0x26, 0x26, 0x18, 0x1c,
+        ] {
+            inject_scancode(scancode);
+        }
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        // The output is on the row above the prompt for the next command
+        let row = WRITER.read().cursor_row() - 1;
+        vga_buffer::snapshot()[row]
+    });
+    for (cell, &expected) in row.iter().zip(b"hello ") {
+        assert_eq!(cell.0, expected);
+    }
+}
+
+/// Types the scancodes into a new line reader, and returns how the line ended
+///
+/// # Arguments
+/// ```scancodes```: the scancodes to type, which must end the line
+fn read_typed_line(scancodes: &[u8]) -> Line {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    // Run without interrupts, so the timer can't print in between
+    interrupts::without_interrupts(|| {
+        println!();
+        let mut task = pin!(line_reader::read_line());
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        for &scancode in scancodes {
+            inject_scancode(scancode);
+        }
+        match task.as_mut().poll(&mut context) {
+            Poll::Ready(line) => line,
+            Poll::Pending => panic!("Line not ended by {:x?}", scancodes),
+        }
+    })
+}
+
+/// Checks whether Ctrl-C cancels the line, Ctrl-D only ends the input on an empty line, and
+/// Ctrl-Q asks to power off, also in the middle of a line
+#[test_case]
+fn read_line_control_keys() {
+    // Left Ctrl down and up, around each letter
+    const CTRL: u8 = 0x1d;
+    const CTRL_UP: u8 = 0x9d;
+
+    // h, then Ctrl-C
+    assert_eq!(
+        read_typed_line(&[0x23, 0xa3, CTRL, 0x2e, 0xae, CTRL_UP]),
+        Line::Cancelled
+    );
+
+    // Ctrl-D on an empty line
+    assert_eq!(
+        read_typed_line(&[CTRL, 0x20, 0xa0, CTRL_UP]),
+        Line::EndOfInput
+    );
+
+    // h, Ctrl-D, which is ignored after text, i, and Enter
+    assert_eq!(
+        read_typed_line(&[0x23, 0xa3, CTRL, 0x20, 0xa0, CTRL_UP, 0x17, 0x97, 0x1c, 0x9c]),
+        Line::Entered(String::from("hi"))
+    );
+
+    // h, then Ctrl-Q
+    assert_eq!(
+        read_typed_line(&[0x23, 0xa3, CTRL, 0x10, 0x90, CTRL_UP]),
+        Line::PowerOff
+    );
+}
+
+/// Checks whether the shell ends asking to power off when Ctrl-Q is pressed, which the kernel
+/// binary handles by shutting down
+#[test_case]
+fn shell_ctrl_q() {
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    let result = interrupts::without_interrupts(|| {
+        println!();
+        let mut task = pin!(shell::shell(0));
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        // Ctrl-C only cancels the line, so the shell keeps running
+        for scancode in [0x1d, 0x2e, 0xae, 0x9d] {
+            inject_scancode(scancode);
+        }
+        assert_eq!(task.as_mut().poll(&mut context), Poll::Pending);
+
+        for scancode in [0x1d, 0x10, 0x90, 0x9d] {
+            inject_scancode(scancode);
+        }
+        task.as_mut().poll(&mut context)
+    });
+    assert_eq!(result, Poll::Ready(Line::PowerOff));
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use alloc::{boxed::Box, vec::Vec};
+use blog_os::{allocator, hlt_loop};
+use bootloader::{entry_point, BootInfo};
+
+extern crate alloc;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::try_init(boot_info).expect("Kernel initialization failed");
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether the heap is empty before anything is allocated
+#[test_case]
+fn empty_at_start() {
+    allocator::assert_heap_empty();
+}
+
+/// Checks whether a dropped vec leaves nothing allocated, also after it grew
+#[test_case]
+fn dropped_vec() {
+    let mut vec = Vec::new();
+    for i in 0..100u64 {
+        vec.push(i);
+    }
+    assert!(allocator::heap_bytes_in_use() >= 100 * 8);
+
+    drop(vec);
+    allocator::assert_heap_empty();
+}
+
+/// Checks whether the bytes of a box are counted while it's alive
+#[test_case]
+fn dropped_box() {
+    let value = Box::new([0u8; 100]);
+    assert_eq!(allocator::heap_bytes_in_use(), 100);
+
+    drop(value);
+    allocator::assert_heap_empty();
+}
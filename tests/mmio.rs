@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    hlt_loop,
+    memory::{self, MMIO_START},
+    KernelResources,
+};
+use bootloader::{entry_point, BootInfo};
+use spin::Mutex;
+use x86_64::{structures::paging::PageTableFlags, PhysAddr};
+
+// The page table and frame allocator, for the tests to map memory with
+static RESOURCES: Mutex<Option<KernelResources>> = Mutex::new(None);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    let resources = blog_os::try_init(boot_info).expect("Kernel initialization failed");
+    *RESOURCES.lock() = Some(resources);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Checks whether an unaligned range spanning two pages of the VGA buffer is mapped uncached,
+/// and reads the same memory as the physical memory mapping
+#[test_case]
+fn map_vga_buffer_as_mmio() {
+    let mut guard = RESOURCES.lock();
+    let KernelResources {
+        mapper,
+        frame_allocator,
+    } = guard.as_mut().expect("Kernel not initialized");
+
+    let phys = PhysAddr::new(0xb8ff0);
+    let address =
+        unsafe { memory::map_mmio(phys, 0x20, mapper, frame_allocator) }.expect("Mapping failed");
+    assert!(address.as_u64() >= MMIO_START);
+    assert_eq!(address.as_u64() % 4096, 0xff0);
+
+    let offset = memory::physical_memory_offset().expect("Memory not initialized");
+    for byte in [0u64, 0x1f] {
+        let translated = unsafe { memory::translate_addr(address + byte, offset) };
+        assert_eq!(translated, Some(phys + byte));
+    }
+
+    let flags = memory::describe_mapping(address + 0x10u64, mapper).expect("Not mapped");
+    assert!(flags.contains(
+        PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::NO_CACHE
+            | PageTableFlags::WRITE_THROUGH
+    ));
+
+    let via_mmio = unsafe { address.as_ptr::<u64>().read_volatile() };
+    let via_offset = unsafe { (offset + phys.as_u64()).as_ptr::<u64>().read_volatile() };
+    assert_eq!(via_mmio, via_offset);
+}
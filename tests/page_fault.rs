@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+
+use blog_os::{
+    exit_qemu, hlt_loop,
+    interrupts::{in_page_fault, report_page_fault},
+    serial_print, serial_println, QemuExitCode,
+};
+use lazy_static::lazy_static;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+// Create a separate IDT for this test, to check the state after reporting a page fault
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+        idt
+    };
+}
+
+pub fn init_test_idt() {
+    TEST_IDT.load();
+}
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    report_page_fault(&stack_frame, error_code);
+
+    // The report should have completed and cleared the recursion guard
+    if in_page_fault() {
+        serial_println!("[failed]");
+        serial_println!("Page fault guard not cleared after reporting");
+        exit_qemu(QemuExitCode::Failed);
+    } else {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+    hlt_loop();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("page_fault::report_page_fault...\t");
+
+    blog_os::gdt::init();
+    init_test_idt();
+
+    // Trigger a page fault by writing to an unmapped address
+    unsafe { (0xdead_beef_000 as *mut u8).write_volatile(42) };
+
+    panic!("Execution continued after page fault");
+}
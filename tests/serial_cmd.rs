@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(blog_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::{
+    future::Future,
+    panic::PanicInfo,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use alloc::string::String;
+use blog_os::{hlt_loop, serial_cmd::run_commands};
+use bootloader::{entry_point, BootInfo};
+use futures_util::{stream, task::noop_waker};
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    blog_os::test_panic_handler(info)
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    blog_os::init(boot_info);
+
+    test_main();
+    hlt_loop();
+}
+
+/// Runs the commands in the input, returning the replies
+///
+/// # Arguments
+/// ```input```: the bytes the host would send
+fn drive(input: &'static [u8]) -> String {
+    let mut output = String::new();
+    let waker = noop_waker();
+    let mut context = Context::from_waker(&waker);
+
+    // The input is available immediately, so a single poll runs every command
+    let future = pin!(run_commands(
+        stream::iter(input.iter().copied()),
+        &mut output
+    ));
+    assert_eq!(future.poll(&mut context), Poll::Ready(()));
+    output
+}
+
+/// Checks whether PING is answered with PONG
+#[test_case]
+fn ping() {
+    assert_eq!(drive(b"PING\n"), "PONG\n");
+}
+
+/// Checks whether a command split over partial lines, and an unknown command, are answered
+#[test_case]
+fn partial_and_unknown_commands() {
+    assert_eq!(
+        drive(b"PI\rNG\r\nHELLO\nMEM"),
+        "PONG\nERR unknown command\n"
+    );
+}
@@ -1,25 +1,95 @@
 #![no_std]
 #![no_main]
 
-use blog_os::{exit_qemu, hlt_loop, serial_println, QemuExitCode};
-use core::panic::PanicInfo;
-
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    should_fail();
-    serial_println!("[test did not panic]");
-    exit_qemu(QemuExitCode::Failed);
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::boxed::Box;
+use blog_os::{allocator, exit_qemu, hlt_loop, serial_print, serial_println, QemuExitCode};
+use bootloader::{entry_point, BootInfo};
+
+extern crate alloc;
+
+/// The tests, which should all panic
+const TESTS: &[(&str, fn())] = &[
+    ("should_fail", should_fail),
+    ("leaked_allocation", leaked_allocation),
+    ("single_row_scroll_region", single_row_scroll_region),
+    // Positions outside of the screen are only rejected in debug builds
+    #[cfg(debug_assertions)]
+    ("vga_row_out_of_range", vga_row_out_of_range),
+];
+
+// The index of the next test to run
+static NEXT_TEST: AtomicUsize = AtomicUsize::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    // The heap is needed to check for leaks
+    blog_os::try_init(boot_info).expect("Kernel initialization failed");
+
+    run_next_test();
+}
+
+/// Runs the next test, exits QEMU with success once every test has panicked
+///
+/// # Returns
+/// Never, as the test should panic, which runs the test after it
+fn run_next_test() -> ! {
+    match TESTS.get(NEXT_TEST.fetch_add(1, Ordering::SeqCst)) {
+        Some((name, test)) => {
+            serial_print!("should_panic::{}...\t", name);
+            test();
+            serial_println!("[test did not panic]");
+            exit_qemu(QemuExitCode::Failed);
+        }
+        None => exit_qemu(QemuExitCode::Success),
+    }
     hlt_loop();
 }
 
 fn should_fail() {
-    serial_println!("should_panic::should_fail...\t");
     assert_eq!(0, 1);
 }
 
+/// Leaks an allocation on purpose, which assert_heap_empty should catch
+fn leaked_allocation() {
+    core::mem::forget(Box::new(42u64));
+    allocator::assert_heap_empty();
+}
+
+/// Sets a scroll region of a single row, which can't scroll
+fn single_row_scroll_region() {
+    use blog_os::vga_buffer::WRITER;
+
+    // Without interrupts, as the timer interrupt would wait for the writer forever
+    x86_64::instructions::interrupts::disable();
+    WRITER.write().set_scroll_region(3, 3);
+}
+
+/// Clears a row below the screen, which the VGA writer should reject
+#[cfg(debug_assertions)]
+fn vga_row_out_of_range() {
+    use blog_os::vga_buffer::WRITER;
+
+    // Without interrupts, as the timer interrupt would wait for the writer forever
+    x86_64::instructions::interrupts::disable();
+    let mut writer = WRITER.write();
+    let height = writer.height();
+    writer.release_row(height);
+}
+
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     serial_println!("[ok]");
-    exit_qemu(QemuExitCode::Success);
-    hlt_loop();
+
+    // A test may have panicked while holding the VGA writer.
+    // Safe, as the test which held it stopped running.
+    unsafe { blog_os::vga_buffer::force_unlock() };
+
+    // Execution can't continue in the test which panicked, so continue with the next test
+    run_next_test();
 }